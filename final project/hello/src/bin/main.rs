@@ -1,59 +1,120 @@
-use std::process;
+use std::env;
 use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::process;
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
-use std::net::{TcpListener, TcpStream};
-use std::io::prelude::*;
 use hello::ThreadPool;
+use hello::cgi::Cgi;
+use hello::http::{Request, Response};
+use hello::middleware::{Logger, RequestId};
+use hello::proxy::Proxy;
+use hello::router::Router;
+use hello::server::{Server, VirtualHost};
+use hello::template::{Context, Templates};
+use hello::websocket::Message;
 
 fn main() {
-    let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
-
     let pool = ThreadPool::new(5)
         .unwrap_or_else(|e| {
             println!("Error while creating ThreadPool: {}", e);
             process::exit(1);
         });
 
-    for stream in listener.incoming() {
+    // With HELLO_DEV set, edited templates show up without a restart.
+    let templates = Arc::new(Templates::new(".", env::var("HELLO_DEV").is_ok()));
 
-        let stream = stream.unwrap();
+    // HELLO_LISTEN=127.0.0.1:7878,[::1]:7878,unix:/tmp/hello.sock listens on all three.
+    let listen = env::var("HELLO_LISTEN").unwrap_or_else(|_| String::from("127.0.0.1:7878"));
 
-        pool.execute(|| { 
-            handle_connection(stream); 
+    let mut server = Server::new();
+    for addr in listen.split(',').map(str::trim) {
+        server = server.bind(addr).unwrap_or_else(|e| {
+            eprintln!("Error while binding {}: {}", addr, e);
+            process::exit(1);
         });
+    }
+
+    // The hello app comes first, so it answers for any name not claimed below.
+    server = server.host(VirtualHost::new(&["localhost"], "static", routes(templates)));
 
+    // Every directory in ./sites is a static site served under its own name.
+    if let Ok(entries) = fs::read_dir("sites") {
+        for entry in entries.flatten().filter(|e| e.path().is_dir()) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            server = server.host(VirtualHost::new(&[&name], entry.path(), Router::new().wrap(Logger)));
+        }
     }
+
+    server.run(pool);
 }
 
-fn handle_connection(mut stream: TcpStream) {
+fn routes(templates: Arc<Templates>) -> Router {
+    let home = Arc::clone(&templates);
+    let sleep = Arc::clone(&templates);
 
-    let mut buffer = [0; 1024];
-    stream.read(&mut buffer).unwrap();
+    let mut router = Router::new();
 
-    let get = b"GET / HTTP/1.1\r\n";
-    let sleep = b"GET /sleep HTTP/1.1\r\n";
+    // HELLO_UPSTREAMS=127.0.0.1:8080,127.0.0.1:8081 puts the backends behind /api.
+    if let Ok(upstreams) = env::var("HELLO_UPSTREAMS") {
+        let upstreams: Vec<&str> = upstreams.split(',').map(str::trim).collect();
+        router = router.proxy("/api", Proxy::new(&upstreams));
+    }
 
-    let (status_line, filename) = if buffer.starts_with(get) {
-        ("HTTP/1.1 200 OK", "hello.html")
-    }else if buffer.starts_with(sleep) {
-        thread::sleep(Duration::from_secs(5));
-        ("HTTP/1.1 200 OK", "hello.html")
-    }else {
-        ("HTTP/1.1 404 NOT FOUND", "404.html")
-    };
+    // Every executable in ./cgi-bin is served as /cgi-bin/<name>.
+    if let Ok(entries) = fs::read_dir("cgi-bin") {
+        for entry in entries.flatten() {
+            let executable = entry.metadata().is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0);
+            if executable {
+                let path = format!("/cgi-bin/{}", entry.file_name().to_string_lossy());
+                router = router.cgi(&path, Cgi::new(entry.path()));
+            }
+        }
+    }
 
-    let contents = fs::read_to_string(filename).unwrap();
+    router
+        .wrap(Logger)
+        .wrap(RequestId::new())
+        .get("/", move |_| render(&home, 200, "hello.html", Context::new()))
+        .get("/sleep", move |_| {
+            thread::sleep(Duration::from_secs(5));
+            render(&sleep, 200, "hello.html", Context::new())
+        })
+        .websocket("/echo", |_, mut socket| {
+            loop {
+                let reply = match socket.recv() {
+                    Ok(Message::Text(text)) => Message::Text(text),
+                    Ok(Message::Binary(data)) => Message::Binary(data),
+                    Ok(_) => continue,
+                    Err(_) => break,
+                };
+                if socket.send(&reply).is_err() {
+                    break;
+                }
+            }
+        })
+        .not_found(move |request: &Request| {
+            let mut context = Context::new();
+            context.insert("path", request.path.as_str());
+            render(&templates, 404, "404.html", context)
+        })
+}
 
-    let response = format!(
-        "{}\r\nContent-Lenght: {}\r\n\r\n{}",
-        status_line,
-        contents.len(),
-        contents
-    );
+fn render(templates: &Templates, status: u16, filename: &str, mut context: Context) -> Response {
+    context.insert("pages", vec![page("/", "Home"), page("/sleep", "A slow page")]);
 
-    stream.write(response.as_bytes()).unwrap();
+    match templates.render(filename, &context) {
+        Ok(contents) => Response::html(status, contents),
+        Err(e) => {
+            eprintln!("Error while rendering {}: {}", filename, e);
+            Response::text(500, "Internal Server Error")
+        }
+    }
+}
 
-    stream.flush().unwrap();
-    
+fn page(path: &str, title: &str) -> Context {
+    let mut page = Context::new();
+    page.insert("path", path).insert("title", title);
+    page
 }
@@ -0,0 +1,374 @@
+use std::env;
+use std::io::{self, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::http::{Request, Response};
+
+/// Most output we'll take from a script, headers included.
+const MAX_OUTPUT: usize = 16 * 1024 * 1024;
+
+/// The same as `SIGKILL`.
+const SIGKILL: i32 = 9;
+
+extern "C" {
+    fn kill(pid: i32, sig: i32) -> i32;
+}
+
+/// Runs an executable for each request, following CGI/1.1 (RFC 3875).
+///
+/// The request is described to the script through environment variables, its
+/// body is piped to the script's stdin, and the script answers on stdout with
+/// headers, a blank line and the body. Scripts that take longer than the
+/// timeout are killed, along with anything they started, and so are scripts
+/// whose output grows past `MAX_OUTPUT`.
+pub struct Cgi {
+    program: PathBuf,
+    args: Vec<String>,
+    timeout: Duration,
+    current_dir: Option<PathBuf>,
+    env: Vec<(String, String)>,
+}
+
+impl Cgi {
+    pub fn new<P: AsRef<Path>>(program: P) -> Cgi {
+        Cgi {
+            program: program.as_ref().to_path_buf(),
+            args: Vec::new(),
+            timeout: Duration::from_secs(30),
+            current_dir: None,
+            env: Vec::new(),
+        }
+    }
+
+    /// Pass an argument to the program, for example the script an interpreter should run.
+    pub fn arg(mut self, arg: &str) -> Cgi {
+        self.args.push(arg.to_string());
+        self
+    }
+
+    /// How long the script may run before it is killed.
+    pub fn timeout(mut self, timeout: Duration) -> Cgi {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Run the script in `dir` instead of the server's working directory.
+    pub fn current_dir<P: AsRef<Path>>(mut self, dir: P) -> Cgi {
+        self.current_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set an extra environment variable for the script.
+    pub fn env(mut self, name: &str, value: &str) -> Cgi {
+        self.env.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Run the script for a request to `script_name` or a path below it.
+    ///
+    /// Returns `502 Bad Gateway` if the script can't be started or its output
+    /// is too large or isn't a valid CGI response, and `504 Gateway Timeout`
+    /// if it ran out of time.
+    pub fn run(&self, request: &Request, script_name: &str) -> Response {
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.args)
+            .env_clear()
+            .envs(self.meta_variables(request, script_name))
+            .envs(self.env.iter().map(|(name, value)| (name, value)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            // Its own process group, so whatever it starts can be killed with it.
+            .process_group(0);
+
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                eprintln!("Error while starting {}: {}", self.program.display(), e);
+                return Response::text(502, "Bad Gateway");
+            }
+        };
+
+        match self.communicate(&mut child, request.body.clone()) {
+            Some(Ok(output)) => parse_output(&output).unwrap_or_else(|| {
+                eprintln!("Invalid CGI response from {}", self.program.display());
+                Response::text(502, "Bad Gateway")
+            }),
+            Some(Err(e)) => {
+                eprintln!("Error while talking to {}: {}", self.program.display(), e);
+                Response::text(502, "Bad Gateway")
+            }
+            None => {
+                eprintln!("{} timed out after {:?}; killed", self.program.display(), self.timeout);
+                Response::text(504, "Gateway Timeout")
+            }
+        }
+    }
+
+    /// Feed `body` to the child and collect its output, or return `None` if
+    /// the timeout expired first. Either way the child has exited afterwards,
+    /// and if it was killed, so was the rest of its process group.
+    fn communicate(&self, child: &mut Child, body: Vec<u8>) -> Option<io::Result<Vec<u8>>> {
+        let deadline = Instant::now() + self.timeout;
+
+        // Writing and reading on their own threads means a script that
+        // answers before reading all of its input can't deadlock us.
+        if let Some(mut stdin) = child.stdin.take() {
+            thread::spawn(move || {
+                // The script may exit without reading its input; that's fine.
+                let _ = stdin.write_all(&body);
+            });
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        if let Some(stdout) = child.stdout.take() {
+            thread::spawn(move || {
+                let mut output = Vec::new();
+                let result = match stdout.take(MAX_OUTPUT as u64 + 1).read_to_end(&mut output) {
+                    Ok(len) if len > MAX_OUTPUT => Err(io::Error::new(io::ErrorKind::InvalidData, "output too large")),
+                    result => result.map(|_| output),
+                };
+                let _ = sender.send(result);
+            });
+        }
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let output = match receiver.recv_timeout(remaining) {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => {
+                kill_group(child);
+                return Some(Err(e));
+            }
+            // Something the script started may be what's holding stdout open.
+            Err(_) => {
+                kill_group(child);
+                return None;
+            }
+        };
+
+        // The script may have closed stdout but still be running.
+        loop {
+            match child.try_wait() {
+                Ok(Some(_)) => break,
+                Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+                _ => {
+                    kill_group(child);
+                    return None;
+                }
+            }
+        }
+
+        Some(Ok(output))
+    }
+
+    fn meta_variables(&self, request: &Request, script_name: &str) -> Vec<(String, String)> {
+        let host = request.header("Host").unwrap_or("localhost");
+        let (server_name, server_port) = match host.rsplit_once(':') {
+            Some((name, port)) if !port.is_empty() && port.bytes().all(|b| b.is_ascii_digit()) => (name, port),
+            _ => (host, "80"),
+        };
+
+        let mut vars = vec![
+            ("GATEWAY_INTERFACE", "CGI/1.1".to_string()),
+            ("REQUEST_METHOD", request.method.clone()),
+            ("SCRIPT_NAME", script_name.to_string()),
+            ("PATH_INFO", request.path.strip_prefix(script_name).unwrap_or("").to_string()),
+            ("QUERY_STRING", request.query.clone()),
+            ("SERVER_NAME", server_name.to_string()),
+            ("SERVER_PORT", server_port.to_string()),
+            ("SERVER_PROTOCOL", request.version.clone()),
+            ("SERVER_SOFTWARE", format!("hello/{}", env!("CARGO_PKG_VERSION"))),
+        ];
+
+        if let Ok(path) = env::var("PATH") {
+            vars.push(("PATH", path));
+        }
+        if let Some(peer) = request.peer_addr {
+            vars.push(("REMOTE_ADDR", peer.ip().to_string()));
+            vars.push(("REMOTE_PORT", peer.port().to_string()));
+        }
+        if !request.body.is_empty() {
+            vars.push(("CONTENT_LENGTH", request.body.len().to_string()));
+        }
+        if let Some(content_type) = request.header("Content-Type") {
+            vars.push(("CONTENT_TYPE", content_type.to_string()));
+        }
+        if let Some((scheme, _)) = request.header("Authorization").and_then(|a| a.split_once(' ')) {
+            vars.push(("AUTH_TYPE", scheme.to_string()));
+        }
+
+        let mut vars: Vec<(String, String)> = vars.into_iter().map(|(name, value)| (name.to_string(), value)).collect();
+
+        // Every other header becomes HTTP_<NAME>. Credentials are left out,
+        // as RFC 3875 recommends, so scripts can't leak them, and so is
+        // `Proxy`, which would turn into the HTTP_PROXY many tools obey.
+        for (name, value) in &request.headers {
+            let skip = ["Content-Length", "Content-Type", "Authorization", "Proxy-Authorization", "Proxy"]
+                .iter()
+                .any(|h| h.eq_ignore_ascii_case(name));
+            if !skip {
+                vars.push((format!("HTTP_{}", name.to_ascii_uppercase().replace('-', "_")), value.clone()));
+            }
+        }
+
+        vars
+    }
+}
+
+/// Kill `child` and everything else in its process group, which it leads,
+/// and wait for it.
+fn kill_group(child: &mut Child) {
+    // SAFETY: kill takes no pointers. The group can't have been reused by
+    // anyone else, since its leader hasn't been waited for yet.
+    unsafe {
+        kill(-(child.id() as i32), SIGKILL);
+    }
+    let _ = child.wait();
+}
+
+/// Turn the script's output into a response. A `Status` header sets the
+/// status, and a `Location` without one makes it a redirect.
+fn parse_output(output: &[u8]) -> Option<Response> {
+    let (head, body) = split_head(output)?;
+    let head = std::str::from_utf8(head).ok()?;
+
+    let mut status = None;
+    let mut response = Response::new(200);
+
+    for line in head.lines() {
+        let (name, value) = line.split_once(':')?;
+        let (name, value) = (name.trim(), value.trim());
+
+        if name.eq_ignore_ascii_case("Status") {
+            status = Some(value.split_whitespace().next()?.parse().ok()?);
+        } else {
+            response = response.with_header(name, value);
+        }
+    }
+
+    response.status = match status {
+        Some(status) => status,
+        None if response.header("Location").is_some() => 302,
+        None if response.headers.is_empty() => return None,
+        None => 200,
+    };
+
+    Some(response.with_body(body.to_vec()))
+}
+
+/// Split at the first blank line, which scripts may end with `\n` or `\r\n`.
+fn split_head(output: &[u8]) -> Option<(&[u8], &[u8])> {
+    let mut start = 0;
+
+    while let Some(i) = output[start..].iter().position(|&b| b == b'\n') {
+        let end = start + i + 1;
+        let line = &output[start..end];
+        if line == b"\n" || line == b"\r\n" {
+            return Some((&output[..start], &output[end..]));
+        }
+        start = end;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// A shell script run through `/bin/sh`, which avoids racing other tests
+    /// that fork while the file is still open for writing.
+    fn script(name: &str, source: &str) -> Cgi {
+        let dir = env::temp_dir().join(format!("hello-cgi-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join(name);
+        fs::write(&path, source).unwrap();
+        Cgi::new("/bin/sh").arg(path.to_str().unwrap())
+    }
+
+    fn request(raw: &str) -> Request {
+        Request::read_from(&mut raw.as_bytes(), Some("127.0.0.1:4000".parse().unwrap())).unwrap()
+    }
+
+    #[test]
+    fn passes_environment_and_body() {
+        let cgi = script(
+            "echo.sh",
+            "printf 'Content-Type: text/plain\\r\\n\\r\\n'\n\
+             echo \"$REQUEST_METHOD $SCRIPT_NAME $PATH_INFO $QUERY_STRING\"\n\
+             echo \"$SERVER_NAME:$SERVER_PORT $REMOTE_ADDR $CONTENT_LENGTH $HTTP_X_TOKEN\"\n\
+             cat\n",
+        );
+        let response = cgi.run(
+            &request("POST /ops/echo/extra?a=1 HTTP/1.1\r\nHost: ops.local:8080\r\nX-Token: t\r\nContent-Length: 4\r\n\r\nbody"),
+            "/ops/echo",
+        );
+
+        assert_eq!(200, response.status);
+        assert_eq!(Some("text/plain"), response.header("Content-Type"));
+        assert_eq!(
+            "POST /ops/echo /extra a=1\nops.local:8080 127.0.0.1 4 t\nbody",
+            String::from_utf8(response.body).unwrap()
+        );
+    }
+
+    #[test]
+    fn status_and_redirects() {
+        let cgi = script("missing.sh", "printf 'Status: 404 Not Found\\nContent-Type: text/plain\\n\\ngone'\n");
+        let response = cgi.run(&request("GET /missing HTTP/1.1\r\n\r\n"), "/missing");
+        assert_eq!(404, response.status);
+        assert_eq!(b"gone", response.body.as_slice());
+
+        let cgi = script("redirect.sh", "printf 'Location: /elsewhere\\n\\n'\n");
+        let response = cgi.run(&request("GET /redirect HTTP/1.1\r\n\r\n"), "/redirect");
+        assert_eq!(302, response.status);
+        assert_eq!(Some("/elsewhere"), response.header("Location"));
+    }
+
+    #[test]
+    fn rejects_output_without_headers() {
+        let cgi = script("bad.sh", "echo oops\n");
+        let response = cgi.run(&request("GET /bad HTTP/1.1\r\n\r\n"), "/bad");
+        assert_eq!(502, response.status);
+    }
+
+    #[test]
+    fn kills_scripts_that_time_out() {
+        let marker = env::temp_dir().join(format!("hello-cgi-marker-{}", std::process::id()));
+        let _ = fs::remove_file(&marker);
+        // What the script starts in the background goes with it.
+        let cgi = script("slow.sh", &format!("(sleep 1; touch {0}) &\nsleep 1\ntouch {0}\n", marker.display()));
+
+        let start = Instant::now();
+        let response = cgi
+            .timeout(Duration::from_millis(200))
+            .run(&request("GET /slow HTTP/1.1\r\n\r\n"), "/slow");
+
+        assert_eq!(504, response.status);
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        thread::sleep(Duration::from_millis(1200));
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn rejects_output_that_is_too_large() {
+        let cgi = script("flood.sh", "printf 'Content-Type: text/plain\\n\\n'\nexec yes\n");
+        let start = Instant::now();
+        let response = cgi.timeout(Duration::from_secs(10)).run(&request("GET /flood HTTP/1.1\r\n\r\n"), "/flood");
+        assert_eq!(502, response.status);
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+}
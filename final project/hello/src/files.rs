@@ -0,0 +1,118 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::http::{Request, Response};
+
+/// Serve the file under `root` that `request` asks for.
+///
+/// Returns `None` for anything that isn't a `GET` or `HEAD` of an existing
+/// file, so the caller can fall back to its not-found page. Directories are
+/// served through their `index.html`, and paths that try to climb out of
+/// `root` are never looked up.
+pub fn serve(root: &Path, request: &Request) -> Option<Response> {
+    if request.method != "GET" && request.method != "HEAD" {
+        return None;
+    }
+
+    let mut path = resolve(root, &request.path)?;
+    if path.is_dir() {
+        path.push("index.html");
+    }
+
+    let contents = fs::read(&path).ok()?;
+    let response = Response::new(200).with_header("Content-Type", content_type(&path));
+
+    if request.method == "HEAD" {
+        return Some(response.with_header("Content-Length", &contents.len().to_string()));
+    }
+    Some(response.with_body(contents))
+}
+
+fn resolve(root: &Path, request_path: &str) -> Option<PathBuf> {
+    let decoded = percent_decode(request_path)?;
+    let mut path = root.to_path_buf();
+
+    for component in decoded.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => return None,
+            c if c.contains('\\') || c.contains('\0') => return None,
+            c => path.push(c),
+        }
+    }
+
+    Some(path)
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut input = s.bytes();
+
+    while let Some(b) = input.next() {
+        if b == b'%' {
+            let hex = [input.next()?, input.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("ico") => "image/x-icon",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn request(method: &str, path: &str) -> Request {
+        let raw = format!("{} {} HTTP/1.1\r\n\r\n", method, path);
+        Request::read_from(&mut raw.as_bytes(), None).unwrap()
+    }
+
+    #[test]
+    fn serves_files_and_indexes() {
+        let root = env::temp_dir().join(format!("hello-files-{}", std::process::id()));
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("index.html"), "<h1>home</h1>").unwrap();
+        fs::write(root.join("docs/a b.txt"), "notes").unwrap();
+
+        let response = serve(&root, &request("GET", "/")).unwrap();
+        assert_eq!(Some("text/html; charset=utf-8"), response.header("Content-Type"));
+        assert_eq!(b"<h1>home</h1>", response.body.as_slice());
+
+        let response = serve(&root, &request("GET", "/docs/a%20b.txt")).unwrap();
+        assert_eq!(b"notes", response.body.as_slice());
+
+        let response = serve(&root, &request("HEAD", "/docs/a%20b.txt")).unwrap();
+        assert_eq!(Some("5"), response.header("Content-Length"));
+        assert!(response.body.is_empty());
+
+        assert!(serve(&root, &request("GET", "/missing.txt")).is_none());
+        assert!(serve(&root, &request("POST", "/")).is_none());
+    }
+
+    #[test]
+    fn refuses_to_leave_root() {
+        let root = Path::new("/srv/site");
+        assert_eq!(None, resolve(root, "/../etc/passwd"));
+        assert_eq!(None, resolve(root, "/%2e%2e/etc/passwd"));
+        assert_eq!(Some(PathBuf::from("/srv/site/a/b")), resolve(root, "//a/./b"));
+    }
+}
@@ -0,0 +1,367 @@
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::SocketAddr;
+
+/// Largest request head (request line plus headers) we are willing to read.
+const MAX_HEAD: usize = 16 * 1024;
+/// Largest request body we are willing to buffer.
+const MAX_BODY: usize = 16 * 1024 * 1024;
+
+/// Anything a connection can be served over.
+pub trait Stream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Stream for T {}
+
+/// A client connection with buffered reads.
+///
+/// Bytes the client sent after the request head stay in the buffer, so a
+/// handler that takes over the connection (like a WebSocket) doesn't lose them.
+pub struct Connection {
+    reader: BufReader<Box<dyn Stream>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    pub version: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub peer_addr: Option<SocketAddr>,
+}
+
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// Sent after `body`, until it runs out, without being buffered first.
+    stream: Option<Box<dyn Read + Send>>,
+}
+
+impl Connection {
+    pub fn new<S: Stream + 'static>(stream: S) -> Connection {
+        Connection { reader: BufReader::new(Box::new(stream)) }
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl BufRead for Connection {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.reader.consume(amt)
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.reader.get_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.reader.get_mut().flush()
+    }
+}
+
+impl Request {
+    /// Read one request, including a body announced by `Content-Length` or
+    /// sent with `Transfer-Encoding: chunked`, which is decoded.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `InvalidData` if the request is malformed or too large, or
+    /// with whatever error reading from `reader` produces.
+    pub fn read_from<R: BufRead>(reader: &mut R, peer_addr: Option<SocketAddr>) -> io::Result<Request> {
+        let mut head_len = 0;
+        let request_line = read_line(reader, &mut head_len)?;
+
+        let mut parts = request_line.split_whitespace();
+        let (method, target, version) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(method), Some(target), Some(version), None) if version.starts_with("HTTP/") => {
+                (method.to_string(), target, version.to_string())
+            }
+            _ => return Err(invalid("malformed request line")),
+        };
+
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path.to_string(), query.to_string()),
+            None => (target.to_string(), String::new()),
+        };
+
+        let mut headers = Vec::new();
+        loop {
+            let line = read_line(reader, &mut head_len)?;
+            if line.is_empty() {
+                break;
+            }
+
+            let (name, value) = line.split_once(':').ok_or_else(|| invalid("malformed header"))?;
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+
+        let mut request = Request { method, path, query, version, headers, body: Vec::new(), peer_addr };
+
+        if let Some(coding) = request.header("Transfer-Encoding") {
+            // Chunked has to come last, or the body would only end when the
+            // connection does. It takes precedence over Content-Length.
+            if !coding.rsplit(',').next().is_some_and(|last| last.trim().eq_ignore_ascii_case("chunked")) {
+                return Err(invalid("unsupported Transfer-Encoding"));
+            }
+            request.body = read_chunked(reader)?;
+        } else if let Some(len) = request.header("Content-Length") {
+            let len: usize = len.parse().map_err(|_| invalid("bad Content-Length"))?;
+            if len > MAX_BODY {
+                return Err(invalid("request body too large"));
+            }
+            request.body = vec![0; len];
+            reader.read_exact(&mut request.body)?;
+        }
+
+        Ok(request)
+    }
+
+    /// The value of the first header called `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// Whether the comma-separated header `name` contains `token`, ignoring case.
+    pub fn header_has_token(&self, name: &str, token: &str) -> bool {
+        self.headers
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            .flat_map(|(_, value)| value.split(','))
+            .any(|t| t.trim().eq_ignore_ascii_case(token))
+    }
+}
+
+impl Response {
+    pub fn new(status: u16) -> Response {
+        Response { status, headers: Vec::new(), body: Vec::new(), stream: None }
+    }
+
+    /// An HTML page with the given status.
+    pub fn html<B: Into<Vec<u8>>>(status: u16, body: B) -> Response {
+        Response::new(status)
+            .with_header("Content-Type", "text/html; charset=utf-8")
+            .with_body(body)
+    }
+
+    /// A plain text response with the given status.
+    pub fn text<B: Into<Vec<u8>>>(status: u16, body: B) -> Response {
+        Response::new(status)
+            .with_header("Content-Type", "text/plain; charset=utf-8")
+            .with_body(body)
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Response {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_body<B: Into<Vec<u8>>>(mut self, body: B) -> Response {
+        self.body = body.into();
+        self
+    }
+
+    /// Stream the rest of the body from `reader` as it is written out.
+    ///
+    /// No `Content-Length` is added for streamed responses: unless a header
+    /// says otherwise, the body ends when the connection is closed.
+    pub fn with_stream<R: Read + Send + 'static>(mut self, reader: R) -> Response {
+        self.stream = Some(Box::new(reader));
+        self
+    }
+
+    /// The value of the first header called `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// Write the status line, headers and body.
+    ///
+    /// `Content-Length` is filled in unless the response already has one or
+    /// is streamed, and since we serve one request per connection, so is
+    /// `Connection: close`.
+    pub fn write_to<W: Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        if self.status != 101 {
+            if self.header("Content-Length").is_none() && self.stream.is_none() {
+                head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+            }
+            if self.header("Connection").is_none() {
+                head.push_str("Connection: close\r\n");
+            }
+        }
+        head.push_str("\r\n");
+
+        writer.write_all(head.as_bytes())?;
+        writer.write_all(&self.body)?;
+        if let Some(mut stream) = self.stream.take() {
+            io::copy(&mut stream, writer)?;
+        }
+        writer.flush()
+    }
+}
+
+impl fmt::Debug for Response {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Response")
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .field("body", &self.body)
+            .field("streamed", &self.stream.is_some())
+            .finish()
+    }
+}
+
+/// The standard reason phrase for a status code.
+pub fn reason(status: u16) -> &'static str {
+    match status {
+        101 => "Switching Protocols",
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        421 => "Misdirected Request",
+        426 => "Upgrade Required",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "Unknown",
+    }
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// Read a line terminated by `\n`, dropping the line ending.
+fn read_line<R: BufRead>(reader: &mut R, head_len: &mut usize) -> io::Result<String> {
+    let mut line = Vec::new();
+    let limit = (MAX_HEAD - *head_len) as u64;
+    let len = reader.by_ref().take(limit).read_until(b'\n', &mut line)?;
+
+    if len == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"));
+    }
+    if line.last() != Some(&b'\n') {
+        return Err(invalid("request head too large"));
+    }
+    *head_len += len;
+
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    String::from_utf8(line).map_err(|_| invalid("request head is not UTF-8"))
+}
+
+/// Read a chunked body, up to `MAX_BODY` bytes of it, and the trailer
+/// after it, which is dropped.
+fn read_chunked<R: BufRead>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    let mut framing_len = 0;
+    loop {
+        let line = read_line(reader, &mut framing_len)?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| invalid("bad chunk size"))?;
+        if size == 0 {
+            break;
+        }
+        if size > MAX_BODY - body.len() {
+            return Err(invalid("request body too large"));
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+        if !read_line(reader, &mut framing_len)?.is_empty() {
+            return Err(invalid("chunk longer than its size"));
+        }
+    }
+
+    while !read_line(reader, &mut framing_len)?.is_empty() {}
+    Ok(body)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn parses_request_with_body() {
+        let raw = "POST /submit?x=1 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nhello";
+        let request = Request::read_from(&mut Cursor::new(raw), None).unwrap();
+
+        assert_eq!("POST", request.method);
+        assert_eq!("/submit", request.path);
+        assert_eq!("x=1", request.query);
+        assert_eq!(Some("localhost"), request.header("host"));
+        assert_eq!(b"hello", request.body.as_slice());
+    }
+
+    #[test]
+    fn decodes_chunked_bodies() {
+        let raw = "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\nX-Trailer: 1\r\n\r\n";
+        let request = Request::read_from(&mut Cursor::new(raw), None).unwrap();
+        assert_eq!(b"hello, world", request.body.as_slice());
+
+        let bad = ["5\r\nhello, world\r\n0\r\n\r\n", "x\r\n\r\n", "5\r\nhel"];
+        for body in bad {
+            let raw = format!("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n{}", body);
+            assert!(Request::read_from(&mut Cursor::new(raw), None).is_err());
+        }
+        let raw = "POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n";
+        assert!(Request::read_from(&mut Cursor::new(raw), None).is_err());
+        let raw = format!("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n", MAX_BODY + 1);
+        assert!(Request::read_from(&mut Cursor::new(raw), None).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_requests() {
+        let raw = "GET /\r\n\r\n";
+        assert!(Request::read_from(&mut Cursor::new(raw), None).is_err());
+    }
+
+    #[test]
+    fn writes_content_length() {
+        let mut out = Vec::new();
+        Response::text(404, "nope").write_to(&mut out).unwrap();
+
+        assert_eq!(
+            "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: 4\r\nConnection: close\r\n\r\nnope",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
@@ -1,3 +1,14 @@
+mod base64;
+pub mod cgi;
+mod files;
+pub mod http;
+pub mod middleware;
+pub mod proxy;
+pub mod router;
+pub mod server;
+pub mod template;
+pub mod websocket;
+
 use std::fmt::Display;
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
@@ -0,0 +1,346 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use crate::base64;
+use crate::http::{Request, Response};
+
+/// Code that runs around every request a `Router` handles.
+///
+/// A middleware gets the request before the handler does and can change it,
+/// answer it without calling `next` at all, or call `next` and change the
+/// response on its way back out.
+pub trait Middleware: Send + Sync {
+    fn handle(&self, request: Request, next: Next<'_>) -> Response;
+}
+
+/// The rest of the chain: the remaining middleware and then the handler.
+pub struct Next<'a> {
+    middleware: &'a [Box<dyn Middleware>],
+    endpoint: &'a dyn Fn(Request) -> Response,
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(Request, Next<'_>) -> Response + Send + Sync,
+{
+    fn handle(&self, request: Request, next: Next<'_>) -> Response {
+        self(request, next)
+    }
+}
+
+impl<'a> Next<'a> {
+    pub fn new(middleware: &'a [Box<dyn Middleware>], endpoint: &'a dyn Fn(Request) -> Response) -> Next<'a> {
+        Next { middleware, endpoint }
+    }
+
+    /// Pass the request on to the next middleware, or the handler if this was the last one.
+    pub fn run(self, request: Request) -> Response {
+        match self.middleware.split_first() {
+            Some((first, rest)) => first.handle(request, Next::new(rest, self.endpoint)),
+            None => (self.endpoint)(request),
+        }
+    }
+}
+
+/// Prints one line per request with the status and how long it took.
+pub struct Logger;
+
+impl Middleware for Logger {
+    fn handle(&self, request: Request, next: Next<'_>) -> Response {
+        let start = Instant::now();
+        let line = format!("{} {}", request.method, request.path);
+        let response = next.run(request);
+
+        println!("{} -> {} in {:?}", line, response.status, start.elapsed());
+        response
+    }
+}
+
+/// Makes sure every request has an `X-Request-Id` and echoes it in the response.
+///
+/// An id the client sent is kept if it looks sane, otherwise a new one is made.
+pub struct RequestId {
+    prefix: u64,
+    counter: AtomicU64,
+}
+
+impl RequestId {
+    pub fn new() -> RequestId {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u8(0);
+        RequestId { prefix: hasher.finish() >> 32, counter: AtomicU64::new(0) }
+    }
+}
+
+impl Default for RequestId {
+    fn default() -> RequestId {
+        RequestId::new()
+    }
+}
+
+impl Middleware for RequestId {
+    fn handle(&self, mut request: Request, next: Next<'_>) -> Response {
+        let valid = |id: &str| {
+            !id.is_empty() && id.len() <= 64 && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        };
+
+        let id = match request.header("X-Request-Id") {
+            Some(id) if valid(id) => id.to_string(),
+            _ => {
+                let n = self.counter.fetch_add(1, Ordering::Relaxed);
+                format!("{:08x}-{:08x}", self.prefix, n)
+            }
+        };
+
+        request.headers.retain(|(name, _)| !name.eq_ignore_ascii_case("X-Request-Id"));
+        request.headers.push(("X-Request-Id".to_string(), id.clone()));
+
+        next.run(request).with_header("X-Request-Id", &id)
+    }
+}
+
+/// Cross-origin resource sharing: answers preflight requests and adds the
+/// `Access-Control-*` headers for allowed origins.
+pub struct Cors {
+    origins: Option<Vec<String>>,
+    methods: String,
+    headers: String,
+    credentials: bool,
+    max_age: Option<u32>,
+}
+
+impl Cors {
+    /// Allow any origin, the common methods and no extra request headers.
+    pub fn new() -> Cors {
+        Cors {
+            origins: None,
+            methods: "GET, HEAD, POST".to_string(),
+            headers: String::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// Only allow the given origin. Can be called several times.
+    pub fn allow_origin(mut self, origin: &str) -> Cors {
+        self.origins.get_or_insert_with(Vec::new).push(origin.to_string());
+        self
+    }
+
+    pub fn allow_methods(mut self, methods: &[&str]) -> Cors {
+        self.methods = methods.join(", ");
+        self
+    }
+
+    pub fn allow_headers(mut self, headers: &[&str]) -> Cors {
+        self.headers = headers.join(", ");
+        self
+    }
+
+    /// Let browsers send cookies and credentials along.
+    pub fn allow_credentials(mut self) -> Cors {
+        self.credentials = true;
+        self
+    }
+
+    /// How many seconds browsers may cache a preflight answer.
+    pub fn max_age(mut self, seconds: u32) -> Cors {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    fn allowed(&self, origin: &str) -> bool {
+        match &self.origins {
+            Some(origins) => origins.iter().any(|o| o == origin),
+            None => true,
+        }
+    }
+
+    fn add_headers(&self, mut response: Response, origin: &str) -> Response {
+        // With credentials, browsers refuse a wildcard, so echo the origin back.
+        let allow = if self.origins.is_none() && !self.credentials { "*" } else { origin };
+        response = response.with_header("Access-Control-Allow-Origin", allow);
+
+        if allow != "*" {
+            response = response.with_header("Vary", "Origin");
+        }
+        if self.credentials {
+            response = response.with_header("Access-Control-Allow-Credentials", "true");
+        }
+        response
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Cors {
+        Cors::new()
+    }
+}
+
+impl Middleware for Cors {
+    fn handle(&self, request: Request, next: Next<'_>) -> Response {
+        let origin = match request.header("Origin") {
+            Some(origin) if self.allowed(origin) => origin.to_string(),
+            _ => return next.run(request),
+        };
+
+        let preflight = request.method == "OPTIONS" && request.header("Access-Control-Request-Method").is_some();
+        if !preflight {
+            return self.add_headers(next.run(request), &origin);
+        }
+
+        let mut response = Response::new(204).with_header("Access-Control-Allow-Methods", &self.methods);
+        if !self.headers.is_empty() {
+            response = response.with_header("Access-Control-Allow-Headers", &self.headers);
+        }
+        if let Some(max_age) = self.max_age {
+            response = response.with_header("Access-Control-Max-Age", &max_age.to_string());
+        }
+        self.add_headers(response, &origin)
+    }
+}
+
+type CredentialCheck = Box<dyn Fn(&str, &str) -> bool + Send + Sync>;
+
+/// HTTP Basic authentication. Requests without valid credentials get a `401`.
+pub struct BasicAuth {
+    realm: String,
+    check: CredentialCheck,
+}
+
+impl BasicAuth {
+    /// Accept the user name and password pairs `check` returns true for.
+    pub fn new<F>(realm: &str, check: F) -> BasicAuth
+    where
+        F: Fn(&str, &str) -> bool + Send + Sync + 'static,
+    {
+        BasicAuth { realm: realm.to_string(), check: Box::new(check) }
+    }
+
+    /// Accept a fixed list of user names and passwords.
+    pub fn with_users(realm: &str, users: &[(&str, &str)]) -> BasicAuth {
+        let users: Vec<(String, String)> = users
+            .iter()
+            .map(|(user, password)| (user.to_string(), password.to_string()))
+            .collect();
+
+        BasicAuth::new(realm, move |user, password| {
+            users.iter().any(|(u, p)| u == user && constant_time_eq(p.as_bytes(), password.as_bytes()))
+        })
+    }
+
+    fn credentials(request: &Request) -> Option<(String, String)> {
+        let header = request.header("Authorization")?;
+        let (scheme, encoded) = header.split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("Basic") {
+            return None;
+        }
+
+        let decoded = String::from_utf8(base64::decode(encoded.trim())?).ok()?;
+        let (user, password) = decoded.split_once(':')?;
+        Some((user.to_string(), password.to_string()))
+    }
+}
+
+impl Middleware for BasicAuth {
+    fn handle(&self, request: Request, next: Next<'_>) -> Response {
+        match BasicAuth::credentials(&request) {
+            Some((user, password)) if (self.check)(&user, &password) => next.run(request),
+            _ => Response::text(401, "Unauthorized")
+                .with_header("WWW-Authenticate", &format!("Basic realm=\"{}\", charset=\"UTF-8\"", self.realm)),
+        }
+    }
+}
+
+/// Compare without bailing out at the first difference, so the time taken
+/// doesn't tell an attacker how much of a password was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(raw: &str) -> Request {
+        Request::read_from(&mut raw.as_bytes(), None).unwrap()
+    }
+
+    fn run(middleware: Vec<Box<dyn Middleware>>, request: Request) -> Response {
+        let endpoint = |request: Request| {
+            let id = request.header("X-Request-Id").unwrap_or("none").to_string();
+            Response::text(200, id)
+        };
+        Next::new(&middleware, &endpoint).run(request)
+    }
+
+    #[test]
+    fn runs_in_order_and_short_circuits() {
+        let outer = |request: Request, next: Next<'_>| next.run(request).with_header("X-Order", "outer");
+        let inner = |request: Request, next: Next<'_>| {
+            if request.path == "/blocked" {
+                return Response::text(403, "Forbidden");
+            }
+            next.run(request).with_header("X-Order", "inner")
+        };
+        let chain: Vec<Box<dyn Middleware>> = vec![Box::new(outer), Box::new(inner)];
+
+        let response = Next::new(&chain, &|_| Response::new(200)).run(request("GET / HTTP/1.1\r\n\r\n"));
+        let order: Vec<&str> = response.headers.iter().map(|(_, v)| v.as_str()).collect();
+        assert_eq!(vec!["inner", "outer"], order);
+
+        let response = Next::new(&chain, &|_| Response::new(200)).run(request("GET /blocked HTTP/1.1\r\n\r\n"));
+        assert_eq!(403, response.status);
+    }
+
+    #[test]
+    fn request_id_reaches_handler_and_response() {
+        let response = run(vec![Box::new(RequestId::new())], request("GET / HTTP/1.1\r\n\r\n"));
+        let id = response.header("X-Request-Id").unwrap();
+        assert_eq!(id.as_bytes(), response.body.as_slice());
+
+        let response = run(
+            vec![Box::new(RequestId::new())],
+            request("GET / HTTP/1.1\r\nX-Request-Id: abc-123\r\n\r\n"),
+        );
+        assert_eq!(Some("abc-123"), response.header("X-Request-Id"));
+    }
+
+    #[test]
+    fn cors_preflight() {
+        let cors = Cors::new()
+            .allow_origin("https://example.com")
+            .allow_methods(&["GET", "PUT"])
+            .max_age(600);
+        let response = run(
+            vec![Box::new(cors)],
+            request("OPTIONS /api HTTP/1.1\r\nOrigin: https://example.com\r\nAccess-Control-Request-Method: PUT\r\n\r\n"),
+        );
+
+        assert_eq!(204, response.status);
+        assert_eq!(Some("https://example.com"), response.header("Access-Control-Allow-Origin"));
+        assert_eq!(Some("GET, PUT"), response.header("Access-Control-Allow-Methods"));
+        assert_eq!(Some("600"), response.header("Access-Control-Max-Age"));
+
+        let cors = Cors::new().allow_origin("https://example.com");
+        let response = run(vec![Box::new(cors)], request("GET / HTTP/1.1\r\nOrigin: https://evil.test\r\n\r\n"));
+        assert_eq!(None, response.header("Access-Control-Allow-Origin"));
+    }
+
+    #[test]
+    fn basic_auth() {
+        let auth = || Box::new(BasicAuth::with_users("hello", &[("admin", "secret")])) as Box<dyn Middleware>;
+
+        let response = run(vec![auth()], request("GET / HTTP/1.1\r\n\r\n"));
+        assert_eq!(401, response.status);
+        assert_eq!(Some("Basic realm=\"hello\", charset=\"UTF-8\""), response.header("WWW-Authenticate"));
+
+        let wrong = format!("GET / HTTP/1.1\r\nAuthorization: Basic {}\r\n\r\n", base64::encode(b"admin:guess"));
+        assert_eq!(401, run(vec![auth()], request(&wrong)).status);
+
+        let right = format!("GET / HTTP/1.1\r\nAuthorization: Basic {}\r\n\r\n", base64::encode(b"admin:secret"));
+        assert_eq!(200, run(vec![auth()], request(&right)).status);
+    }
+}
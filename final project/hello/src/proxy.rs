@@ -0,0 +1,417 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::http::{Request, Response};
+
+/// Headers that only describe one hop and must not be passed along.
+const HOP_BY_HOP: [&str; 7] = [
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Connection",
+    "Proxy-Authorization",
+    "TE",
+    "Trailer",
+    "Upgrade",
+];
+
+/// Forwards requests to one or more upstream `host:port` backends.
+///
+/// Upstreams are tried round-robin. Health is checked passively: an upstream
+/// that fails `max_fails` times in a row is skipped for `fail_timeout`,
+/// unless every upstream is down, in which case they are all tried anyway.
+pub struct Proxy {
+    upstreams: Vec<Upstream>,
+    next: AtomicUsize,
+    timeout: Duration,
+    max_fails: u32,
+    fail_timeout: Duration,
+}
+
+struct Upstream {
+    addr: String,
+    health: Mutex<Health>,
+}
+
+#[derive(Default)]
+struct Health {
+    failures: u32,
+    down_until: Option<Instant>,
+}
+
+/// Why forwarding to one upstream failed, and whether another may be tried.
+enum ForwardError {
+    /// Nothing reached the upstream, so trying the next one is always safe.
+    Connect(io::Error),
+    /// The request may have been seen by the upstream.
+    Exchange(io::Error),
+}
+
+impl Proxy {
+    /// Proxy to the given upstreams, each written as `host:port`.
+    pub fn new(upstreams: &[&str]) -> Proxy {
+        Proxy {
+            upstreams: upstreams
+                .iter()
+                .map(|addr| Upstream { addr: addr.to_string(), health: Mutex::new(Health::default()) })
+                .collect(),
+            next: AtomicUsize::new(0),
+            timeout: Duration::from_secs(30),
+            max_fails: 1,
+            fail_timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// How long to wait when connecting to, reading from or writing to an upstream.
+    pub fn timeout(mut self, timeout: Duration) -> Proxy {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How many failures in a row take an upstream out of rotation.
+    pub fn max_fails(mut self, max_fails: u32) -> Proxy {
+        self.max_fails = max_fails.max(1);
+        self
+    }
+
+    /// How long an upstream stays out of rotation.
+    pub fn fail_timeout(mut self, fail_timeout: Duration) -> Proxy {
+        self.fail_timeout = fail_timeout;
+        self
+    }
+
+    /// Send `request` upstream and stream the answer back.
+    ///
+    /// Returns `502 Bad Gateway` if no upstream could be reached.
+    pub fn forward(&self, request: &Request) -> Response {
+        if self.upstreams.is_empty() {
+            return Response::text(502, "Bad Gateway");
+        }
+
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let now = Instant::now();
+        let order: Vec<&Upstream> = (0..self.upstreams.len())
+            .map(|i| &self.upstreams[(start + i) % self.upstreams.len()])
+            .collect();
+
+        let mut candidates: Vec<&Upstream> = order.iter().copied().filter(|u| u.is_up(now)).collect();
+        if candidates.is_empty() {
+            candidates = order;
+        }
+
+        // A request that might already have had an effect is only sent again
+        // if doing it twice is harmless.
+        let idempotent = matches!(request.method.as_str(), "GET" | "HEAD" | "OPTIONS" | "PUT" | "DELETE");
+
+        for upstream in candidates {
+            match self.exchange(upstream, request) {
+                Ok(response) => {
+                    upstream.succeeded();
+                    return response;
+                }
+                Err(ForwardError::Connect(e)) => {
+                    eprintln!("Error while connecting to upstream {}: {}", upstream.addr, e);
+                    upstream.failed(self.max_fails, self.fail_timeout);
+                }
+                Err(ForwardError::Exchange(e)) => {
+                    eprintln!("Error while talking to upstream {}: {}", upstream.addr, e);
+                    upstream.failed(self.max_fails, self.fail_timeout);
+                    if !idempotent {
+                        break;
+                    }
+                }
+            }
+        }
+
+        Response::text(502, "Bad Gateway")
+    }
+
+    fn exchange(&self, upstream: &Upstream, request: &Request) -> Result<Response, ForwardError> {
+        let stream = self.connect(&upstream.addr).map_err(ForwardError::Connect)?;
+        self.send(&stream, request).map_err(ForwardError::Exchange)?;
+        read_response(stream).map_err(ForwardError::Exchange)
+    }
+
+    fn connect(&self, addr: &str) -> io::Result<TcpStream> {
+        let mut last_error = io::Error::new(io::ErrorKind::InvalidInput, "upstream address did not resolve");
+
+        for addr in addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, self.timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(self.timeout))?;
+                    stream.set_write_timeout(Some(self.timeout))?;
+                    return Ok(stream);
+                }
+                Err(e) => last_error = e,
+            }
+        }
+
+        Err(last_error)
+    }
+
+    fn send(&self, mut stream: &TcpStream, request: &Request) -> io::Result<()> {
+        let target = if request.query.is_empty() {
+            request.path.clone()
+        } else {
+            format!("{}?{}", request.path, request.query)
+        };
+        let mut head = format!("{} {} HTTP/1.1\r\n", request.method, target);
+
+        let connection_headers: Vec<String> = request
+            .headers
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case("Connection"))
+            .flat_map(|(_, value)| value.split(',').map(|t| t.trim().to_string()))
+            .collect();
+
+        let mut forwarded_for: Vec<&str> = Vec::new();
+        for (name, value) in &request.headers {
+            let skip = is_hop_by_hop(name)
+                || name.eq_ignore_ascii_case("Transfer-Encoding")
+                || name.eq_ignore_ascii_case("Content-Length")
+                || connection_headers.iter().any(|h| h.eq_ignore_ascii_case(name));
+
+            if name.eq_ignore_ascii_case("X-Forwarded-For") {
+                forwarded_for.push(value);
+            } else if !skip {
+                head.push_str(&format!("{}: {}\r\n", name, value));
+            }
+        }
+
+        // Several X-Forwarded-For headers are one list, which we add to.
+        let peer = request.peer_addr.map(|peer| peer.ip().to_string());
+        forwarded_for.extend(peer.as_deref());
+        if !forwarded_for.is_empty() {
+            head.push_str(&format!("X-Forwarded-For: {}\r\n", forwarded_for.join(", ")));
+        }
+
+        // A chunked body has been decoded, so it's sent with a length.
+        let framed = request.header("Content-Length").is_some() || request.header("Transfer-Encoding").is_some();
+        if !request.body.is_empty() || framed {
+            head.push_str(&format!("Content-Length: {}\r\n", request.body.len()));
+        }
+        head.push_str("Connection: close\r\n\r\n");
+
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(&request.body)?;
+        stream.flush()
+    }
+}
+
+impl Upstream {
+    fn is_up(&self, now: Instant) -> bool {
+        match self.health.lock().unwrap().down_until {
+            Some(until) => now >= until,
+            None => true,
+        }
+    }
+
+    fn succeeded(&self) {
+        *self.health.lock().unwrap() = Health::default();
+    }
+
+    fn failed(&self, max_fails: u32, fail_timeout: Duration) {
+        let mut health = self.health.lock().unwrap();
+        health.failures += 1;
+        if health.failures >= max_fails {
+            health.failures = 0;
+            health.down_until = Some(Instant::now() + fail_timeout);
+        }
+    }
+}
+
+/// Read the upstream's status line and headers, and leave the body to be
+/// streamed straight from the socket.
+///
+/// The upstream was asked to close the connection when it is done, and so do
+/// we, so the body framing headers can be passed through untouched.
+fn read_response(stream: TcpStream) -> io::Result<Response> {
+    let mut reader = BufReader::new(stream);
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed upstream status line"))?;
+
+    let mut response = Response::new(status);
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "upstream closed during headers"));
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if !is_hop_by_hop(name.trim()) {
+                response = response.with_header(name.trim(), value.trim());
+            }
+        }
+    }
+
+    Ok(response.with_stream(reader))
+}
+
+fn is_hop_by_hop(name: &str) -> bool {
+    HOP_BY_HOP.iter().any(|h| h.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::mpsc;
+    use std::thread;
+
+    /// A stand-in backend that answers every request with `body` and sends
+    /// the raw requests it got down the returned channel.
+    fn backend(body: &'static str) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let request = Request::read_from(&mut BufReader::new(&mut stream), None).unwrap();
+
+                let mut raw = format!("{} {}\n", request.method, request.path);
+                for (name, value) in &request.headers {
+                    raw.push_str(&format!("{}: {}\n", name, value));
+                }
+                raw.push_str(&String::from_utf8_lossy(&request.body));
+                sender.send(raw).unwrap();
+
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        (addr, receiver)
+    }
+
+    /// An upstream that never answers. Its listener has to be kept alive,
+    /// or another test could bind the port, but it never accepts, so
+    /// requests to it time out.
+    fn dead_backend() -> (String, TcpListener) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        (listener.local_addr().unwrap().to_string(), listener)
+    }
+
+    fn request(raw: &str) -> Request {
+        let peer: SocketAddr = "10.0.0.7:5555".parse().unwrap();
+        Request::read_from(&mut raw.as_bytes(), Some(peer)).unwrap()
+    }
+
+    fn body(mut response: Response) -> String {
+        let mut out = Vec::new();
+        response.write_to(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        out.split_once("\r\n\r\n").unwrap().1.to_string()
+    }
+
+    #[test]
+    fn forwards_headers_and_body() {
+        let (addr, requests) = backend("hi");
+        let proxy = Proxy::new(&[&addr]);
+
+        let response = proxy.forward(&request(
+            "POST /api/items?id=3 HTTP/1.1\r\nHost: example.com\r\nConnection: keep-alive\r\n\
+             X-Forwarded-For: 192.168.1.1\r\nContent-Length: 4\r\n\r\ndata",
+        ));
+
+        let seen = requests.recv().unwrap();
+        assert!(seen.starts_with("POST /api/items\n"));
+        assert!(seen.contains("Host: example.com\n"));
+        assert!(seen.contains("X-Forwarded-For: 192.168.1.1, 10.0.0.7\n"));
+        assert!(seen.contains("Connection: close\n"));
+        assert!(!seen.contains("keep-alive"));
+        assert!(seen.ends_with("data"));
+
+        assert_eq!(200, response.status);
+        assert_eq!(Some("chunked"), response.header("Transfer-Encoding"));
+        assert_eq!("2\r\nhi\r\n0\r\n\r\n", body(response));
+    }
+
+    #[test]
+    fn forwards_chunked_bodies_with_a_length() {
+        let (addr, requests) = backend("hi");
+        let proxy = Proxy::new(&[&addr]);
+
+        let response = proxy.forward(&request(
+            "POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n4\r\ndata\r\n5\r\n more\r\n0\r\n\r\n",
+        ));
+
+        let seen = requests.recv().unwrap();
+        assert!(seen.contains("Content-Length: 9\n"));
+        assert!(!seen.contains("Transfer-Encoding"));
+        assert!(seen.ends_with("\ndata more"));
+        assert_eq!(200, response.status);
+    }
+
+    #[test]
+    fn joins_forwarded_for_headers() {
+        let (addr, requests) = backend("hi");
+        let proxy = Proxy::new(&[&addr]);
+
+        proxy.forward(&request(
+            "GET / HTTP/1.1\r\nX-Forwarded-For: 192.168.1.1, 172.16.0.2\r\nX-Forwarded-For: 192.168.1.9\r\n\r\n",
+        ));
+
+        let seen = requests.recv().unwrap();
+        assert!(seen.contains("X-Forwarded-For: 192.168.1.1, 172.16.0.2, 192.168.1.9, 10.0.0.7\n"));
+        assert_eq!(1, seen.matches("X-Forwarded-For").count());
+    }
+
+    #[test]
+    fn round_robin() {
+        let (first, _first_requests) = backend("first");
+        let (second, _second_requests) = backend("second");
+        let proxy = Proxy::new(&[&first, &second]);
+
+        let bodies: Vec<String> = (0..4)
+            .map(|_| body(proxy.forward(&request("GET / HTTP/1.1\r\n\r\n"))))
+            .collect();
+
+        assert!(bodies[0].contains("first"));
+        assert!(bodies[1].contains("second"));
+        assert!(bodies[2].contains("first"));
+        assert!(bodies[3].contains("second"));
+    }
+
+    #[test]
+    fn skips_failed_upstreams() {
+        let (dead, _listener) = dead_backend();
+        let (alive, requests) = backend("alive");
+        let proxy = Proxy::new(&[&dead, &alive]).timeout(Duration::from_secs(1));
+
+        for _ in 0..4 {
+            let response = proxy.forward(&request("GET / HTTP/1.1\r\n\r\n"));
+            assert!(body(response).contains("alive"));
+        }
+        assert_eq!(4, requests.try_iter().count());
+
+        // The dead upstream was only tried once before being taken out of rotation.
+        assert!(!proxy.upstreams[0].is_up(Instant::now()));
+    }
+
+    #[test]
+    fn bad_gateway_when_everything_is_down() {
+        let (dead, _listener) = dead_backend();
+        let proxy = Proxy::new(&[&dead]).timeout(Duration::from_secs(1));
+        let response = proxy.forward(&request("GET / HTTP/1.1\r\n\r\n"));
+        assert_eq!(502, response.status);
+    }
+}
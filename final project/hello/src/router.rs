@@ -0,0 +1,302 @@
+use std::cell::RefCell;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use crate::cgi::Cgi;
+use crate::files;
+use crate::http::{Connection, Request, Response, Stream};
+use crate::middleware::{Middleware, Next};
+use crate::proxy::Proxy;
+use crate::websocket::{self, WebSocket};
+
+type Handler = Box<dyn Fn(&Request) -> Response + Send + Sync>;
+type WebSocketHandler = Box<dyn Fn(&Request, WebSocket<Connection>) + Send + Sync>;
+
+struct Route {
+    method: String,
+    path: String,
+    handler: Handler,
+}
+
+/// Maps request paths to handlers.
+///
+/// A router is shared between the `ThreadPool` workers, and each connection
+/// is served start to finish on the worker that picked it up. For WebSocket
+/// routes that means the worker is busy until the socket closes.
+pub struct Router {
+    routes: Vec<Route>,
+    websockets: Vec<(String, WebSocketHandler)>,
+    mounts: Vec<(String, Handler)>,
+    root: Option<PathBuf>,
+    middleware: Vec<Box<dyn Middleware>>,
+    not_found: Handler,
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router {
+            routes: Vec::new(),
+            websockets: Vec::new(),
+            mounts: Vec::new(),
+            root: None,
+            middleware: Vec::new(),
+            not_found: Box::new(|_| Response::text(404, "Not Found")),
+        }
+    }
+
+    /// Handle `method` requests for `path`.
+    pub fn route<F>(mut self, method: &str, path: &str, handler: F) -> Router
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.routes.push(Route {
+            method: method.to_string(),
+            path: path.to_string(),
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// Handle `GET` requests for `path`.
+    pub fn get<F>(self, path: &str, handler: F) -> Router
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.route("GET", path, handler)
+    }
+
+    /// Accept WebSocket upgrades on `path` and hand the open socket to `handler`.
+    pub fn websocket<F>(mut self, path: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, WebSocket<Connection>) + Send + Sync + 'static,
+    {
+        self.websockets.push((path.to_string(), Box::new(handler)));
+        self
+    }
+
+    /// Handle every request, whatever the method, for `prefix` and the paths below it.
+    ///
+    /// `/api` covers `/api/items` but not `/apis`. Plain routes take
+    /// precedence, and the first matching mount wins.
+    pub fn mount<F>(mut self, prefix: &str, handler: F) -> Router
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.mounts.push((prefix.trim_end_matches('/').to_string(), Box::new(handler)));
+        self
+    }
+
+    /// Forward every request under `prefix` to `proxy`'s upstreams.
+    pub fn proxy(self, prefix: &str, proxy: Proxy) -> Router {
+        self.mount(prefix, move |request| proxy.forward(request))
+    }
+
+    /// Run the CGI script `cgi` for `path` and the paths below it, which the
+    /// script sees as `PATH_INFO`.
+    pub fn cgi(self, path: &str, cgi: Cgi) -> Router {
+        let script_name = path.trim_end_matches('/').to_string();
+        self.mount(path, move |request| cgi.run(request, &script_name))
+    }
+
+    /// Serve static files from `root` for `GET` and `HEAD` requests that no
+    /// route or mount matched.
+    pub fn files<P: AsRef<Path>>(mut self, root: P) -> Router {
+        self.root = Some(root.as_ref().to_path_buf());
+        self
+    }
+
+    /// Handle requests no route matched.
+    pub fn not_found<F>(mut self, handler: F) -> Router
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.not_found = Box::new(handler);
+        self
+    }
+
+    /// Run `middleware` around every request, WebSocket upgrades included.
+    ///
+    /// Middleware added first is the outermost: it sees the request first and
+    /// the response last.
+    pub fn wrap<M: Middleware + 'static>(mut self, middleware: M) -> Router {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Work out the response to a plain HTTP request, middleware included.
+    pub fn respond(&self, request: Request) -> Response {
+        Next::new(&self.middleware, &|request| self.dispatch(&request)).run(request)
+    }
+
+    fn dispatch(&self, request: &Request) -> Response {
+        let mut path_matched = false;
+
+        for route in &self.routes {
+            if route.path == request.path {
+                if route.method == request.method {
+                    return (route.handler)(request);
+                }
+                path_matched = true;
+            }
+        }
+
+        if path_matched {
+            return Response::text(405, "Method Not Allowed");
+        }
+
+        let mount = self.mounts.iter().find(|(prefix, _)| {
+            request
+                .path
+                .strip_prefix(prefix.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        });
+
+        if let Some((_, handler)) = mount {
+            return handler(request);
+        }
+
+        self.root
+            .as_ref()
+            .and_then(|root| files::serve(root, request))
+            .unwrap_or_else(|| (self.not_found)(request))
+    }
+
+    /// Read one request from `stream` and answer it.
+    pub fn handle<S: Stream + 'static>(&self, stream: S, peer_addr: Option<SocketAddr>) {
+        let mut connection = Connection::new(stream);
+
+        if let Some(request) = read_request(&mut connection, peer_addr) {
+            self.serve(connection, request);
+        }
+    }
+
+    /// Answer a request that was already read from `connection`.
+    pub fn serve(&self, mut connection: Connection, request: Request) {
+        let websocket = self.websockets.iter().find(|(path, _)| *path == request.path);
+
+        let (_, handler) = match websocket {
+            Some(websocket) => websocket,
+            None => {
+                let mut response = self.respond(request);
+                if let Err(e) = response.write_to(&mut connection) {
+                    eprintln!("Error while writing response: {}", e);
+                }
+                return;
+            }
+        };
+
+        // The middleware may change the request, so keep the version that
+        // made it through the chain for the WebSocket handler.
+        let upgraded = RefCell::new(None);
+        let endpoint = |request: Request| match websocket::handshake(&request) {
+            Ok(response) => {
+                *upgraded.borrow_mut() = Some(request);
+                response
+            }
+            Err(response) => response,
+        };
+        let mut response = Next::new(&self.middleware, &endpoint).run(request);
+
+        if let Err(e) = response.write_to(&mut connection) {
+            eprintln!("Error while writing response: {}", e);
+            return;
+        }
+
+        if let (101, Some(request)) = (response.status, upgraded.into_inner()) {
+            handler(&request, WebSocket::server(connection));
+        }
+    }
+}
+
+/// Read a request, answering `400 Bad Request` if that fails.
+pub(crate) fn read_request(connection: &mut Connection, peer_addr: Option<SocketAddr>) -> Option<Request> {
+    match Request::read_from(connection, peer_addr) {
+        Ok(request) => Some(request),
+        Err(e) => {
+            eprintln!("Error while reading request: {}", e);
+            let _ = Response::text(400, "Bad Request").write_to(connection);
+            None
+        }
+    }
+}
+
+impl Default for Router {
+    fn default() -> Router {
+        Router::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::websocket::Message;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+    use std::thread;
+
+    fn serve(router: Router) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = Arc::new(router);
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let router = Arc::clone(&router);
+                thread::spawn(move || router.handle(stream, None));
+            }
+        });
+
+        addr
+    }
+
+    #[test]
+    fn dispatches_by_method_and_path() {
+        let router = Router::new()
+            .get("/", |_| Response::text(200, "home"))
+            .not_found(|request| Response::text(404, format!("no {}", request.path)));
+
+        let request = |method: &str, path: &str| {
+            let raw = format!("{} {} HTTP/1.1\r\n\r\n", method, path);
+            Request::read_from(&mut raw.as_bytes(), None).unwrap()
+        };
+
+        assert_eq!(b"home", router.respond(request("GET", "/")).body.as_slice());
+        assert_eq!(405, router.respond(request("POST", "/")).status);
+        assert_eq!(b"no /x", router.respond(request("GET", "/x")).body.as_slice());
+    }
+
+    #[test]
+    fn websocket_echo() {
+        let addr = serve(Router::new().websocket("/echo", |_, mut socket| {
+            while let Ok(Message::Text(text)) = socket.recv() {
+                socket.send(&Message::Text(text)).unwrap();
+            }
+        }));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(
+                b"GET /echo HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                  Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            )
+            .unwrap();
+
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut status = String::new();
+        reader.read_line(&mut status).unwrap();
+        assert_eq!("HTTP/1.1 101 Switching Protocols\r\n", status);
+        let mut line = String::new();
+        while line != "\r\n" {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+        }
+
+        let mut client = WebSocket::client(stream);
+        client.send(&Message::Text("hello".to_string())).unwrap();
+        assert_eq!(Message::Text("hello".to_string()), client.recv().unwrap());
+        client.close(1000, "").unwrap();
+        assert_eq!(Message::Close(Some((1000, String::new()))), client.recv().unwrap());
+    }
+}
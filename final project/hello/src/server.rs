@@ -0,0 +1,254 @@
+use std::fs;
+use std::io;
+use std::mem;
+use std::net::{SocketAddr, TcpListener};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+use crate::http::{Connection, Response, Stream};
+use crate::router::{self, Router};
+use crate::ThreadPool;
+
+/// A site served by name, with its own document root and router.
+pub struct VirtualHost {
+    names: Vec<String>,
+    root: PathBuf,
+    router: Router,
+}
+
+/// Accepts connections on any number of sockets and hands each request to
+/// the virtual host its `Host` header names.
+///
+/// Requests for a name no host claims, or without a `Host` header, go to the
+/// first host that was added.
+pub struct Server {
+    listeners: Vec<Listener>,
+    hosts: Vec<VirtualHost>,
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl VirtualHost {
+    /// A host answering to `names`, where a name like `*.example.com` covers
+    /// every subdomain. Static files under `root` are served for requests
+    /// `router` has no route for.
+    pub fn new<P: AsRef<Path>>(names: &[&str], root: P, router: Router) -> VirtualHost {
+        VirtualHost {
+            names: names.iter().map(|name| name.to_ascii_lowercase()).collect(),
+            root: root.as_ref().to_path_buf(),
+            router: router.files(root),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        self.names.iter().any(|name| match name.strip_prefix("*.") {
+            Some(domain) => host
+                .strip_suffix(domain)
+                .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+            None => name == host,
+        })
+    }
+}
+
+impl Server {
+    pub fn new() -> Server {
+        Server { listeners: Vec::new(), hosts: Vec::new() }
+    }
+
+    /// Listen on another socket.
+    ///
+    /// `addr` is either a TCP address such as `127.0.0.1:7878` or `[::1]:7878`,
+    /// or `unix:` followed by the path of a Unix domain socket. A stale socket
+    /// file left behind by an earlier run is replaced.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error binding the socket failed with.
+    pub fn bind(mut self, addr: &str) -> io::Result<Server> {
+        let listener = match addr.strip_prefix("unix:") {
+            Some(path) => {
+                if fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+                    fs::remove_file(path)?;
+                }
+                Listener::Unix(UnixListener::bind(path)?)
+            }
+            None => Listener::Tcp(TcpListener::bind(addr)?),
+        };
+
+        self.listeners.push(listener);
+        Ok(self)
+    }
+
+    /// Serve another site. The first one added is the default.
+    pub fn host(mut self, host: VirtualHost) -> Server {
+        self.hosts.push(host);
+        self
+    }
+
+    /// The addresses of the TCP sockets, useful after binding to port 0.
+    pub fn tcp_addrs(&self) -> Vec<SocketAddr> {
+        self.listeners
+            .iter()
+            .filter_map(|listener| match listener {
+                Listener::Tcp(listener) => listener.local_addr().ok(),
+                Listener::Unix(_) => None,
+            })
+            .collect()
+    }
+
+    /// Accept connections on every socket until the process ends, serving
+    /// each one on a `pool` worker.
+    pub fn run(mut self, pool: ThreadPool) {
+        let listeners = mem::take(&mut self.listeners);
+        let server = Arc::new(self);
+        let pool = Arc::new(pool);
+
+        let accept_threads: Vec<_> = listeners
+            .into_iter()
+            .map(|listener| {
+                let server = Arc::clone(&server);
+                let pool = Arc::clone(&pool);
+                thread::spawn(move || listener.accept_loop(&server, &pool))
+            })
+            .collect();
+
+        for thread in accept_threads {
+            thread.join().unwrap();
+        }
+    }
+
+    /// Read one request from `stream` and let the matching host answer it.
+    pub fn handle<S: Stream + 'static>(&self, stream: S, peer_addr: Option<SocketAddr>) {
+        let mut connection = Connection::new(stream);
+
+        let request = match router::read_request(&mut connection, peer_addr) {
+            Some(request) => request,
+            None => return,
+        };
+
+        let host = request.header("Host").map(host_name).unwrap_or_default();
+        let vhost = self
+            .hosts
+            .iter()
+            .find(|vhost| vhost.matches(&host))
+            .or_else(|| self.hosts.first());
+
+        match vhost {
+            Some(vhost) => vhost.router.serve(connection, request),
+            None => {
+                let _ = Response::text(421, "Misdirected Request").write_to(&mut connection);
+            }
+        }
+    }
+}
+
+impl Default for Server {
+    fn default() -> Server {
+        Server::new()
+    }
+}
+
+impl Listener {
+    fn accept_loop(self, server: &Arc<Server>, pool: &ThreadPool) {
+        loop {
+            let accepted = match &self {
+                Listener::Tcp(listener) => listener.accept().map(|(stream, peer)| {
+                    let server = Arc::clone(server);
+                    pool.execute(move || server.handle(stream, Some(peer)));
+                }),
+                Listener::Unix(listener) => listener.accept().map(|(stream, _)| {
+                    let server = Arc::clone(server);
+                    pool.execute(move || server.handle(stream, None));
+                }),
+            };
+
+            if let Err(e) = accepted {
+                eprintln!("Error while accepting connection: {}", e);
+            }
+        }
+    }
+}
+
+/// The lowercased host name from a `Host` header, without the port.
+fn host_name(host: &str) -> String {
+    let host = host.trim();
+    let name = if host.starts_with('[') {
+        // An IPv6 literal such as [::1]:7878.
+        host.split_once(']').map_or(host, |(name, _)| &host[..name.len() + 1])
+    } else {
+        host.split(':').next().unwrap_or(host)
+    };
+
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::os::unix::net::UnixStream;
+
+    fn get<S: Read + Write>(mut stream: S, host: &str, path: &str) -> String {
+        write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, host).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn matches_host_names() {
+        assert_eq!("example.com", host_name("Example.COM:8080"));
+        assert_eq!("[::1]", host_name("[::1]:7878"));
+        assert_eq!("example.com", host_name("example.com."));
+
+        let vhost = VirtualHost::new(&["docs.internal", "*.example.com"], ".", Router::new());
+        assert!(vhost.matches("docs.internal"));
+        assert!(vhost.matches("a.example.com"));
+        assert!(!vhost.matches("example.com"));
+        assert!(!vhost.matches("badexample.com"));
+    }
+
+    #[test]
+    fn routes_by_host_over_tcp_and_unix_sockets() {
+        let dir = env::temp_dir().join(format!("hello-server-{}", std::process::id()));
+        fs::create_dir_all(dir.join("wiki")).unwrap();
+        fs::write(dir.join("wiki/index.html"), "wiki home").unwrap();
+        let socket = dir.join("hello.sock");
+
+        let server = Server::new()
+            .bind("127.0.0.1:0")
+            .unwrap()
+            .bind(&format!("unix:{}", socket.display()))
+            .unwrap()
+            .host(VirtualHost::new(&["app.internal"], &dir, Router::new().get("/", |_| Response::text(200, "app"))))
+            .host(VirtualHost::new(&["wiki.internal"], dir.join("wiki"), Router::new()));
+        let addr = server.tcp_addrs()[0];
+
+        thread::spawn(move || server.run(ThreadPool::new(2).ok().unwrap()));
+
+        let response = get(TcpStream::connect(addr).unwrap(), "app.internal", "/");
+        assert!(response.ends_with("\r\n\r\napp"));
+
+        let response = get(TcpStream::connect(addr).unwrap(), "wiki.internal:7878", "/");
+        assert!(response.ends_with("\r\n\r\nwiki home"));
+
+        // Unknown hosts get the first site.
+        let response = get(TcpStream::connect(addr).unwrap(), "other", "/");
+        assert!(response.ends_with("\r\n\r\napp"));
+
+        let response = get(UnixStream::connect(&socket).unwrap(), "wiki.internal", "/index.html");
+        assert!(response.ends_with("\r\n\r\nwiki home"));
+    }
+}
@@ -0,0 +1,616 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// How deep includes and layouts may nest before rendering gives up.
+const MAX_DEPTH: usize = 16;
+
+/// A value that can be interpolated into a template.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    Bool(bool),
+    List(Vec<Value>),
+    Map(HashMap<String, Value>),
+}
+
+/// The variables a template is rendered with.
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    values: HashMap<String, Value>,
+}
+
+/// A parsed template, ready to be rendered any number of times.
+#[derive(Debug)]
+pub struct Template {
+    extends: Option<String>,
+    nodes: Vec<Node>,
+}
+
+/// Loads templates from a directory and keeps the compiled versions around.
+///
+/// In development mode every lookup checks the file's modification time and
+/// recompiles the template when it changed on disk.
+pub struct Templates {
+    dir: PathBuf,
+    dev_mode: bool,
+    cache: Mutex<HashMap<String, Cached>>,
+}
+
+#[derive(Debug)]
+pub enum TemplateError {
+    Io(PathBuf, io::Error),
+    Syntax { name: String, message: String },
+    TooDeep(String),
+}
+
+struct Cached {
+    template: Arc<Template>,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Var { path: Vec<String>, escape: bool },
+    If { negate: bool, path: Vec<String>, then: Vec<Node>, otherwise: Vec<Node> },
+    For { var: String, path: Vec<String>, body: Vec<Node> },
+    Include(String),
+    Block { name: String, body: Arc<Vec<Node>> },
+}
+
+/// Block overrides collected from the templates that extend a layout.
+type Blocks = HashMap<String, Arc<Vec<Node>>>;
+
+type Scopes = Vec<HashMap<String, Value>>;
+
+enum Token<'a> {
+    Text(&'a str),
+    Expr(&'a str),
+    Tag(&'a str),
+}
+
+impl Value {
+    fn is_truthy(&self) -> bool {
+        match self {
+            Value::Str(s) => !s.is_empty(),
+            Value::Bool(b) => *b,
+            Value::List(items) => !items.is_empty(),
+            Value::Map(map) => !map.is_empty(),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(map) => map.get(key),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::List(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                Ok(())
+            }
+            Value::Map(_) => Ok(()),
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value {
+        Value::Str(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value {
+        Value::Str(s)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value {
+        Value::Bool(b)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(n: usize) -> Value {
+        Value::Str(n.to_string())
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(items: Vec<T>) -> Value {
+        Value::List(items.into_iter().map(Into::into).collect())
+    }
+}
+
+impl From<Context> for Value {
+    fn from(context: Context) -> Value {
+        Value::Map(context.values)
+    }
+}
+
+impl Context {
+    pub fn new() -> Context {
+        Context::default()
+    }
+
+    /// Set a variable, replacing any previous value with the same name.
+    pub fn insert<V: Into<Value>>(&mut self, name: &str, value: V) -> &mut Context {
+        self.values.insert(name.to_string(), value.into());
+        self
+    }
+}
+
+impl Template {
+    /// Compile template source. `name` is only used in error messages.
+    pub fn parse(name: &str, source: &str) -> Result<Template, TemplateError> {
+        let tokens = tokenize(name, source)?;
+        let mut tokens = tokens.into_iter();
+        let mut extends = None;
+        let (nodes, end) = parse_nodes(name, &mut tokens, &mut extends)?;
+
+        if let Some(tag) = end {
+            return Err(syntax(name, format!("unexpected `{{% {} %}}`", tag)));
+        }
+
+        Ok(Template { extends, nodes })
+    }
+}
+
+impl Templates {
+    /// Create a loader for templates stored in `dir`.
+    pub fn new<P: AsRef<Path>>(dir: P, dev_mode: bool) -> Templates {
+        Templates {
+            dir: dir.as_ref().to_path_buf(),
+            dev_mode,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Render the template stored in the file `name` with the given variables.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TemplateError` when the template, or anything it includes or
+    /// extends, cannot be read or does not parse.
+    pub fn render(&self, name: &str, context: &Context) -> Result<String, TemplateError> {
+        let mut scopes = vec![context.values.clone()];
+        let mut out = String::new();
+        self.render_template(name, &mut scopes, &Blocks::new(), &mut out, 0)?;
+        Ok(out)
+    }
+
+    fn get(&self, name: &str) -> Result<Arc<Template>, TemplateError> {
+        let path = self.dir.join(name);
+        let modified = if self.dev_mode {
+            fs::metadata(&path).and_then(|m| m.modified()).ok()
+        } else {
+            None
+        };
+
+        if let Some(cached) = self.cache.lock().unwrap().get(name) {
+            if !self.dev_mode || cached.modified == modified {
+                return Ok(Arc::clone(&cached.template));
+            }
+        }
+
+        let source = fs::read_to_string(&path).map_err(|e| TemplateError::Io(path, e))?;
+        let template = Arc::new(Template::parse(name, &source)?);
+
+        self.cache.lock().unwrap().insert(
+            name.to_string(),
+            Cached { template: Arc::clone(&template), modified },
+        );
+
+        Ok(template)
+    }
+
+
+    fn render_template(
+        &self,
+        name: &str,
+        scopes: &mut Scopes,
+        blocks: &Blocks,
+        out: &mut String,
+        depth: usize,
+    ) -> Result<(), TemplateError> {
+        if depth > MAX_DEPTH {
+            return Err(TemplateError::TooDeep(name.to_string()));
+        }
+
+        let template = self.get(name)?;
+
+        match &template.extends {
+            Some(layout) => {
+                let mut blocks = blocks.clone();
+                collect_blocks(&template.nodes, &mut blocks);
+                self.render_template(layout, scopes, &blocks, out, depth + 1)
+            }
+            None => self.render_nodes(&template.nodes, scopes, blocks, out, depth),
+        }
+    }
+
+    fn render_nodes(
+        &self,
+        nodes: &[Node],
+        scopes: &mut Scopes,
+        blocks: &Blocks,
+        out: &mut String,
+        depth: usize,
+    ) -> Result<(), TemplateError> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Var { path, escape } => {
+                    if let Some(value) = lookup(scopes, path) {
+                        let value = value.to_string();
+                        if *escape {
+                            escape_html(&value, out);
+                        } else {
+                            out.push_str(&value);
+                        }
+                    }
+                }
+                Node::If { negate, path, then, otherwise } => {
+                    let truthy = lookup(scopes, path).is_some_and(Value::is_truthy);
+                    let branch = if truthy != *negate { then } else { otherwise };
+                    self.render_nodes(branch, scopes, blocks, out, depth)?;
+                }
+                Node::For { var, path, body } => {
+                    let items = match lookup(scopes, path) {
+                        Some(Value::List(items)) => items.clone(),
+                        _ => continue,
+                    };
+                    let len = items.len();
+
+                    for (i, item) in items.into_iter().enumerate() {
+                        let mut meta = Context::new();
+                        meta.insert("index", i + 1)
+                            .insert("first", i == 0)
+                            .insert("last", i + 1 == len);
+
+                        let mut scope = HashMap::new();
+                        scope.insert(var.clone(), item);
+                        scope.insert("loop".to_string(), Value::from(meta));
+
+                        scopes.push(scope);
+                        let result = self.render_nodes(body, scopes, blocks, out, depth);
+                        scopes.pop();
+                        result?;
+                    }
+                }
+                Node::Include(name) => {
+                    self.render_template(name, scopes, &Blocks::new(), out, depth + 1)?;
+                }
+                Node::Block { name, body } => {
+                    let body = blocks.get(name).unwrap_or(body);
+                    self.render_nodes(body, scopes, blocks, out, depth)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Io(path, e) => write!(f, "Could not read template {}: {}", path.display(), e),
+            TemplateError::Syntax { name, message } => write!(f, "Syntax error in template {}: {}", name, message),
+            TemplateError::TooDeep(name) => write!(f, "Template {} nests includes or layouts too deeply", name),
+        }
+    }
+}
+
+impl Error for TemplateError {}
+
+/// Escape the characters that have a meaning in HTML text and attributes.
+pub fn escape_html(s: &str, out: &mut String) {
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn lookup<'a>(scopes: &'a Scopes, path: &[String]) -> Option<&'a Value> {
+    let (first, rest) = path.split_first()?;
+    let mut value = scopes.iter().rev().find_map(|scope| scope.get(first))?;
+
+    for key in rest {
+        value = value.get(key)?;
+    }
+
+    Some(value)
+}
+
+/// Add every block in `nodes` that isn't overridden yet.
+fn collect_blocks(nodes: &[Node], blocks: &mut Blocks) {
+    for node in nodes {
+        if let Node::Block { name, body } = node {
+            blocks.entry(name.clone()).or_insert_with(|| Arc::clone(body));
+            collect_blocks(body, blocks);
+        }
+    }
+}
+
+fn syntax(name: &str, message: String) -> TemplateError {
+    TemplateError::Syntax { name: name.to_string(), message }
+}
+
+fn tokenize<'a>(name: &str, source: &'a str) -> Result<Vec<Token<'a>>, TemplateError> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+
+    while let Some(start) = rest.find('{') {
+        let close = match &rest[start..] {
+            s if s.starts_with("{{") => "}}",
+            s if s.starts_with("{%") => "%}",
+            s if s.starts_with("{#") => "#}",
+            _ => {
+                tokens.push(Token::Text(&rest[..start + 1]));
+                rest = &rest[start + 1..];
+                continue;
+            }
+        };
+
+        if start > 0 {
+            tokens.push(Token::Text(&rest[..start]));
+        }
+
+        let inner = &rest[start + 2..];
+        let end = inner
+            .find(close)
+            .ok_or_else(|| syntax(name, format!("missing closing `{}`", close)))?;
+        let body = inner[..end].trim();
+
+        match close {
+            "}}" => tokens.push(Token::Expr(body)),
+            "%}" => tokens.push(Token::Tag(body)),
+            _ => {}
+        }
+
+        rest = &inner[end + 2..];
+    }
+
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest));
+    }
+
+    Ok(tokens)
+}
+
+/// Parse nodes until the end of input or a closing tag such as `endif`,
+/// which is returned so the caller can check it is the one it expected.
+fn parse_nodes<'a, I>(
+    name: &str,
+    tokens: &mut I,
+    extends: &mut Option<String>,
+) -> Result<(Vec<Node>, Option<&'a str>), TemplateError>
+where
+    I: Iterator<Item = Token<'a>>,
+{
+    let mut nodes = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        let tag = match token {
+            Token::Text(text) => {
+                match nodes.last_mut() {
+                    Some(Node::Text(prev)) => prev.push_str(text),
+                    _ => nodes.push(Node::Text(text.to_string())),
+                }
+                continue;
+            }
+            Token::Expr(expr) => {
+                nodes.push(parse_expr(name, expr)?);
+                continue;
+            }
+            Token::Tag(tag) => tag,
+        };
+
+        let words: Vec<&str> = tag.split_whitespace().collect();
+
+        match words.as_slice() {
+            ["else"] | ["endif"] | ["endfor"] | ["endblock"] => return Ok((nodes, Some(tag))),
+            ["if", rest @ ..] => {
+                let (negate, path) = match rest {
+                    ["not", path] => (true, path),
+                    [path] => (false, path),
+                    _ => return Err(syntax(name, format!("bad condition `{}`", tag))),
+                };
+                let path = parse_path(name, path)?;
+
+                let (then, end) = parse_nodes(name, tokens, extends)?;
+                let otherwise = match end {
+                    Some("else") => {
+                        let (otherwise, end) = parse_nodes(name, tokens, extends)?;
+                        expect_end(name, end, "endif")?;
+                        otherwise
+                    }
+                    end => {
+                        expect_end(name, end, "endif")?;
+                        Vec::new()
+                    }
+                };
+
+                nodes.push(Node::If { negate, path, then, otherwise });
+            }
+            ["for", var, "in", path] => {
+                let path = parse_path(name, path)?;
+                let (body, end) = parse_nodes(name, tokens, extends)?;
+                expect_end(name, end, "endfor")?;
+
+                nodes.push(Node::For { var: var.to_string(), path, body });
+            }
+            ["block", block] => {
+                let (body, end) = parse_nodes(name, tokens, extends)?;
+                expect_end(name, end, "endblock")?;
+
+                nodes.push(Node::Block { name: block.to_string(), body: Arc::new(body) });
+            }
+            ["include", file] => nodes.push(Node::Include(parse_string(name, file)?)),
+            ["extends", file] => *extends = Some(parse_string(name, file)?),
+            _ => return Err(syntax(name, format!("unknown tag `{{% {} %}}`", tag))),
+        }
+    }
+
+    Ok((nodes, None))
+}
+
+fn expect_end(name: &str, end: Option<&str>, expected: &str) -> Result<(), TemplateError> {
+    match end {
+        Some(end) if end == expected => Ok(()),
+        Some(end) => Err(syntax(name, format!("expected `{}`, found `{}`", expected, end))),
+        None => Err(syntax(name, format!("missing `{{% {} %}}`", expected))),
+    }
+}
+
+fn parse_expr(name: &str, expr: &str) -> Result<Node, TemplateError> {
+    let mut parts = expr.split('|').map(str::trim);
+    let path = parse_path(name, parts.next().unwrap_or_default())?;
+    let mut escape = true;
+
+    for filter in parts {
+        match filter {
+            "safe" => escape = false,
+            "escape" => escape = true,
+            _ => return Err(syntax(name, format!("unknown filter `{}`", filter))),
+        }
+    }
+
+    Ok(Node::Var { path, escape })
+}
+
+fn parse_path(name: &str, path: &str) -> Result<Vec<String>, TemplateError> {
+    let path: Vec<String> = path.split('.').map(str::to_string).collect();
+
+    if path.iter().any(|key| key.is_empty()) {
+        return Err(syntax(name, "empty variable name".to_string()));
+    }
+
+    Ok(path)
+}
+
+fn parse_string(name: &str, s: &str) -> Result<String, TemplateError> {
+    s.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .map(str::to_string)
+        .ok_or_else(|| syntax(name, format!("expected a quoted file name, found {}", s)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::thread;
+    use std::time::Duration;
+
+    fn template_dir(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("hello-templates-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn escapes_variables_by_default() {
+        let dir = template_dir("escape", &[("page.html", "<p>{{ path }}</p>{{ path | safe }}")]);
+        let templates = Templates::new(&dir, false);
+
+        let mut context = Context::new();
+        context.insert("path", "<script>");
+
+        assert_eq!(
+            "<p>&lt;script&gt;</p><script>",
+            templates.render("page.html", &context).unwrap()
+        );
+    }
+
+    #[test]
+    fn if_and_for_blocks() {
+        let source = "{% if items %}{% for item in items %}{{ loop.index }}:{{ item }}{% if not loop.last %},{% endif %}{% endfor %}{% else %}empty{% endif %}";
+        let dir = template_dir("blocks", &[("list.html", source)]);
+        let templates = Templates::new(&dir, false);
+
+        let mut context = Context::new();
+        context.insert("items", vec!["a", "b"]);
+        assert_eq!("1:a,2:b", templates.render("list.html", &context).unwrap());
+
+        context.insert("items", Vec::<String>::new());
+        assert_eq!("empty", templates.render("list.html", &context).unwrap());
+    }
+
+    #[test]
+    fn layouts_and_includes() {
+        let dir = template_dir(
+            "layouts",
+            &[
+                ("layout.html", "<title>{% block title %}Default{% endblock %}</title>{% include \"nav.html\" %}{% block body %}{% endblock %}"),
+                ("nav.html", "<nav>{{ user.name }}</nav>"),
+                ("page.html", "{% extends \"layout.html\" %}{% block body %}Hi{% endblock %}"),
+            ],
+        );
+        let templates = Templates::new(&dir, false);
+
+        let mut user = Context::new();
+        user.insert("name", "Ferris");
+        let mut context = Context::new();
+        context.insert("user", user);
+
+        assert_eq!(
+            "<title>Default</title><nav>Ferris</nav>Hi",
+            templates.render("page.html", &context).unwrap()
+        );
+    }
+
+    #[test]
+    fn reports_unclosed_blocks() {
+        let err = Template::parse("bad.html", "{% if x %}never closed").unwrap_err();
+        assert!(err.to_string().contains("endif"));
+    }
+
+    #[test]
+    fn reloads_changed_files_in_dev_mode() {
+        let dir = template_dir("reload", &[("page.html", "old")]);
+        let cached = Templates::new(&dir, false);
+        let dev = Templates::new(&dir, true);
+        let context = Context::new();
+
+        assert_eq!("old", cached.render("page.html", &context).unwrap());
+        assert_eq!("old", dev.render("page.html", &context).unwrap());
+
+        // Make sure the new modification time differs from the old one.
+        thread::sleep(Duration::from_millis(20));
+        fs::write(dir.join("page.html"), "new").unwrap();
+
+        assert_eq!("old", cached.render("page.html", &context).unwrap());
+        assert_eq!("new", dev.render("page.html", &context).unwrap());
+    }
+}
@@ -0,0 +1,590 @@
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Write};
+
+use crate::base64;
+use crate::http::{Request, Response};
+
+/// The GUID RFC 6455 appends to the client's key.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Largest message we assemble from fragments before giving up.
+const MAX_MESSAGE: usize = 16 * 1024 * 1024;
+
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xa;
+
+/// A complete WebSocket message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    /// A close frame, with its status code and reason if it had one.
+    Close(Option<(u16, String)>),
+}
+
+/// One end of a WebSocket connection.
+///
+/// `recv` puts fragmented messages back together, answers pings and replies
+/// to the peer's close frame, so callers only see whole messages.
+pub struct WebSocket<S> {
+    stream: S,
+    /// Servers must get masked frames and must not mask their own, clients the other way round.
+    client: bool,
+    max_frame_size: Option<usize>,
+    fragments: Option<(u8, Vec<u8>)>,
+    close_sent: bool,
+    close_received: bool,
+}
+
+#[derive(Debug)]
+pub enum WebSocketError {
+    Io(io::Error),
+    Protocol(&'static str),
+    /// The connection was closed and can't be used anymore.
+    Closed,
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+/// Whether the client asked to upgrade the connection to a WebSocket.
+pub fn is_upgrade(request: &Request) -> bool {
+    request.header_has_token("Upgrade", "websocket") && request.header_has_token("Connection", "upgrade")
+}
+
+/// Check an upgrade request and build the `101 Switching Protocols` answer.
+///
+/// # Errors
+///
+/// Returns the error response to send instead when the request isn't a valid
+/// opening handshake.
+pub fn handshake(request: &Request) -> Result<Response, Response> {
+    if request.method != "GET" || request.version != "HTTP/1.1" || !is_upgrade(request) {
+        return Err(Response::text(400, "Expected a WebSocket upgrade request"));
+    }
+
+    if request.header("Sec-WebSocket-Version") != Some("13") {
+        return Err(Response::text(426, "Unsupported WebSocket version")
+            .with_header("Sec-WebSocket-Version", "13"));
+    }
+
+    let key = match request.header("Sec-WebSocket-Key") {
+        Some(key) if base64::decode(key).is_some_and(|k| k.len() == 16) => key,
+        _ => return Err(Response::text(400, "Missing or invalid Sec-WebSocket-Key")),
+    };
+
+    Ok(Response::new(101)
+        .with_header("Upgrade", "websocket")
+        .with_header("Connection", "Upgrade")
+        .with_header("Sec-WebSocket-Accept", &accept_key(key)))
+}
+
+/// The `Sec-WebSocket-Accept` value for a client's `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+    base64::encode(&sha1(format!("{}{}", key, GUID).as_bytes()))
+}
+
+impl<S: Read + Write> WebSocket<S> {
+    /// Wrap the server side of a connection that finished the handshake.
+    pub fn server(stream: S) -> WebSocket<S> {
+        WebSocket::new(stream, false)
+    }
+
+    /// Wrap the client side of a connection that finished the handshake.
+    pub fn client(stream: S) -> WebSocket<S> {
+        WebSocket::new(stream, true)
+    }
+
+    fn new(stream: S, client: bool) -> WebSocket<S> {
+        WebSocket {
+            stream,
+            client,
+            max_frame_size: None,
+            fragments: None,
+            close_sent: false,
+            close_received: false,
+        }
+    }
+
+    /// Split outgoing text and binary messages into frames of at most `size` bytes.
+    pub fn set_max_frame_size(&mut self, size: Option<usize>) {
+        self.max_frame_size = size.map(|size| size.max(1));
+    }
+
+    /// Wait for the next message.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WebSocketError::Closed` once a close frame was received, and
+    /// `WebSocketError::Protocol` when the peer breaks RFC 6455, in which case
+    /// the connection is closed with status 1002 or 1007.
+    pub fn recv(&mut self) -> Result<Message, WebSocketError> {
+        if self.close_received {
+            return Err(WebSocketError::Closed);
+        }
+
+        match self.recv_message() {
+            Err(WebSocketError::Protocol(message)) => {
+                let code = if message.starts_with("invalid UTF-8") { 1007 } else { 1002 };
+                let _ = self.close(code, "");
+                Err(WebSocketError::Protocol(message))
+            }
+            result => result,
+        }
+    }
+
+    /// Send a message, split into several frames if a max frame size is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WebSocketError::Closed` if we already sent a close frame.
+    pub fn send(&mut self, message: &Message) -> Result<(), WebSocketError> {
+        if self.close_sent {
+            return Err(WebSocketError::Closed);
+        }
+
+        match message {
+            Message::Text(text) => self.send_data(TEXT, text.as_bytes()),
+            Message::Binary(data) => self.send_data(BINARY, data),
+            Message::Ping(data) => self.send_control(PING, data),
+            Message::Pong(data) => self.send_control(PONG, data),
+            Message::Close(None) => {
+                self.close_sent = true;
+                self.write_frame(true, CLOSE, &[])
+            }
+            Message::Close(Some((code, reason))) => self.close(*code, reason),
+        }
+    }
+
+    /// Start the closing handshake with a status code and reason.
+    pub fn close(&mut self, code: u16, reason: &str) -> Result<(), WebSocketError> {
+        if self.close_sent {
+            return Ok(());
+        }
+
+        // A control frame carries at most 125 bytes, two of them the code,
+        // and the reason has to stay valid UTF-8, so it's cut between chars.
+        let mut end = reason.len().min(123);
+        while !reason.is_char_boundary(end) {
+            end -= 1;
+        }
+        let mut payload = code.to_be_bytes().to_vec();
+        payload.extend_from_slice(&reason.as_bytes()[..end]);
+
+        self.close_sent = true;
+        self.write_frame(true, CLOSE, &payload)
+    }
+
+    /// Give back the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    fn recv_message(&mut self) -> Result<Message, WebSocketError> {
+        loop {
+            let frame = self.read_frame()?;
+
+            match frame.opcode {
+                PING => {
+                    if !self.close_sent {
+                        self.write_frame(true, PONG, &frame.payload)?;
+                    }
+                    return Ok(Message::Ping(frame.payload));
+                }
+                PONG => return Ok(Message::Pong(frame.payload)),
+                CLOSE => {
+                    self.close_received = true;
+                    let status = parse_close(&frame.payload)?;
+                    if !self.close_sent {
+                        self.close_sent = true;
+                        self.write_frame(true, CLOSE, &frame.payload[..frame.payload.len().min(2)])?;
+                    }
+                    return Ok(Message::Close(status));
+                }
+                TEXT | BINARY => {
+                    if self.fragments.is_some() {
+                        return Err(WebSocketError::Protocol("new message before the last one was finished"));
+                    }
+                    if frame.fin {
+                        return data_message(frame.opcode, frame.payload);
+                    }
+                    self.fragments = Some((frame.opcode, frame.payload));
+                }
+                CONTINUATION => {
+                    let (_, buffer) = self
+                        .fragments
+                        .as_mut()
+                        .ok_or(WebSocketError::Protocol("continuation frame without a message"))?;
+                    if buffer.len() + frame.payload.len() > MAX_MESSAGE {
+                        return Err(WebSocketError::Protocol("message too large"));
+                    }
+                    buffer.extend_from_slice(&frame.payload);
+
+                    if frame.fin {
+                        let (opcode, payload) = self.fragments.take().unwrap();
+                        return data_message(opcode, payload);
+                    }
+                }
+                _ => return Err(WebSocketError::Protocol("unknown opcode")),
+            }
+        }
+    }
+
+    fn send_data(&mut self, opcode: u8, data: &[u8]) -> Result<(), WebSocketError> {
+        let size = self.max_frame_size.unwrap_or(usize::MAX);
+        if data.len() <= size {
+            return self.write_frame(true, opcode, data);
+        }
+
+        let mut chunks = data.chunks(size).peekable();
+        let mut opcode = opcode;
+        while let Some(chunk) = chunks.next() {
+            self.write_frame(chunks.peek().is_none(), opcode, chunk)?;
+            opcode = CONTINUATION;
+        }
+
+        Ok(())
+    }
+
+    fn send_control(&mut self, opcode: u8, data: &[u8]) -> Result<(), WebSocketError> {
+        if data.len() > 125 {
+            return Err(WebSocketError::Protocol("control frame payload longer than 125 bytes"));
+        }
+        self.write_frame(true, opcode, data)
+    }
+
+    fn read_frame(&mut self) -> Result<Frame, WebSocketError> {
+        let mut head = [0; 2];
+        self.stream.read_exact(&mut head)?;
+
+        let fin = head[0] & 0x80 != 0;
+        let opcode = head[0] & 0x0f;
+        let masked = head[1] & 0x80 != 0;
+
+        if head[0] & 0x70 != 0 {
+            return Err(WebSocketError::Protocol("reserved bits set without an extension"));
+        }
+        if masked == self.client {
+            return Err(WebSocketError::Protocol(if self.client {
+                "server sent a masked frame"
+            } else {
+                "client sent an unmasked frame"
+            }));
+        }
+
+        let len = match head[1] & 0x7f {
+            126 => {
+                let mut len = [0; 2];
+                self.stream.read_exact(&mut len)?;
+                u16::from_be_bytes(len) as u64
+            }
+            127 => {
+                let mut len = [0; 8];
+                self.stream.read_exact(&mut len)?;
+                u64::from_be_bytes(len)
+            }
+            len => len as u64,
+        };
+
+        if opcode & 0x8 != 0 && (!fin || len > 125) {
+            return Err(WebSocketError::Protocol("fragmented or oversized control frame"));
+        }
+        if len > MAX_MESSAGE as u64 {
+            return Err(WebSocketError::Protocol("message too large"));
+        }
+
+        let mut mask = [0; 4];
+        if masked {
+            self.stream.read_exact(&mut mask)?;
+        }
+
+        let mut payload = vec![0; len as usize];
+        self.stream.read_exact(&mut payload)?;
+        if masked {
+            apply_mask(&mut payload, mask);
+        }
+
+        Ok(Frame { fin, opcode, payload })
+    }
+
+    fn write_frame(&mut self, fin: bool, opcode: u8, payload: &[u8]) -> Result<(), WebSocketError> {
+        let mut frame = Vec::with_capacity(payload.len() + 14);
+        frame.push(if fin { 0x80 } else { 0 } | opcode);
+
+        let mask_bit = if self.client { 0x80 } else { 0 };
+        match payload.len() {
+            len if len < 126 => frame.push(mask_bit | len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(mask_bit | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(mask_bit | 127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+
+        if self.client {
+            let mask = mask_key();
+            frame.extend_from_slice(&mask);
+            let start = frame.len();
+            frame.extend_from_slice(payload);
+            apply_mask(&mut frame[start..], mask);
+        } else {
+            frame.extend_from_slice(payload);
+        }
+
+        self.stream.write_all(&frame)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+}
+
+impl Display for WebSocketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebSocketError::Io(e) => write!(f, "WebSocket I/O error: {}", e),
+            WebSocketError::Protocol(message) => write!(f, "WebSocket protocol error: {}", message),
+            WebSocketError::Closed => write!(f, "WebSocket connection is closed"),
+        }
+    }
+}
+
+impl Error for WebSocketError {}
+
+impl From<io::Error> for WebSocketError {
+    fn from(e: io::Error) -> WebSocketError {
+        WebSocketError::Io(e)
+    }
+}
+
+fn data_message(opcode: u8, payload: Vec<u8>) -> Result<Message, WebSocketError> {
+    if opcode == BINARY {
+        return Ok(Message::Binary(payload));
+    }
+    String::from_utf8(payload)
+        .map(Message::Text)
+        .map_err(|_| WebSocketError::Protocol("invalid UTF-8 in text message"))
+}
+
+fn parse_close(payload: &[u8]) -> Result<Option<(u16, String)>, WebSocketError> {
+    match payload {
+        [] => Ok(None),
+        [_] => Err(WebSocketError::Protocol("close frame with a one byte payload")),
+        [hi, lo, reason @ ..] => {
+            let reason = String::from_utf8(reason.to_vec())
+                .map_err(|_| WebSocketError::Protocol("invalid UTF-8 in close reason"))?;
+            Ok(Some((u16::from_be_bytes([*hi, *lo]), reason)))
+        }
+    }
+}
+
+fn apply_mask(data: &mut [u8], mask: [u8; 4]) {
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+/// Masking only has to be unpredictable to the peer's intermediaries, so the
+/// randomly seeded std hasher is good enough.
+fn mask_key() -> [u8; 4] {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u8(0);
+    (hasher.finish() as u32).to_be_bytes()
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes([block[4 * i], block[4 * i + 1], block[4 * i + 2], block[4 * i + 3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+
+    let mut out = [0; 20];
+    for (i, word) in h.iter().enumerate() {
+        out[4 * i..4 * i + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Reads from a fixed input and records everything written.
+    struct Pipe {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn pipe(input: Vec<u8>) -> Pipe {
+        Pipe { input: Cursor::new(input), output: Vec::new() }
+    }
+
+    /// What a client would send for `messages`, masked and fragmented.
+    fn client_frames(messages: &[Message], max_frame_size: usize) -> Vec<u8> {
+        let mut client = WebSocket::client(pipe(Vec::new()));
+        client.set_max_frame_size(Some(max_frame_size));
+        for message in messages {
+            client.send(message).unwrap();
+        }
+        client.into_inner().output
+    }
+
+    #[test]
+    fn accept_key_from_rfc() {
+        assert_eq!("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=", accept_key("dGhlIHNhbXBsZSBub25jZQ=="));
+    }
+
+    #[test]
+    fn handshake_checks_version() {
+        let mut request = Request {
+            method: "GET".to_string(),
+            path: "/ws".to_string(),
+            query: String::new(),
+            version: "HTTP/1.1".to_string(),
+            headers: vec![
+                ("Upgrade".to_string(), "websocket".to_string()),
+                ("Connection".to_string(), "keep-alive, Upgrade".to_string()),
+                ("Sec-WebSocket-Key".to_string(), "dGhlIHNhbXBsZSBub25jZQ==".to_string()),
+                ("Sec-WebSocket-Version".to_string(), "8".to_string()),
+            ],
+            body: Vec::new(),
+            peer_addr: None,
+        };
+        assert_eq!(426, handshake(&request).unwrap_err().status);
+
+        request.headers[3].1 = "13".to_string();
+        let response = handshake(&request).unwrap();
+        assert_eq!(101, response.status);
+        assert_eq!(Some("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="), response.header("Sec-WebSocket-Accept"));
+    }
+
+    #[test]
+    fn reassembles_fragments_around_pings() {
+        let mut input = client_frames(&[Message::Text("Hello, ".to_string())], 3);
+        // Move the last fragment of the text after a ping to interleave them.
+        let last = input.split_off(input.len() - 7);
+        input.extend(client_frames(&[Message::Ping(b"hi".to_vec())], 125));
+        input.extend(last);
+        input.extend(client_frames(&[Message::Binary(vec![1, 2, 3])], 125));
+
+        let mut server = WebSocket::server(pipe(input));
+        assert_eq!(Message::Ping(b"hi".to_vec()), server.recv().unwrap());
+        assert_eq!(Message::Text("Hello, ".to_string()), server.recv().unwrap());
+        assert_eq!(Message::Binary(vec![1, 2, 3]), server.recv().unwrap());
+
+        // The ping was answered with an unmasked pong.
+        assert_eq!(vec![0x8a, 2, b'h', b'i'], server.into_inner().output);
+    }
+
+    #[test]
+    fn answers_close() {
+        let input = client_frames(&[Message::Close(Some((1000, "bye".to_string())))], 125);
+        let mut server = WebSocket::server(pipe(input));
+
+        assert_eq!(Message::Close(Some((1000, "bye".to_string()))), server.recv().unwrap());
+        assert!(matches!(server.recv(), Err(WebSocketError::Closed)));
+        assert!(matches!(server.send(&Message::Text("late".to_string())), Err(WebSocketError::Closed)));
+        assert_eq!(vec![0x88, 2, 0x03, 0xe8], server.into_inner().output);
+    }
+
+    #[test]
+    fn cuts_long_close_reasons_between_chars() {
+        let mut client = WebSocket::client(pipe(Vec::new()));
+        client.close(1001, &"é".repeat(70)).unwrap();
+        let mut server = WebSocket::server(pipe(client.into_inner().output));
+
+        assert_eq!(Message::Close(Some((1001, "é".repeat(61)))), server.recv().unwrap());
+    }
+
+    #[test]
+    fn rejects_invalid_close_reasons() {
+        let mut client = WebSocket::client(pipe(Vec::new()));
+        client.write_frame(true, CLOSE, &[0x03, 0xe8, 0xc3]).unwrap();
+        let mut server = WebSocket::server(pipe(client.into_inner().output));
+
+        assert!(matches!(server.recv(), Err(WebSocketError::Protocol("invalid UTF-8 in close reason"))));
+        // The connection is closed with 1007 (inconsistent data).
+        assert_eq!(vec![0x88, 2, 0x03, 0xef], server.into_inner().output);
+    }
+
+    #[test]
+    fn rejects_unmasked_client_frames() {
+        let mut server = WebSocket::server(pipe(vec![0x81, 1, b'x']));
+
+        assert!(matches!(server.recv(), Err(WebSocketError::Protocol(_))));
+        // The connection is closed with 1002 (protocol error).
+        assert_eq!(vec![0x88, 2, 0x03, 0xea], server.into_inner().output);
+    }
+
+    #[test]
+    fn long_payload_lengths() {
+        let data = vec![7; 70_000];
+        let input = client_frames(&[Message::Binary(data.clone())], usize::MAX);
+        let mut server = WebSocket::server(pipe(input));
+
+        assert_eq!(Message::Binary(data), server.recv().unwrap());
+    }
+}
@@ -0,0 +1,102 @@
+use std::error::Error;
+use std::fmt;
+
+/// One command-line option, which is either set or not.
+pub struct Opt {
+    pub short: Option<char>,
+    pub long: &'static str,
+    pub help: &'static str,
+}
+
+/// Every option improved_minigrep understands, in the order `--help` lists them.
+pub const OPTIONS: &[Opt] = &[
+    Opt { short: Some('i'), long: "ignore-case", help: "ignore case distinctions" },
+    Opt { short: None, long: "no-ignore-case", help: "don't ignore case, even if CASE_INSENSITIVE is set" },
+    Opt { short: Some('v'), long: "invert-match", help: "select non-matching lines" },
+    Opt { short: Some('n'), long: "line-number", help: "print line numbers" },
+    Opt { short: None, long: "help", help: "display this help and exit" },
+    Opt { short: Some('V'), long: "version", help: "display version information and exit" },
+];
+
+/// A parsed argument: an option, by its long name, or a positional one.
+#[derive(Debug, PartialEq)]
+pub enum Arg {
+    Opt(&'static str),
+    Positional(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ArgsError {
+    UnknownOption(String),
+    UnexpectedValue(String),
+    MissingQuery,
+    MissingFilename,
+}
+
+/// Split `args`, which shouldn't include the program name, into options and
+/// positional arguments. Short flags can be combined, as in `-inv`, and
+/// everything after `--` is positional. A lone `-` is positional too.
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Vec<Arg>, ArgsError> {
+    let mut args = args.into_iter();
+    let mut parsed = Vec::new();
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            parsed.extend(args.by_ref().map(Arg::Positional));
+        } else if let Some(long) = arg.strip_prefix("--") {
+            // None of the options takes a value.
+            if let Some((name, _)) = long.split_once('=') {
+                return Err(ArgsError::UnexpectedValue(format!("--{}", name)));
+            }
+            let opt = OPTIONS
+                .iter()
+                .find(|opt| opt.long == long)
+                .ok_or_else(|| ArgsError::UnknownOption(format!("--{}", long)))?;
+            parsed.push(Arg::Opt(opt.long));
+        } else if arg.len() > 1 && arg.starts_with('-') {
+            for c in arg[1..].chars() {
+                let opt = OPTIONS
+                    .iter()
+                    .find(|opt| opt.short == Some(c))
+                    .ok_or_else(|| ArgsError::UnknownOption(format!("-{}", c)))?;
+                parsed.push(Arg::Opt(opt.long));
+            }
+        } else {
+            parsed.push(Arg::Positional(arg));
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// The text `--help` prints.
+pub fn usage() -> String {
+    let mut usage = String::from(
+        "Usage: improved_minigrep [OPTION]... QUERY FILENAME\n\
+         Print the lines of FILENAME that contain QUERY.\n\nOptions:\n",
+    );
+
+    for opt in OPTIONS {
+        let short = opt.short.map_or("    ".to_string(), |c| format!("-{}, ", c));
+        usage.push_str(&format!("  {}--{:<16} {}\n", short, opt.long, opt.help));
+    }
+
+    usage.push_str(
+        "\nCase is ignored when the CASE_INSENSITIVE environment variable is set,\n\
+         unless -i or --no-ignore-case says otherwise: the flags take precedence.\n",
+    );
+    usage
+}
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArgsError::UnknownOption(opt) => write!(f, "unknown option {}", opt),
+            ArgsError::UnexpectedValue(opt) => write!(f, "option {} doesn't take a value", opt),
+            ArgsError::MissingQuery => write!(f, "Didn't get a query string!"),
+            ArgsError::MissingFilename => write!(f, "Didn't get a filename!"),
+        }
+    }
+}
+
+impl Error for ArgsError {}
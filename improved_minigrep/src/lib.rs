@@ -23,18 +23,38 @@ use std::fs;
 use std::error::Error;
 use std::env;
 
+use args::Arg;
+
+mod args;
+
+pub use args::{usage, ArgsError};
+
 #[derive(Debug)]
 pub struct Config {
     query: String,
     filename: String,
     case_sensitive: bool,
+    invert: bool,
+    line_number: bool,
+    pub help: bool,
+    pub version: bool,
 }
 
 impl Config {
-    //This still won’t compile because we need to update the function body.
-    pub fn new(mut args: env::Args) -> Result<Config, &'static str> {
+    //Config::new now takes ownership of the iterator env::args returns, and hands it on to from_args
+    //along with the real environment.
+    pub fn new(args: impl Iterator<Item = String>) -> Result<Config, ArgsError> {
+        Config::from_args(args, |name| env::var(name).ok())
+    }
+
+    /// Like `new`, but with environment variables looked up through `env`,
+    /// so what they do can be tried without touching the real ones.
+    ///
+    /// Options can come before, between or after the query and filename.
+    /// A flag beats the `CASE_INSENSITIVE` variable, whichever way it points.
+    pub fn from_args(mut args: impl Iterator<Item = String>, env: impl Fn(&str) -> Option<String>) -> Result<Config, ArgsError> {
     //The standard library documentation for the env::args function shows that the type of the iterator it returns is std::env::Args. 
-    //We’ve updated the signature of the Config::new function so the parameter args has the type std::env::Args instead of &[String].
+    //Rather than &[String], the parameter args takes any iterator of Strings, std::env::Args included, so tests can hand in their own.
     //Because we’re taking ownership of args and we’ll be mutating args by iterating over it, 
     //we can add the mut keyword into the specification of the args parameter to make it mutable.
 
@@ -44,21 +64,53 @@ impl Config {
         //Remember that the first value in the return value of env::args is the name of the program. 
         //We want to ignore that and get to the next value, so first we call next and do nothing with the return value.
 
+        //The rest go through the option parser, which sets flags aside and hands back the other arguments in order.
+        let mut config = Config {
+            query: String::new(),
+            filename: String::new(),
+            case_sensitive: true,
+            invert: false,
+            line_number: false,
+            help: false,
+            version: false,
+        };
+        let mut ignore_case = None;
+        let mut positional = Vec::new();
+        for arg in args::parse(args)? {
+            match arg {
+                Arg::Positional(arg) => positional.push(arg),
+                Arg::Opt("ignore-case") => ignore_case = Some(true),
+                Arg::Opt("no-ignore-case") => ignore_case = Some(false),
+                Arg::Opt("invert-match") => config.invert = true,
+                Arg::Opt("line-number") => config.line_number = true,
+                Arg::Opt("help") => config.help = true,
+                Arg::Opt("version") => config.version = true,
+                Arg::Opt(name) => unreachable!("option --{} isn't handled", name),
+            }
+        }
+        if config.help || config.version {
+            return Ok(config);
+        }
+        let mut positional = positional.into_iter();
+
         //Second, we call next to get the value we want to put in the query field of Config. 
         //If next returns a Some, we use a match to extract the value. 
         //If it returns None, it means not enough arguments were given and we return early with an Err value.
-        let query = match args.next() {
+        config.query = match positional.next() {
             Some(arg) => arg,
-            None => return Err("Didn't get a query string!"),  
+            None => return Err(ArgsError::MissingQuery),  
         };
         //We do the same thing for the filename value.
-        let filename = match args.next() {
+        config.filename = match positional.next() {
             Some(arg) => arg,
-            None => return Err("Didn't get a filename!"),
+            None => return Err(ArgsError::MissingFilename),
+        };
+        config.case_sensitive = match ignore_case {
+            Some(ignore_case) => !ignore_case,
+            None => env("CASE_INSENSITIVE").is_none(),
         };
-        let case_sensitive = env::var("CASE_INSENSITIVE").is_err();
 
-        Ok(Config { query, filename, case_sensitive })
+        Ok(config)
     }
 }
 
@@ -66,13 +118,17 @@ pub fn run(config: &Config) -> Result<(), Box<dyn Error>> {
     let contents = fs::read_to_string(&config.filename)?;
 
     let results = if config.case_sensitive {
-        contains_query_sensitive(&config.query, &contents)
+        contains_query_sensitive(&config.query, &contents, config.invert)
     }else {
-        contains_query_insensitive(&config.query, &contents)
+        contains_query_insensitive(&config.query, &contents, config.invert)
     };
 
-    for line in results {
-        println!("{line}");
+    for (number, line) in results {
+        if config.line_number {
+            println!("{}:{line}", number + 1);
+        } else {
+            println!("{line}");
+        }
     }
 
     Ok(())
@@ -84,20 +140,83 @@ pub fn run(config: &Config) -> Result<(), Box<dyn Error>> {
 //We can write this code in a more concise way using iterator adaptor methods. Doing so also lets us avoid having a mutable intermediate results vector. 
 //The functional programming style prefers to minimize the amount of mutable state to make code clearer. 
 //Removing the mutable state might enable a future enhancement to make searching happen in parallel, because we wouldn’t have to manage concurrent access to the results vector.
-fn contains_query_sensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+//Each line comes with its index, for -n, and with invert set the other lines are kept instead, for -v.
+fn contains_query_sensitive<'a>(query: &str, contents: &'a str, invert: bool) -> Vec<(usize, &'a str)> {
     contents
         .lines()
+        .enumerate()
         //This code uses the filter adaptor to keep only the lines that line.contains(query) returns true for.
-        .filter(|line| line.contains(query))
+        .filter(|(_, line)| line.contains(query) != invert)
         //We then collect the matching lines into another vector with collect.
         .collect()
 }
 
-fn contains_query_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+fn contains_query_insensitive<'a>(query: &str, contents: &'a str, invert: bool) -> Vec<(usize, &'a str)> {
+    let query = query.to_lowercase();
     contents
         .lines()
-        .filter(|line| line.to_lowercase().contains(query.to_lowercase().as_str()))
+        .enumerate()
+        .filter(|(_, line)| line.to_lowercase().contains(query.as_str()) != invert)
         .collect()
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(args: &[&str], case_insensitive: bool) -> Result<Config, ArgsError> {
+        let args = ["improved_minigrep"].iter().chain(args).map(|arg| arg.to_string());
+        Config::from_args(args, |name| (case_insensitive && name == "CASE_INSENSITIVE").then(|| "1".to_string()))
+    }
+
+    #[test]
+    fn combined_short_flags() {
+        let config = config(&["-inv", "to", "poem.txt"], false).unwrap();
+        assert!(!config.case_sensitive);
+        assert!(config.invert);
+        assert!(config.line_number);
+        assert_eq!(("to", "poem.txt"), (config.query.as_str(), config.filename.as_str()));
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let config = config(&["--", "-v", "poem.txt"], false).unwrap();
+        assert_eq!("-v", config.query);
+        assert!(!config.invert);
+    }
 
+    #[test]
+    fn options_anywhere() {
+        for args in [["-n", "to", "poem.txt"], ["to", "-n", "poem.txt"], ["to", "poem.txt", "-n"]] {
+            let config = config(&args, false).unwrap();
+            assert_eq!(("to", "poem.txt"), (config.query.as_str(), config.filename.as_str()));
+            assert!(config.line_number);
+        }
+    }
+
+    #[test]
+    fn flags_beat_environment() {
+        assert!(!config(&["to", "poem.txt"], true).unwrap().case_sensitive);
+        assert!(config(&["--no-ignore-case", "to", "poem.txt"], true).unwrap().case_sensitive);
+        assert!(!config(&["-i", "to", "poem.txt"], false).unwrap().case_sensitive);
+        assert!(config(&["to", "poem.txt"], false).unwrap().case_sensitive);
+    }
+
+    #[test]
+    fn help_and_version_need_no_query() {
+        assert!(config(&["--help"], false).unwrap().help);
+        assert!(config(&["-V"], false).unwrap().version);
+        assert_eq!(ArgsError::MissingQuery, config(&[], false).unwrap_err());
+        assert_eq!(ArgsError::MissingFilename, config(&["to"], false).unwrap_err());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(ArgsError::UnknownOption("-q".to_string()), config(&["-iq", "to", "poem.txt"], false).unwrap_err());
+        assert_eq!(ArgsError::UnknownOption("--colour".to_string()), config(&["--colour", "to", "poem.txt"], false).unwrap_err());
+        assert_eq!(
+            ArgsError::UnexpectedValue("--line-number".to_string()),
+            config(&["--line-number=3", "to", "poem.txt"], false).unwrap_err()
+        );
+    }
+}
@@ -3,7 +3,7 @@
 //We’ll change the start of the main function!
 
 use std::env;
-use improved_minigrep::{Config, run};
+use improved_minigrep::{Config, run, usage};
 use std::process;
 
 fn main() {
@@ -17,6 +17,15 @@ fn main() {
     });
     //Next, we need to update the definition of Config::new. In your I/O project’s src/lib.rs file, let’s change the signature of Config::new
 
+    if config.help {
+        print!("{}", usage());
+        return;
+    }
+    if config.version {
+        println!("improved_minigrep {}", env!("CARGO_PKG_VERSION"));
+        return;
+    }
+
     if let Err(e) = run(&config) {
         eprintln!("Error while trying to read the file: {}", e);
         process::exit(1);
@@ -0,0 +1,96 @@
+//! Compares the literal search paths with the plain `contains` filter the
+//! earlier minigreps used. Run with `cargo bench`.
+//!
+//! Horspool is measured on its own, at every length, since where it starts
+//! beating `contains` decides which patterns `Finder` hands it.
+
+use std::time::{Duration, Instant};
+
+use minigrep5::{AhoCorasick, Horspool};
+
+/// The search from improved_minigrep, which every variant is measured against.
+fn contains_query_sensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    contents.lines().filter(|line| line.contains(query)).collect()
+}
+
+fn contains_any<'a>(queries: &[String], contents: &'a str) -> Vec<&'a str> {
+    contents.lines().filter(|line| queries.iter().any(|query| line.contains(query.as_str()))).collect()
+}
+
+/// Lines of made-up words, the same every run.
+fn text(size: usize) -> String {
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut random = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+
+    let mut text = String::with_capacity(size + 100);
+    while text.len() < size {
+        for _ in 0..(random() % 12 + 2) {
+            let len = random() % 9 + 1;
+            text.extend((0..len).map(|_| (b'a' + (random() % 26) as u8) as char));
+            text.push(' ');
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// The best of a few runs, and how many lines the search found.
+fn time<F: FnMut() -> usize>(mut search: F) -> (Duration, usize) {
+    let mut best = Duration::MAX;
+    let mut found = 0;
+    for _ in 0..5 {
+        let start = Instant::now();
+        found = search();
+        best = best.min(start.elapsed());
+    }
+    (best, found)
+}
+
+fn report(name: &str, size: usize, baseline: (Duration, usize), candidate: (Duration, usize)) {
+    assert_eq!(baseline.1, candidate.1, "{} found different lines", name);
+    let rate = |d: Duration| size as f64 / d.as_secs_f64() / 1e6;
+    println!(
+        "{:<28} contains {:>9.2?} ({:>7.0} MB/s)   new {:>9.2?} ({:>7.0} MB/s)   {:>5.2}x   {} lines",
+        name,
+        baseline.0,
+        rate(baseline.0),
+        candidate.0,
+        rate(candidate.0),
+        baseline.0.as_secs_f64() / candidate.0.as_secs_f64(),
+        candidate.1,
+    );
+}
+
+fn main() {
+    let big = text(32 << 20);
+
+    for query in ["zq", "needle", "abcdefghij", "abcdefghijklmnop", "abcdefghijklmnopqrstuvwx", "the quick brown fox jumps over the lazy dog"] {
+        let horspool = Horspool::new(query);
+        report(
+            &format!("horspool, {} bytes", query.len()),
+            big.len(),
+            time(|| contains_query_sensitive(query, &big).len()),
+            time(|| big.lines().filter(|line| horspool.is_match(line)).count()),
+        );
+    }
+
+    // Every query is checked against every line in the baseline, so its
+    // input is kept smaller.
+    let small = text(2 << 20);
+    for count in [10, 100, 2000] {
+        let queries: Vec<String> = big.split_whitespace().skip(1000).step_by(97).take(count).map(String::from).collect();
+        let queries: Vec<String> = queries.into_iter().map(|q| q + " x").collect();
+        let automaton = AhoCorasick::new(&queries);
+        report(
+            &format!("aho-corasick {} patterns", count),
+            small.len(),
+            time(|| contains_any(&queries, &small).len()),
+            time(|| small.lines().filter(|line| automaton.is_match(line)).count()),
+        );
+    }
+}
@@ -0,0 +1,84 @@
+#!/usr/bin/env python3
+"""Generate src/casefold/tables.rs from the Unicode CaseFolding.txt.
+
+Usage: scripts/casefold.py CaseFolding.txt > src/casefold/tables.rs
+
+CaseFolding.txt is at https://www.unicode.org/Public/UCD/latest/ucd/.
+"""
+
+import re
+import sys
+
+
+def parse(path):
+    version = None
+    entries = {"C": {}, "S": {}, "F": {}, "T": {}}
+
+    with open(path, encoding="utf-8") as f:
+        for line in f:
+            match = re.match(r"# CaseFolding-(\d+\.\d+\.\d+)\.txt", line)
+            if match:
+                version = match.group(1)
+            line = line.split("#", 1)[0].strip()
+            if not line:
+                continue
+            code, status, mapping = [field.strip() for field in line.split(";")[:3]]
+            entries[status][int(code, 16)] = [int(c, 16) for c in mapping.split()]
+
+    # Every simple-only mapping has a full one too, so full folding never
+    # has to look at the simple-only entries.
+    assert all(c in entries["F"] for c in entries["S"])
+    return version, entries
+
+
+def char(c):
+    return "'\\u{%04X}'" % c
+
+
+def string(cs):
+    return '"' + "".join("\\u{%04X}" % c for c in cs) + '"'
+
+
+def main():
+    version, entries = parse(sys.argv[1])
+
+    simple = {c: m[0] for c, m in entries["C"].items()}
+    simple.update((c, m[0]) for c, m in entries["S"].items())
+
+    print("// Generated by scripts/casefold.py from CaseFolding.txt"
+          + (", Unicode %s." % version if version else "."))
+    print("// Don't edit by hand.")
+    print()
+
+    print("/// Simple case folding, from the C and S entries, sorted by character.")
+    print("pub const SIMPLE: &[(char, char)] = &[")
+    for c in sorted(simple):
+        print("    (%s, %s)," % (char(c), char(simple[c])))
+    print("];")
+    print()
+
+    print("/// The same pairs the other way around, sorted by folded character, so")
+    print("/// every character that folds to the same one can be found.")
+    print("pub const SIMPLE_INVERSE: &[(char, char)] = &[")
+    for folded, c in sorted((f, c) for c, f in simple.items()):
+        print("    (%s, %s)," % (char(folded), char(c)))
+    print("];")
+    print()
+
+    print("/// Full case folding where it differs from simple folding: the F entries,")
+    print("/// which fold one character to several.")
+    print("pub const FULL: &[(char, &str)] = &[")
+    for c in sorted(entries["F"]):
+        print("    (%s, %s)," % (char(c), string(entries["F"][c])))
+    print("];")
+    print()
+
+    print("/// The T entries, which replace the mappings for I and İ in Turkish and Azeri.")
+    print("pub const TURKIC: &[(char, char)] = &[")
+    for c in sorted(entries["T"]):
+        print("    (%s, %s)," % (char(c), char(entries["T"][c][0])))
+    print("];")
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,261 @@
+use std::error::Error;
+use std::fmt;
+
+use Value::{Optional, Required};
+
+/// One command-line option. Options with a `value` take an argument, written
+/// `-A 3`, `-A3`, `--after-context 3` or `--after-context=3`.
+pub struct Opt {
+    pub short: Option<char>,
+    pub long: &'static str,
+    pub value: Option<Value>,
+    pub help: &'static str,
+}
+
+/// The argument an option takes, named as `--help` shows it.
+#[derive(Clone, Copy)]
+pub enum Value {
+    Required(&'static str),
+    /// Given only as `--color=always`, never as the next argument, so that
+    /// `--color frog` searches for frog.
+    Optional(&'static str),
+}
+
+/// Every option minigrep understands, in the order `--help` lists them.
+pub const OPTIONS: &[Opt] = &[
+    Opt { short: Some('E'), long: "extended-regexp", value: None, help: "QUERY is a regular expression" },
+    Opt { short: Some('i'), long: "ignore-case", value: None, help: "ignore case distinctions" },
+    Opt { short: None, long: "no-ignore-case", value: None, help: "don't ignore case, even if CASE_INSENSITIVE is set" },
+    Opt { short: None, long: "turkic", value: None, help: "ignore case the Turkish way: I is ı and İ is i" },
+    Opt { short: Some('e'), long: "regexp", value: Some(Required("PATTERN")), help: "search for PATTERN instead of QUERY; can be given more than once" },
+    Opt { short: None, long: "boolean", value: None, help: "patterns are expressions like 'foo AND NOT (bar OR baz)'" },
+    Opt { short: None, long: "fuzzy", value: Some(Required("K")), help: "match QUERY with up to K typos, printing each line's count first" },
+    Opt { short: Some('f'), long: "file", value: Some(Required("FILE")), help: "take fixed strings, or with -E patterns, from FILE, one per line" },
+    Opt { short: Some('w'), long: "word-regexp", value: None, help: "match only whole words" },
+    Opt { short: Some('x'), long: "line-regexp", value: None, help: "match only whole lines" },
+    Opt { short: Some('z'), long: "decompress", value: None, help: "search inside gzip-compressed files, found by their first bytes" },
+    Opt { short: Some('a'), long: "text", value: None, help: "search binary files as if they were text" },
+    Opt { short: None, long: "replace", value: Some(Required("TEXT")), help: "show a diff replacing matches with TEXT; with -E, $1 or ${name} is a group" },
+    Opt { short: None, long: "write", value: None, help: "with --replace, change the files in place instead" },
+    Opt { short: Some('v'), long: "invert-match", value: None, help: "select non-matching lines" },
+    Opt { short: Some('m'), long: "max-count", value: Some(Required("NUM")), help: "stop after NUM selected lines" },
+    Opt { short: Some('n'), long: "line-number", value: None, help: "print line numbers" },
+    Opt { short: Some('b'), long: "byte-offset", value: None, help: "print the byte offset of each line" },
+    Opt { short: Some('A'), long: "after-context", value: Some(Required("NUM")), help: "print NUM lines of trailing context" },
+    Opt { short: Some('B'), long: "before-context", value: Some(Required("NUM")), help: "print NUM lines of leading context" },
+    Opt { short: Some('C'), long: "context", value: Some(Required("NUM")), help: "print NUM lines of context around matches" },
+    Opt { short: Some('c'), long: "count", value: None, help: "print only a count of selected lines per file" },
+    Opt { short: Some('l'), long: "files-with-matches", value: None, help: "print only names of files with selected lines" },
+    Opt { short: Some('L'), long: "files-without-match", value: None, help: "print only names of files with no selected lines" },
+    Opt { short: None, long: "json", value: None, help: "print matches and context as JSON events, one per line" },
+    Opt { short: None, long: "color", value: Some(Optional("WHEN")), help: "highlight matches: auto, always or never; WHEN is auto if left out" },
+    Opt { short: None, long: "include", value: Some(Required("GLOB")), help: "search only files that match GLOB" },
+    Opt { short: None, long: "exclude", value: Some(Required("GLOB")), help: "skip files and directories that match GLOB" },
+    Opt { short: Some('j'), long: "threads", value: Some(Required("NUM")), help: "search NUM files at a time on worker threads" },
+    Opt { short: None, long: "sort-files", value: None, help: "with -j, print files in path order, not as they finish" },
+    Opt { short: None, long: "gitignore", value: None, help: "skip files ignored by .gitignore and .git directories" },
+    Opt { short: None, long: "index", value: None, help: "skip files that 'minigrep index' rules out" },
+    Opt { short: None, long: "follow", value: None, help: "keep searching lines added to files, like tail -f" },
+    Opt { short: None, long: "watch", value: None, help: "search what's written under directories from now on" },
+    Opt { short: None, long: "help", value: None, help: "display this help and exit" },
+    Opt { short: Some('V'), long: "version", value: None, help: "display version information and exit" },
+];
+
+/// A parsed argument: an option, by its long name, or a positional one.
+#[derive(Debug, PartialEq)]
+pub enum Arg {
+    Opt(&'static str, Option<String>),
+    Positional(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ArgsError {
+    UnknownOption(String),
+    MissingValue(String),
+    UnexpectedValue(String),
+    InvalidValue(String, String),
+    MissingQuery,
+    /// The first option only makes sense along with the second.
+    Requires(String, String),
+    Conflicts(String, String),
+}
+
+/// Split `args`, which shouldn't include the program name, into options and
+/// positional arguments. Short flags can be combined, as in `-inv`, and
+/// everything after `--` is positional. A lone `-` is positional too.
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Vec<Arg>, ArgsError> {
+    let mut args = args.into_iter();
+    let mut parsed = Vec::new();
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            parsed.extend(args.by_ref().map(Arg::Positional));
+        } else if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let opt = OPTIONS
+                .iter()
+                .find(|opt| opt.long == name)
+                .ok_or_else(|| ArgsError::UnknownOption(format!("--{}", name)))?;
+
+            let value = match (opt.value, inline) {
+                (Some(_), Some(value)) => Some(value),
+                (Some(Required(_)), None) => {
+                    Some(args.next().ok_or_else(|| ArgsError::MissingValue(format!("--{}", name)))?)
+                }
+                (Some(Optional(_)), None) => None,
+                (None, Some(_)) => return Err(ArgsError::UnexpectedValue(format!("--{}", name))),
+                (None, None) => None,
+            };
+            parsed.push(Arg::Opt(opt.long, value));
+        } else if arg.len() > 1 && arg.starts_with('-') {
+            for (i, c) in arg[1..].char_indices() {
+                let opt = OPTIONS
+                    .iter()
+                    .find(|opt| opt.short == Some(c))
+                    .ok_or_else(|| ArgsError::UnknownOption(format!("-{}", c)))?;
+
+                if opt.value.is_none() {
+                    parsed.push(Arg::Opt(opt.long, None));
+                    continue;
+                }
+
+                // The rest of the argument is the value, or else the next one.
+                let rest = &arg[1 + i + c.len_utf8()..];
+                let value = match opt.value {
+                    _ if !rest.is_empty() => Some(rest.to_string()),
+                    Some(Optional(_)) => None,
+                    _ => Some(args.next().ok_or_else(|| ArgsError::MissingValue(format!("-{}", c)))?),
+                };
+                parsed.push(Arg::Opt(opt.long, value));
+                break;
+            }
+        } else {
+            parsed.push(Arg::Positional(arg));
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// Parse the value of a numeric option.
+pub fn number(name: &str, value: Option<String>) -> Result<usize, ArgsError> {
+    let value = value.unwrap_or_default();
+    value.parse().map_err(|_| ArgsError::InvalidValue(format!("--{}", name), value))
+}
+
+/// The text `--help` prints.
+pub fn usage() -> String {
+    let mut usage = String::from(
+        "Usage: minigrep [OPTION]... QUERY [PATH]...\n\
+         \x20 or:  minigrep [OPTION]... -e PATTERN... [PATH]...\n\
+         \x20 or:  minigrep index [OPTION]... [DIR]...\n\
+         Search for QUERY in each PATH. Directories are searched recursively,\n\
+         and standard input when PATH is - or missing.\n\nOptions:\n",
+    );
+
+    for opt in OPTIONS {
+        let short = opt.short.map_or("    ".to_string(), |c| format!("-{}, ", c));
+        let long = match opt.value {
+            Some(Required(value)) => format!("--{}={}", opt.long, value),
+            Some(Optional(value)) => format!("--{}[={}]", opt.long, value),
+            None => format!("--{}", opt.long),
+        };
+        usage.push_str(&format!("  {}{:<26} {}\n", short, long, opt.help));
+    }
+
+    usage.push_str(
+        "\nCase is ignored when the CASE_INSENSITIVE environment variable is set,\n\
+         unless -i or --no-ignore-case says otherwise: the flags take precedence.\n\
+         \n'minigrep index' writes a trigram index of each DIR, or of the current\n\
+         directory, to DIR/.minigrep-index, reading only files changed since the\n\
+         last run. With --index, literal searches of an indexed DIR skip the files\n\
+         it shows can't match; files changed since are always searched.\n\
+         \nWith --follow, files that are truncated or replaced, as when logs are\n\
+         rotated, are searched again from the start. --watch needs Linux.\n\
+         \nThe exit status is 0 if a line is selected, 1 if none is, and 2 if an error occurred.\n",
+    );
+    usage
+}
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArgsError::UnknownOption(opt) => write!(f, "unknown option {}", opt),
+            ArgsError::MissingValue(opt) => write!(f, "option {} needs a value", opt),
+            ArgsError::UnexpectedValue(opt) => write!(f, "option {} doesn't take a value", opt),
+            ArgsError::InvalidValue(opt, value) => write!(f, "invalid value '{}' for {}", value, opt),
+            ArgsError::MissingQuery => write!(f, "no query given"),
+            ArgsError::Requires(opt, other) => write!(f, "option {} needs {}", opt, other),
+            ArgsError::Conflicts(opt, other) => write!(f, "options {} and {} can't be used together", opt, other),
+        }
+    }
+}
+
+impl Error for ArgsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(args: &[&str]) -> Result<Vec<Arg>, ArgsError> {
+        parse(args.iter().map(|s| s.to_string()))
+    }
+
+    fn opt(name: &'static str, value: Option<&str>) -> Arg {
+        Arg::Opt(name, value.map(String::from))
+    }
+
+    #[test]
+    fn short_and_long_options() {
+        assert_eq!(
+            vec![
+                opt("ignore-case", None),
+                opt("line-number", None),
+                opt("invert-match", None),
+                opt("after-context", Some("2")),
+                opt("max-count", Some("5")),
+                opt("include", Some("*.rs")),
+                opt("before-context", Some("1")),
+                Arg::Positional("query".to_string()),
+                Arg::Positional("-".to_string()),
+            ],
+            parse_str(&["-inv", "-A2", "-m", "5", "--include=*.rs", "--before-context", "1", "query", "-"]).unwrap()
+        );
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        assert_eq!(
+            vec![opt("count", None), Arg::Positional("-v".to_string()), Arg::Positional("--help".to_string())],
+            parse_str(&["-c", "--", "-v", "--help"]).unwrap()
+        );
+    }
+
+    #[test]
+    fn optional_values_only_follow_an_equals_sign() {
+        assert_eq!(
+            vec![opt("color", None), Arg::Positional("frog".to_string()), Arg::Positional("file".to_string())],
+            parse_str(&["--color", "frog", "file"]).unwrap()
+        );
+        assert_eq!(vec![opt("color", Some("never"))], parse_str(&["--color=never"]).unwrap());
+        assert!(usage().contains("--color[=WHEN]"));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Err(ArgsError::UnknownOption("-q".to_string())), parse_str(&["-iq"]));
+        assert_eq!(Err(ArgsError::UnknownOption("--colour".to_string())), parse_str(&["--colour"]));
+        assert_eq!(Err(ArgsError::MissingValue("-A".to_string())), parse_str(&["-A"]));
+        assert_eq!(Err(ArgsError::UnexpectedValue("--count".to_string())), parse_str(&["--count=3"]));
+        assert!(number("max-count", Some("x".to_string())).is_err());
+    }
+
+    #[test]
+    fn usage_lists_every_option() {
+        let usage = usage();
+        assert!(OPTIONS.iter().all(|opt| usage.contains(&format!("--{}", opt.long))));
+    }
+}
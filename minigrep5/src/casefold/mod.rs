@@ -0,0 +1,191 @@
+//! Unicode case folding, for comparing text regardless of case.
+//!
+//! Simple folding maps every character to exactly one other, which is what
+//! the regex engine needs to compare a character at a time. Full folding can
+//! map one character to several, so that `ß`, `ẞ` and `SS` all become `ss`;
+//! the literal `-i` search uses it. The tables in `tables.rs` are generated
+//! from the Unicode data by `scripts/casefold.py`.
+
+mod tables;
+
+use std::str::Chars;
+
+use tables::{FULL, SIMPLE, SIMPLE_INVERSE, TURKIC};
+
+/// Fold `c` on its own.
+pub fn simple(c: char) -> char {
+    if c.is_ascii() {
+        return c.to_ascii_lowercase();
+    }
+    match SIMPLE.binary_search_by_key(&c, |&(from, _)| from) {
+        Ok(i) => SIMPLE[i].1,
+        Err(_) => c,
+    }
+}
+
+/// Every character that folds the same way `c` does, `c` included, as
+/// `k`, `K` and the Kelvin sign `K` do.
+pub fn variants(c: char) -> impl Iterator<Item = char> {
+    let folded = simple(c);
+    let start = SIMPLE_INVERSE.partition_point(|&(to, _)| to < folded);
+    let others = SIMPLE_INVERSE[start..].iter().take_while(move |&&(to, _)| to == folded);
+    std::iter::once(folded).chain(others.map(|&(_, from)| from))
+}
+
+/// Fold `c` into the characters it stands for when ignoring case. With
+/// `turkic`, `I` folds to the dotless `ı` and `İ` to a plain `i`.
+pub fn full(c: char, turkic: bool) -> Folded {
+    if turkic {
+        if let Ok(i) = TURKIC.binary_search_by_key(&c, |&(from, _)| from) {
+            return Folded::One(Some(TURKIC[i].1));
+        }
+    }
+    if !c.is_ascii() {
+        if let Ok(i) = FULL.binary_search_by_key(&c, |&(from, _)| from) {
+            return Folded::Many(FULL[i].1.chars());
+        }
+    }
+    Folded::One(Some(simple(c)))
+}
+
+/// The characters one character folds to.
+pub enum Folded {
+    One(Option<char>),
+    Many(Chars<'static>),
+}
+
+impl Iterator for Folded {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        match self {
+            Folded::One(c) => c.take(),
+            Folded::Many(chars) => chars.next(),
+        }
+    }
+}
+
+/// A query matched ignoring case, by comparing its full folding with the
+/// folding of the text as it's read. Nothing is allocated per search.
+///
+/// A match always covers whole characters of the text, so `ss` finds `ß`
+/// but `s` doesn't.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Caseless {
+    query: Vec<char>,
+    turkic: bool,
+}
+
+impl Caseless {
+    pub fn new(query: &str, turkic: bool) -> Caseless {
+        let query = query.chars().flat_map(|c| full(c, turkic)).collect();
+        Caseless { query, turkic }
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find_at(text, 0).is_some()
+    }
+
+    /// The first match starting at or after byte offset `start`.
+    pub fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+        if self.query.is_empty() {
+            return Some((start, start));
+        }
+        text[start..]
+            .char_indices()
+            .find_map(|(i, _)| self.match_at(text, start + i).map(|end| (start + i, end)))
+    }
+
+    /// Successive non-overlapping matches, as `(start, end)` byte offsets.
+    pub fn find_iter<'a>(&'a self, text: &'a str) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let mut at = Some(0);
+        std::iter::from_fn(move || {
+            let (start, end) = self.find_at(text, at?)?;
+            // An empty match has to move on by a character to get anywhere.
+            at = match text[end..].chars().next() {
+                Some(c) if start == end => Some(end + c.len_utf8()),
+                None if start == end => None,
+                _ => Some(end),
+            };
+            Some((start, end))
+        })
+    }
+
+    /// Where a match starting at `at` ends, if there is one.
+    fn match_at(&self, text: &str, at: usize) -> Option<usize> {
+        let mut matched = 0;
+        for (i, c) in text[at..].char_indices() {
+            for folded in full(c, self.turkic) {
+                // Running out of query halfway through a character fails too.
+                if self.query.get(matched) != Some(&folded) {
+                    return None;
+                }
+                matched += 1;
+            }
+            if matched == self.query.len() {
+                return Some(at + i + c.len_utf8());
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(query: &str, text: &str) -> Vec<(usize, usize)> {
+        Caseless::new(query, false).find_iter(text).collect()
+    }
+
+    #[test]
+    fn simple_folding() {
+        assert_eq!('a', simple('A'));
+        assert_eq!('σ', simple('Σ'));
+        assert_eq!('σ', simple('ς'));
+        assert_eq!('k', simple('\u{212A}'));
+        assert_eq!('ß', simple('ẞ'));
+        assert_eq!('ß', simple('ß'));
+        assert_eq!('ⴀ', simple('Ⴀ'));
+        assert_eq!('𐐨', simple('𐐀'));
+
+        let mut sigmas: Vec<_> = variants('ς').collect();
+        sigmas.sort();
+        assert_eq!(vec!['Σ', 'ς', 'σ'], sigmas);
+        assert_eq!(vec!['1'], variants('1').collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn full_folding() {
+        assert_eq!("ss", full('ß', false).collect::<String>());
+        assert_eq!("ss", full('ẞ', false).collect::<String>());
+        assert_eq!("i\u{307}", full('İ', false).collect::<String>());
+        assert_eq!("ı", full('I', true).collect::<String>());
+        assert_eq!("i", full('İ', true).collect::<String>());
+        assert_eq!("ἀι", full('ᾈ', false).collect::<String>());
+    }
+
+    #[test]
+    fn finds_across_expansions() {
+        assert_eq!(vec![(0, 7), (8, 15)], find("strasse", "Straße STRASSE"));
+        assert_eq!(vec![(4, 6)], find("SS", "Stra\u{df}e"));
+        assert_eq!(Vec::<(usize, usize)>::new(), find("s", "ß"));
+        assert_eq!(vec![(0, 10), (11, 21)], find("ΣΟΦΟΣ", "σοφος σοφος"));
+        assert_eq!(vec![(0, 2)], find("ﬁ", "FI"));
+        assert_eq!(vec![(0, 4), (5, 9)], find("𐐨", "𐐀 𐐨"));
+        assert_eq!(vec![(0, 0), (1, 1), (3, 3)], find("", "aé"));
+    }
+
+    #[test]
+    fn turkish_dotted_and_dotless_i() {
+        // Without the Turkic rules İ is an i with a combining dot, which a
+        // plain i can't match on its own.
+        assert!(!Caseless::new("istanbul", false).is_match("İSTANBUL"));
+        assert!(Caseless::new("i\u{307}stanbul", false).is_match("İSTANBUL"));
+
+        let turkic = Caseless::new("istanbul", true);
+        assert!(turkic.is_match("İSTANBUL"));
+        assert!(!turkic.is_match("ISTANBUL"));
+        assert!(Caseless::new("ılık", true).is_match("ILIK"));
+    }
+}
@@ -0,0 +1,108 @@
+/// SGR sequences for each part of the output, in the `GREP_COLORS` format
+/// GNU grep uses: `ms=01;31:mc=01;31:sl=:cx=:fn=35:ln=32:bn=32:se=36`.
+///
+/// An empty sequence leaves that part uncoloured.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Colors {
+    /// Matched text in a selected line.
+    pub selected_match: String,
+    /// Matched text in a context line.
+    pub context_match: String,
+    /// The rest of a selected line.
+    pub selected_line: String,
+    /// The rest of a context line.
+    pub context_line: String,
+    pub file_name: String,
+    pub line_number: String,
+    pub byte_offset: String,
+    pub separator: String,
+    /// Whether to erase to the end of the line after each sequence, which
+    /// keeps background colours from bleeding. Turned off by `ne`.
+    pub erase_line: bool,
+}
+
+impl Colors {
+    /// The defaults, with any capabilities in `spec` overriding them.
+    /// Unknown capabilities and malformed values are ignored, as grep does.
+    pub fn parse(spec: &str) -> Colors {
+        let mut colors = Colors::default();
+
+        for capability in spec.split(':') {
+            let (name, value) = capability.split_once('=').unwrap_or((capability, ""));
+            if !value.bytes().all(|b| b.is_ascii_digit() || b == b';') {
+                continue;
+            }
+            let value = value.to_string();
+
+            match name {
+                "mt" => {
+                    colors.selected_match = value.clone();
+                    colors.context_match = value;
+                }
+                "ms" => colors.selected_match = value,
+                "mc" => colors.context_match = value,
+                "sl" => colors.selected_line = value,
+                "cx" => colors.context_line = value,
+                "fn" => colors.file_name = value,
+                "ln" => colors.line_number = value,
+                "bn" => colors.byte_offset = value,
+                "se" => colors.separator = value,
+                "ne" => colors.erase_line = false,
+                _ => {}
+            }
+        }
+
+        colors
+    }
+
+    /// Wrap `text` in `color`, unless either is empty.
+    pub fn paint(&self, color: &str, text: &str) -> String {
+        if color.is_empty() || text.is_empty() {
+            return text.to_string();
+        }
+        let erase = if self.erase_line { "\x1b[K" } else { "" };
+        format!("\x1b[{}m{}{}\x1b[m{}", color, erase, text, erase)
+    }
+}
+
+impl Default for Colors {
+    fn default() -> Colors {
+        Colors {
+            selected_match: "01;31".to_string(),
+            context_match: "01;31".to_string(),
+            selected_line: String::new(),
+            context_line: String::new(),
+            file_name: "35".to_string(),
+            line_number: "32".to_string(),
+            byte_offset: "32".to_string(),
+            separator: "36".to_string(),
+            erase_line: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_grep_colors() {
+        let colors = Colors::parse("mt=04;33:fn=:ln=1;34:sl=7:bogus=1:se=x:ne");
+        assert_eq!("04;33", colors.selected_match);
+        assert_eq!("04;33", colors.context_match);
+        assert_eq!("", colors.file_name);
+        assert_eq!("1;34", colors.line_number);
+        assert_eq!("7", colors.selected_line);
+        assert_eq!("36", colors.separator);
+        assert!(!colors.erase_line);
+        assert_eq!(Colors::default(), Colors::parse(""));
+    }
+
+    #[test]
+    fn paints() {
+        let colors = Colors::default();
+        assert_eq!("\x1b[32m\x1b[K12\x1b[m\x1b[K", colors.paint("32", "12"));
+        assert_eq!("12", colors.paint("", "12"));
+        assert_eq!("", colors.paint("32", ""));
+    }
+}
@@ -0,0 +1,159 @@
+use std::io::{self, BufRead, Read};
+
+/// The encoding a byte order mark at the start of `bytes` announces, if
+/// it's UTF-16: `Some(true)` for big endian, `Some(false)` for little.
+pub fn utf16_bom(bytes: &[u8]) -> Option<bool> {
+    match bytes {
+        [0xfe, 0xff, ..] => Some(true),
+        [0xff, 0xfe, ..] => Some(false),
+        _ => None,
+    }
+}
+
+/// `reader`, with at least its first `len` bytes, or all of it if it's
+/// shorter, in the first buffer `fill_buf` returns, so that what it starts
+/// with can be checked. A single read can come up short, as pipes do.
+pub fn buffer_start<R: BufRead>(mut reader: R, len: usize) -> io::Result<io::Chain<io::Cursor<Vec<u8>>, R>> {
+    let mut start = Vec::new();
+    while start.len() < len {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() || start.is_empty() && buf.len() >= len {
+            break;
+        }
+        start.extend_from_slice(buf);
+        let read = buf.len();
+        reader.consume(read);
+    }
+    Ok(io::Cursor::new(start).chain(reader))
+}
+
+/// Turns UTF-16 read from `inner` into UTF-8, a chunk at a time.
+///
+/// Unpaired surrogates and a dangling odd byte at the end come out as
+/// U+FFFD, the same way invalid UTF-8 is decoded lossily. The byte order
+/// mark is expected to have been consumed already.
+pub struct Utf16Reader<R> {
+    inner: R,
+    big_endian: bool,
+    /// UTF-8 waiting to be read, from `at` on.
+    decoded: Vec<u8>,
+    at: usize,
+    /// The first byte of a code unit split between two reads.
+    odd_byte: Option<u8>,
+    /// A high surrogate waiting for its low half.
+    high: Option<u16>,
+}
+
+impl<R: BufRead> Utf16Reader<R> {
+    pub fn new(inner: R, big_endian: bool) -> Utf16Reader<R> {
+        Utf16Reader { inner, big_endian, decoded: Vec::new(), at: 0, odd_byte: None, high: None }
+    }
+
+    /// Decode the next chunk of input. Returns false at the end of it.
+    fn decode_chunk(&mut self) -> io::Result<bool> {
+        self.decoded.clear();
+        self.at = 0;
+
+        let chunk = self.inner.fill_buf()?;
+        if chunk.is_empty() {
+            if self.high.take().is_some() || self.odd_byte.take().is_some() {
+                self.push(char::REPLACEMENT_CHARACTER);
+            }
+            return Ok(false);
+        }
+
+        let mut bytes = chunk.to_vec();
+        let len = bytes.len();
+        self.inner.consume(len);
+        if let Some(first) = self.odd_byte.take() {
+            bytes.insert(0, first);
+        }
+        if bytes.len() % 2 == 1 {
+            self.odd_byte = bytes.pop();
+        }
+
+        for pair in bytes.chunks_exact(2) {
+            let unit = match self.big_endian {
+                true => u16::from_be_bytes([pair[0], pair[1]]),
+                false => u16::from_le_bytes([pair[0], pair[1]]),
+            };
+            self.unit(unit);
+        }
+        Ok(true)
+    }
+
+    fn unit(&mut self, unit: u16) {
+        match (self.high.take(), unit) {
+            (Some(high), 0xdc00..=0xdfff) => {
+                let c = 0x10000 + ((u32::from(high) - 0xd800) << 10) + (u32::from(unit) - 0xdc00);
+                self.push(char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER));
+            }
+            (high, _) => {
+                if high.is_some() {
+                    self.push(char::REPLACEMENT_CHARACTER);
+                }
+                match unit {
+                    0xd800..=0xdbff => self.high = Some(unit),
+                    0xdc00..=0xdfff => self.push(char::REPLACEMENT_CHARACTER),
+                    _ => self.push(char::from_u32(u32::from(unit)).unwrap_or(char::REPLACEMENT_CHARACTER)),
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, c: char) {
+        let mut utf8 = [0; 4];
+        self.decoded.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+    }
+}
+
+impl<R: BufRead> Read for Utf16Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // A chunk can decode to nothing, when it's only half a pair.
+        while self.at == self.decoded.len() {
+            if !self.decode_chunk()? && self.decoded.is_empty() {
+                return Ok(0);
+            }
+        }
+
+        let n = buf.len().min(self.decoded.len() - self.at);
+        buf[..n].copy_from_slice(&self.decoded[self.at..self.at + n]);
+        self.at += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8], big_endian: bool) -> String {
+        // A tiny buffer splits units and surrogate pairs between reads.
+        let inner = io::BufReader::with_capacity(3, bytes);
+        let mut decoded = String::new();
+        Utf16Reader::new(inner, big_endian).read_to_string(&mut decoded).unwrap();
+        decoded
+    }
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| if big_endian { unit.to_be_bytes() } else { unit.to_le_bytes() })
+            .collect()
+    }
+
+    #[test]
+    fn decodes_both_byte_orders() {
+        let text = "héllo 𝄞 wörld\r\nzwei\n";
+        assert_eq!(text, decode(&utf16(text, false), false));
+        assert_eq!(text, decode(&utf16(text, true), true));
+        assert_eq!(Some(false), utf16_bom(&[0xff, 0xfe, b'a', 0]));
+        assert_eq!(None, utf16_bom(b"plain"));
+    }
+
+    #[test]
+    fn replaces_broken_input() {
+        // A lone low surrogate, a high one followed by a letter, and an odd byte.
+        let bytes = [0x00, 0xdc, 0x3d, 0xd8, b'a', 0, b'b'];
+        assert_eq!("\u{fffd}\u{fffd}a\u{fffd}", decode(&bytes, false));
+    }
+}
@@ -0,0 +1,265 @@
+//! Boolean combinations of patterns, for `--boolean`.
+//!
+//! Patterns are joined with `AND`, `OR` and `NOT`, which bind in the
+//! order `NOT`, then `AND`, then `OR`, and grouped with parentheses, as in
+//! `error AND NOT (timeout OR retry)`. The operators have to be written in
+//! capitals, so `and` is just a pattern. A pattern with spaces or
+//! parentheses in it, or one that's spelled like an operator, goes in
+//! double quotes, inside which `\"` and `\\` stand for `"` and `\`.
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr<T> {
+    Pattern(T),
+    Not(Box<Expr<T>>),
+    And(Vec<Expr<T>>),
+    Or(Vec<Expr<T>>),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ExprError {
+    pub message: String,
+    /// Counted in characters from the start of the expression.
+    pub position: usize,
+}
+
+impl<T> Expr<T> {
+    /// The same expression with every pattern turned into something else,
+    /// such as a matcher for it.
+    pub fn try_map<U, E>(self, f: &mut impl FnMut(T) -> Result<U, E>) -> Result<Expr<U>, E> {
+        Ok(match self {
+            Expr::Pattern(pattern) => Expr::Pattern(f(pattern)?),
+            Expr::Not(expr) => Expr::Not(Box::new(expr.try_map(f)?)),
+            Expr::And(exprs) => Expr::And(exprs.into_iter().map(|expr| expr.try_map(f)).collect::<Result<_, _>>()?),
+            Expr::Or(exprs) => Expr::Or(exprs.into_iter().map(|expr| expr.try_map(f)).collect::<Result<_, _>>()?),
+        })
+    }
+
+    /// Whether the expression holds, given whether each pattern does.
+    /// Patterns are only asked about as far as the answer depends on them.
+    pub fn eval(&self, holds: &mut impl FnMut(&T) -> bool) -> bool {
+        match self {
+            Expr::Pattern(pattern) => holds(pattern),
+            Expr::Not(expr) => !expr.eval(holds),
+            Expr::And(exprs) => exprs.iter().all(|expr| expr.eval(holds)),
+            Expr::Or(exprs) => exprs.iter().any(|expr| expr.eval(holds)),
+        }
+    }
+
+    /// The patterns that aren't negated, which are the ones whose matches
+    /// are worth showing in a line the expression holds for.
+    pub fn positive(&self) -> Vec<&T> {
+        let mut patterns = Vec::new();
+        self.collect_positive(false, &mut patterns);
+        patterns
+    }
+
+    fn collect_positive<'a>(&'a self, negated: bool, patterns: &mut Vec<&'a T>) {
+        match self {
+            Expr::Pattern(pattern) if !negated => patterns.push(pattern),
+            Expr::Pattern(_) => {}
+            Expr::Not(expr) => expr.collect_positive(!negated, patterns),
+            Expr::And(exprs) | Expr::Or(exprs) => {
+                for expr in exprs {
+                    expr.collect_positive(negated, patterns);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Pattern(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+pub fn parse(expression: &str) -> Result<Expr<String>, ExprError> {
+    let tokens = tokenize(expression)?;
+    let mut parser = Parser { tokens, pos: 0, end: expression.chars().count() };
+    let expr = parser.or()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(expr),
+        Some((Token::Close, at)) => Err(ExprError { message: "unmatched )".to_string(), position: *at }),
+        Some((_, at)) => Err(ExprError { message: "expected AND or OR".to_string(), position: *at }),
+    }
+}
+
+/// Split the expression into tokens, each with the position it starts at.
+fn tokenize(expression: &str) -> Result<Vec<(Token, usize)>, ExprError> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let start = i;
+        match chars[i] {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => {
+                tokens.push((Token::Open, start));
+                i += 1;
+            }
+            ')' => {
+                tokens.push((Token::Close, start));
+                i += 1;
+            }
+            '"' => {
+                let mut pattern = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(ExprError { message: "unclosed quote".to_string(), position: start }),
+                        Some('"') => break,
+                        Some('\\') if matches!(chars.get(i + 1), Some('"' | '\\')) => {
+                            pattern.push(chars[i + 1]);
+                            i += 2;
+                        }
+                        Some(&c) => {
+                            pattern.push(c);
+                            i += 1;
+                        }
+                    }
+                }
+                i += 1;
+                tokens.push((Token::Pattern(pattern), start));
+            }
+            _ => {
+                while i < chars.len() && !chars[i].is_whitespace() && !matches!(chars[i], '(' | ')' | '"') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                let token = match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Pattern(word),
+                };
+                tokens.push((token, start));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    /// Where the expression ends, for errors about running out of it.
+    end: usize,
+}
+
+impl Parser {
+    fn or(&mut self) -> Result<Expr<String>, ExprError> {
+        let mut exprs = vec![self.and()?];
+        while self.eat(&Token::Or) {
+            exprs.push(self.and()?);
+        }
+        Ok(if exprs.len() == 1 { exprs.remove(0) } else { Expr::Or(exprs) })
+    }
+
+    fn and(&mut self) -> Result<Expr<String>, ExprError> {
+        let mut exprs = vec![self.not()?];
+        while self.eat(&Token::And) {
+            exprs.push(self.not()?);
+        }
+        Ok(if exprs.len() == 1 { exprs.remove(0) } else { Expr::And(exprs) })
+    }
+
+    fn not(&mut self) -> Result<Expr<String>, ExprError> {
+        if self.eat(&Token::Not) {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+
+        let position = self.tokens.get(self.pos).map_or(self.end, |&(_, at)| at);
+        match self.tokens.get(self.pos) {
+            Some((Token::Pattern(pattern), _)) => {
+                let pattern = pattern.clone();
+                self.pos += 1;
+                Ok(Expr::Pattern(pattern))
+            }
+            Some((Token::Open, _)) => {
+                self.pos += 1;
+                let expr = self.or()?;
+                if !self.eat(&Token::Close) {
+                    return Err(ExprError { message: "unclosed (".to_string(), position });
+                }
+                Ok(expr)
+            }
+            _ => Err(ExprError { message: "expected a pattern".to_string(), position }),
+        }
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let matched = self.tokens.get(self.pos).is_some_and(|(next, _)| next == token);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl Error for ExprError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(pattern: &str) -> Expr<String> {
+        Expr::Pattern(pattern.to_string())
+    }
+
+    fn error(expression: &str) -> String {
+        parse(expression).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn precedence_and_grouping() {
+        assert_eq!(pattern("foo"), parse(" foo ").unwrap());
+        assert_eq!(Expr::And(vec![pattern("foo"), Expr::Not(Box::new(pattern("bar")))]), parse("foo AND NOT bar").unwrap());
+        assert_eq!(
+            Expr::Or(vec![pattern("a"), Expr::And(vec![pattern("b"), pattern("c")])]),
+            parse("a OR b AND c").unwrap()
+        );
+        assert_eq!(
+            Expr::And(vec![Expr::Or(vec![pattern("a"), pattern("b")]), pattern("c")]),
+            parse("(a OR b) AND c").unwrap()
+        );
+        assert_eq!(Expr::And(vec![pattern("x y"), pattern("AND"), pattern("a\"b\\")]), parse(r#""x y" AND "AND" AND "a\"b\\""#).unwrap());
+        assert_eq!(Expr::Or(vec![pattern("and"), pattern("or")]), parse("and OR or").unwrap());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!("expected a pattern at position 8", error("foo AND "));
+        assert_eq!("expected AND or OR at position 4", error("foo bar"));
+        assert_eq!("unclosed ( at position 0", error("(a OR b"));
+        assert_eq!("unmatched ) at position 1", error("a)"));
+        assert_eq!("unclosed quote at position 6", error("a AND \"b"));
+        assert_eq!("expected a pattern at position 0", error(""));
+    }
+
+    #[test]
+    fn evaluation() {
+        let expr = parse("error AND NOT (timeout OR retry)").unwrap();
+        let holds = |line: &str| expr.eval(&mut |pattern: &String| line.contains(pattern.as_str()));
+        assert!(holds("error: disk full"));
+        assert!(!holds("error: timeout"));
+        assert!(!holds("retry later"));
+        assert_eq!(vec!["error"], expr.positive());
+    }
+}
@@ -0,0 +1,452 @@
+//! Searching files as they grow, for `--follow` and `--watch`.
+//!
+//! Only complete lines are searched: one that's still being written waits
+//! for its newline. A file that gets shorter has been truncated, and is
+//! searched again from its start, and so is a new file that takes the
+//! place of an old one, which is how logs are rotated.
+
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+#[cfg(target_os = "linux")]
+use std::collections::HashMap;
+#[cfg(target_os = "linux")]
+use std::mem;
+
+#[cfg(target_os = "linux")]
+use crate::inotify::{self, Inotify};
+use crate::searcher::Position;
+#[cfg(target_os = "linux")]
+use crate::Walker;
+use crate::{Printer, Searcher};
+
+/// How long to wait between looks at followed files, as `tail -f` does
+/// when it can't be told about changes.
+pub const INTERVAL: Duration = Duration::from_millis(250);
+
+/// How far a file has been searched.
+struct Tail {
+    path: PathBuf,
+    /// The device and inode of the file, which tell it from a new file put
+    /// in its place.
+    id: (u64, u64),
+    position: Position,
+    /// Whether `position.line` is right. Files searched from their end
+    /// only have their lines counted once something is added to them.
+    counted: bool,
+}
+
+impl Tail {
+    fn new(path: &Path, metadata: &Metadata, from_end: bool) -> Tail {
+        let position = match from_end {
+            true => Position { line: 0, offset: metadata.len() as usize },
+            false => Position::default(),
+        };
+        Tail { path: path.to_path_buf(), id: file_id(metadata), position, counted: !from_end }
+    }
+
+    /// Search the complete lines that `file`, the one at `path` or the one
+    /// that was, has gained since it was last searched.
+    fn search<W: Write>(&mut self, mut file: &File, searcher: &Searcher, printer: &mut Printer<W>) -> io::Result<usize> {
+        let metadata = file.metadata()?;
+        let id = file_id(&metadata);
+        if id != self.id {
+            *self = Tail::new(&self.path, &metadata, false);
+        } else if metadata.len() < self.position.offset as u64 {
+            eprintln!("{}: file truncated", self.path.display());
+            self.position = Position::default();
+            self.counted = true;
+        }
+
+        if !self.counted {
+            file.seek(SeekFrom::Start(0))?;
+            self.position.line = count_lines(file.take(self.position.offset as u64))?;
+            self.counted = true;
+        }
+        let start = self.position.offset as u64;
+        let Some(end) = last_line_end(file, start, metadata.len())? else { return Ok(0) };
+
+        file.seek(SeekFrom::Start(start))?;
+        let count = searcher.search_from(BufReader::new(file.take(end - start)), &self.path, printer, &mut self.position)?;
+        // Searching stops early in binary files, whose lines go unread.
+        self.position.offset = end as usize;
+        Ok(count)
+    }
+}
+
+/// Searches files and then what's added to them, like `tail -F`, for
+/// `--follow`.
+///
+/// Files are kept open, so one that's renamed away is read to its end
+/// before the new file at its path is taken up.
+pub struct Follower<'m> {
+    searcher: Searcher<'m>,
+    files: Vec<(Tail, File)>,
+}
+
+impl<'m> Follower<'m> {
+    pub fn new(searcher: Searcher<'m>) -> Follower<'m> {
+        Follower { searcher, files: Vec::new() }
+    }
+
+    /// Follow the file at `path`, from its start.
+    pub fn add(&mut self, path: &Path) -> io::Result<()> {
+        if path == Path::new("-") {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "standard input can't be followed"));
+        }
+        let file = File::open(path)?;
+        let tail = Tail::new(path, &file.metadata()?, false);
+        self.files.push((tail, file));
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Search what the files have gained since the last time, returning
+    /// how many lines were selected.
+    pub fn poll<W: Write>(&mut self, printer: &mut Printer<W>) -> io::Result<usize> {
+        let mut count = 0;
+        for (tail, file) in &mut self.files {
+            // Until a new file turns up, the old one is all there is.
+            let replaced = fs::metadata(&tail.path).is_ok_and(|metadata| file_id(&metadata) != tail.id);
+            count += tail.search(file, &self.searcher, printer)?;
+            if !replaced {
+                continue;
+            }
+            match File::open(&tail.path) {
+                Ok(new) => {
+                    eprintln!("{}: file replaced, following the new one", tail.path.display());
+                    *file = new;
+                    count += tail.search(file, &self.searcher, printer)?;
+                }
+                Err(e) => eprintln!("Error while trying to open the file: {}: {}", tail.path.display(), e),
+            }
+        }
+        Ok(count)
+    }
+}
+
+/// Searches what's written to the files under some directories as inotify
+/// reports it, for `--watch`.
+///
+/// Files that are there when watching starts are searched from where they
+/// end then, and files that turn up later from their start. Directories
+/// that turn up are watched too, if the walker would have gone into them.
+/// Files and directories renamed within the watched ones carry on from
+/// where they were.
+#[cfg(target_os = "linux")]
+pub struct Watcher<'w, 'm> {
+    searcher: Searcher<'m>,
+    walker: &'w Walker,
+    inotify: Inotify,
+    /// The directory of each watch, and the root it's under.
+    dirs: HashMap<i32, (PathBuf, PathBuf)>,
+    tails: HashMap<PathBuf, Tail>,
+    /// Where things were renamed from, by the cookie of the rename, until
+    /// the read after the one that reported it. The two halves of a rename
+    /// can come in different reads.
+    moved: HashMap<u32, PathBuf>,
+}
+
+#[cfg(target_os = "linux")]
+const MASK: u32 = inotify::IN_MODIFY
+    | inotify::IN_CREATE
+    | inotify::IN_MOVED_TO
+    | inotify::IN_MOVED_FROM
+    | inotify::IN_DELETE
+    | inotify::IN_ONLYDIR;
+
+#[cfg(target_os = "linux")]
+impl<'w, 'm> Watcher<'w, 'm> {
+    /// Start watching `roots`, which have to be directories.
+    pub fn new(searcher: Searcher<'m>, walker: &'w Walker, roots: &[String]) -> io::Result<Watcher<'w, 'm>> {
+        let inotify = Inotify::new()?;
+        let mut watcher = Watcher { searcher, walker, inotify, dirs: HashMap::new(), tails: HashMap::new(), moved: HashMap::new() };
+        for root in roots {
+            let root = Path::new(root);
+            if !fs::metadata(root)?.is_dir() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{}: not a directory", root.display())));
+            }
+            watcher.add_tree(root, root, true);
+        }
+        Ok(watcher)
+    }
+
+    /// Wait for files to change and search what's been written to them,
+    /// returning how many lines were selected.
+    pub fn wait<W: Write>(&mut self, printer: &mut Printer<W>) -> io::Result<usize> {
+        let mut count = 0;
+        let mut expiring = mem::take(&mut self.moved);
+        for event in self.inotify.read_events()? {
+            if event.mask & inotify::IN_Q_OVERFLOW != 0 {
+                // Some changes went unreported, so every file is looked at.
+                let mut paths: Vec<PathBuf> = self.tails.keys().cloned().collect();
+                paths.sort();
+                for path in paths {
+                    count += self.search(&path, printer)?;
+                }
+                continue;
+            }
+            if event.mask & inotify::IN_IGNORED != 0 {
+                self.dirs.remove(&event.watch);
+                continue;
+            }
+            let (Some((root, dir)), Some(name)) = (self.dirs.get(&event.watch), &event.name) else { continue };
+            let (root, path) = (root.clone(), dir.join(name));
+            let is_dir = event.mask & inotify::IN_ISDIR != 0;
+
+            if event.mask & inotify::IN_MOVED_FROM != 0 {
+                self.moved.insert(event.cookie, path);
+            } else if event.mask & inotify::IN_DELETE != 0 {
+                self.forget(&path);
+            } else if let Some(from) = self.moved.remove(&event.cookie)
+                .or_else(|| expiring.remove(&event.cookie))
+                .filter(|_| event.mask & inotify::IN_MOVED_TO != 0)
+            {
+                self.forget(&path);
+                if self.walker.admits(&root, &path, is_dir) {
+                    self.rename(&root, &from, &path);
+                } else {
+                    self.forget(&from);
+                }
+            } else if is_dir {
+                if self.walker.admits(&root, &path, true) {
+                    for file in self.add_tree(&root, &path, false) {
+                        count += self.search(&file, printer)?;
+                    }
+                }
+            } else {
+                if !self.tails.contains_key(&path) {
+                    if !self.walker.admits(&root, &path, false) {
+                        continue;
+                    }
+                    // It may be gone again already, which another event says.
+                    let Ok(metadata) = fs::metadata(&path) else { continue };
+                    self.tails.insert(path.clone(), Tail::new(&path, &metadata, false));
+                }
+                count += self.search(&path, printer)?;
+            }
+        }
+        // Whatever was moved out of the watched directories is gone.
+        for path in expiring.into_values() {
+            self.forget(&path);
+        }
+        Ok(count)
+    }
+
+    /// Watch `dir`, under `root`, and every directory the walker finds in
+    /// it, returning the files it finds, which are searched from their end
+    /// or their start.
+    fn add_tree(&mut self, root: &Path, dir: &Path, from_end: bool) -> Vec<PathBuf> {
+        let (dirs, files) = self.walker.tree(dir);
+        for dir in dirs {
+            match self.inotify.add_watch(&dir, MASK) {
+                Ok(watch) => {
+                    self.dirs.insert(watch, (root.to_path_buf(), dir));
+                }
+                Err(e) => eprintln!("Error while watching {}: {}", dir.display(), e),
+            }
+        }
+
+        let mut found = Vec::new();
+        for file in files {
+            match file.and_then(|path| fs::metadata(&path).map(|metadata| (path, metadata))) {
+                Ok((path, metadata)) => {
+                    self.tails.insert(path.clone(), Tail::new(&path, &metadata, from_end));
+                    found.push(path);
+                }
+                Err(e) => eprintln!("Error while trying to open the file: {}", e),
+            }
+        }
+        found
+    }
+
+    /// Carry on with what was at `from`, and everything in it, at `to`,
+    /// under `root`. Watches follow directories wherever they're moved.
+    fn rename(&mut self, root: &Path, from: &Path, to: &Path) {
+        let moved = |path: &Path| {
+            let rest = path.strip_prefix(from).ok()?;
+            Some(if rest.as_os_str().is_empty() { to.to_path_buf() } else { to.join(rest) })
+        };
+
+        let files: Vec<PathBuf> = self.tails.keys().filter(|file| file.starts_with(from)).cloned().collect();
+        for file in files {
+            if let (Some(mut tail), Some(new)) = (self.tails.remove(&file), moved(&file)) {
+                tail.path = new.clone();
+                self.tails.insert(new, tail);
+            }
+        }
+        for (dir_root, dir) in self.dirs.values_mut() {
+            if let Some(new) = moved(dir) {
+                *dir_root = root.to_path_buf();
+                *dir = new;
+            }
+        }
+    }
+
+    /// Stop watching `path` and, if it's a directory, everything in it.
+    fn forget(&mut self, path: &Path) {
+        self.tails.retain(|file, _| !file.starts_with(path));
+        let watches: Vec<i32> = self.dirs.iter().filter(|(_, (_, dir))| dir.starts_with(path)).map(|(&watch, _)| watch).collect();
+        for watch in watches {
+            // A deleted directory's watch is already gone.
+            let _ = self.inotify.remove_watch(watch);
+            self.dirs.remove(&watch);
+        }
+    }
+
+    fn search<W: Write>(&mut self, path: &Path, printer: &mut Printer<W>) -> io::Result<usize> {
+        let Some(tail) = self.tails.get_mut(path) else { return Ok(0) };
+        match File::open(path) {
+            Ok(file) => tail.search(&file, &self.searcher, printer),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(e) => {
+                eprintln!("Error while trying to open the file: {}: {}", path.display(), e);
+                Ok(0)
+            }
+        }
+    }
+}
+
+fn count_lines<R: Read>(mut reader: R) -> io::Result<usize> {
+    let mut buf = [0; 8192];
+    let mut lines = 0;
+    loop {
+        match reader.read(&mut buf)? {
+            0 => return Ok(lines),
+            read => lines += buf[..read].iter().filter(|&&b| b == b'\n').count(),
+        }
+    }
+}
+
+/// Where the last complete line between `start` and `end` in `file` ends,
+/// if there is one, found by reading backwards from `end`.
+fn last_line_end(mut file: &File, start: u64, end: u64) -> io::Result<Option<u64>> {
+    let mut buf = [0; 8192];
+    let mut to = end;
+    while to > start {
+        let from = to.saturating_sub(buf.len() as u64).max(start);
+        let block = &mut buf[..(to - from) as usize];
+        file.seek(SeekFrom::Start(from))?;
+        match file.read_exact(block) {
+            Ok(()) => {}
+            // Truncated since its length was taken: the next look sees it.
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        if let Some(i) = block.iter().rposition(|&b| b == b'\n') {
+            return Ok(Some(from + i as u64 + 1));
+        }
+        to = from;
+    }
+    Ok(None)
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> (u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.dev(), metadata.ino())
+}
+
+/// Without inodes, a replaced file can't be told from the one before.
+#[cfg(not(unix))]
+fn file_id(_: &Metadata) -> (u64, u64) {
+    (0, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Matcher};
+    use std::{env, process};
+
+    fn config(args: &[&str]) -> Config {
+        Config::new(["minigrep"].iter().chain(args).map(|s| s.to_string())).unwrap()
+    }
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("minigrep-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn append(path: &Path, text: &str) {
+        File::options().create(true).append(true).open(path).unwrap().write_all(text.as_bytes()).unwrap();
+    }
+
+    /// What searching the changes the way `step` does prints.
+    fn printed(config: &Config, step: impl FnOnce(&mut Printer<Vec<u8>>) -> io::Result<usize>) -> String {
+        let mut printer = Printer::new(Vec::new(), config, false);
+        step(&mut printer).unwrap();
+        String::from_utf8(printer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn follows_truncation_and_rotation() {
+        let dir = dir("follow");
+        let log = dir.join("log");
+        append(&log, "one frog\n");
+        let config = config(&["-n", "frog"]);
+        let matcher = Matcher::new(&config).unwrap();
+        let mut follower = Follower::new(Searcher::new(&matcher, &config));
+        follower.add(&log).unwrap();
+        assert!(follower.add(Path::new("-")).is_err());
+
+        assert_eq!("1:one frog\n", printed(&config, |p| follower.poll(p)));
+        assert_eq!("", printed(&config, |p| follower.poll(p)));
+
+        // A line isn't searched until it's finished.
+        append(&log, "two\nthree frog");
+        assert_eq!("", printed(&config, |p| follower.poll(p)));
+        append(&log, "!\n");
+        assert_eq!("3:three frog!\n", printed(&config, |p| follower.poll(p)));
+
+        fs::write(&log, "four frog\n").unwrap();
+        assert_eq!("1:four frog\n", printed(&config, |p| follower.poll(p)));
+
+        // The rest of the old file comes before the new one.
+        fs::rename(&log, dir.join("log.1")).unwrap();
+        append(&dir.join("log.1"), "five frog\n");
+        assert_eq!("2:five frog\n", printed(&config, |p| follower.poll(p)));
+        append(&dir.join("log.1"), "six frog\n");
+        append(&log, "seven frog\n");
+        assert_eq!("3:six frog\n1:seven frog\n", printed(&config, |p| follower.poll(p)));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn watches_new_writes_only() {
+        let dir = dir("watch");
+        append(&dir.join("old.log"), "an old frog\nand another\n");
+        fs::write(dir.join("ignored.log"), "").unwrap();
+        fs::write(dir.join(".gitignore"), "ignored.log\n").unwrap();
+        let config = config(&["-n", "--gitignore", "frog"]);
+        let matcher = Matcher::new(&config).unwrap();
+        let walker = Walker::new(&[], &[], true).unwrap();
+        let mut watcher = Watcher::new(Searcher::new(&matcher, &config), &walker, &[dir.to_string_lossy().into_owned()]).unwrap();
+
+        append(&dir.join("old.log"), "a new frog\n");
+        append(&dir.join("ignored.log"), "an ignored frog\n");
+        assert_eq!("3:a new frog\n", printed(&config, |p| watcher.wait(p)));
+
+        // New files and directories are searched from their start.
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        append(&dir.join("sub/new.log"), "frog one\nfrog two\n");
+        assert_eq!("1:frog one\n2:frog two\n", printed(&config, |p| watcher.wait(p)));
+
+        // Renamed files carry on where they were, as rotated logs do.
+        fs::rename(dir.join("old.log"), dir.join("old.log.1")).unwrap();
+        fs::rename(dir.join("sub"), dir.join("moved")).unwrap();
+        append(&dir.join("old.log.1"), "a last frog\n");
+        append(&dir.join("moved/new.log"), "frog three\n");
+        assert_eq!("4:a last frog\n3:frog three\n", printed(&config, |p| watcher.wait(p)));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
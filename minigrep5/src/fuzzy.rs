@@ -0,0 +1,237 @@
+//! Approximate matching, for `--fuzzy`.
+
+use crate::casefold;
+
+/// Finds the query in text with up to `max` typos: characters inserted,
+/// deleted or replaced, as counted by the edit distance.
+///
+/// Text is scanned a character at a time with Myers' bit-parallel algorithm,
+/// which keeps one column of the edit distance table in two machine words,
+/// so queries of up to 64 characters cost a few operations per character.
+/// Longer queries fall back to filling in the column one cell at a time.
+#[derive(Debug, Clone)]
+pub struct Fuzzy {
+    query: Vec<char>,
+    max: usize,
+    fold: bool,
+    /// Where each character occurs in the query, as a bit per position.
+    ascii: [u64; 128],
+    others: Vec<(char, u64)>,
+}
+
+/// One column of the edit distance table, after some of the text.
+enum Column {
+    /// The differences between neighbouring cells, as Myers keeps them:
+    /// which go up by one and which go down by one, and the bottom cell.
+    Bits { plus: u64, minus: u64, distance: usize },
+    Cells(Vec<usize>),
+}
+
+impl Fuzzy {
+    pub fn new(query: &str, max: usize, case_insensitive: bool) -> Fuzzy {
+        let fold = |c| if case_insensitive { casefold::simple(c) } else { c };
+        let query: Vec<char> = query.chars().map(fold).collect();
+
+        let mut ascii = [0; 128];
+        let mut others: Vec<(char, u64)> = Vec::new();
+        for (i, &c) in query.iter().enumerate().take(64) {
+            if c.is_ascii() {
+                ascii[c as usize] |= 1 << i;
+            } else {
+                match others.iter_mut().find(|(other, _)| *other == c) {
+                    Some((_, bits)) => *bits |= 1 << i,
+                    None => others.push((c, 1 << i)),
+                }
+            }
+        }
+        Fuzzy { query, max, fold: case_insensitive, ascii, others }
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.ends(text).any(|(_, distance)| distance <= self.max)
+    }
+
+    /// The fewest typos any match in `text` has, if there's a match.
+    pub fn distance(&self, text: &str) -> Option<usize> {
+        self.ends(text).map(|(_, distance)| distance).min().filter(|&distance| distance <= self.max)
+    }
+
+    /// Byte ranges of the matches in `text`, which don't overlap.
+    ///
+    /// Where a run of neighbouring places all end a close enough match,
+    /// the closest in each dip of the run is taken, and the longest of
+    /// those when there's a tie, so a match isn't cut short before a typo
+    /// at its end. The places after a dip, on the way back up, only end
+    /// worse versions of the same match.
+    pub fn find_iter(&self, text: &str) -> Vec<(usize, usize)> {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        let mut best: Option<(usize, usize)> = None;
+        let mut previous = usize::MAX;
+
+        for (end, distance) in self.ends(text).chain([(usize::MAX, usize::MAX)]) {
+            let rising = best.is_some_and(|(_, best)| distance > best);
+            if distance > self.max || rising {
+                if let Some((end, distance)) = best.take() {
+                    let start = self.start(text, end, distance);
+                    if ranges.last().is_none_or(|&(_, last)| start >= last) {
+                        ranges.push((start, end));
+                    }
+                }
+            } else if best.is_some() || previous > self.max || distance < previous {
+                best = Some((end, distance));
+            }
+            previous = distance;
+        }
+        ranges
+    }
+
+    /// Every place a close enough match ends in `text`, with where the
+    /// longest one ending there starts. These overlap, for `-w` and `-x`
+    /// to choose from.
+    pub fn occurrences(&self, text: &str) -> Vec<(usize, usize)> {
+        self.ends(text)
+            .filter(|&(_, distance)| distance <= self.max)
+            .map(|(end, distance)| (self.start(text, end, distance), end))
+            .collect()
+    }
+
+    /// Each place a match could end in `text`, as a byte offset, with the
+    /// fewest typos a match ending there has. The start of the text is
+    /// included, where only deleting the whole query would do.
+    fn ends<'a>(&'a self, text: &'a str) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let m = self.query.len();
+        let mut column = if m <= 64 {
+            Column::Bits { plus: u64::MAX, minus: 0, distance: m }
+        } else {
+            Column::Cells((0..=m).collect())
+        };
+
+        let first = std::iter::once((0, m));
+        first.chain(text.char_indices().map(move |(i, c)| {
+            let end = i + c.len_utf8();
+            let c = if self.fold { casefold::simple(c) } else { c };
+            (end, self.step(&mut column, c))
+        }))
+    }
+
+    /// Move `column` on past the text character `c`, returning the bottom
+    /// cell: the fewest typos of a match ending at `c`.
+    fn step(&self, column: &mut Column, c: char) -> usize {
+        match column {
+            Column::Bits { plus, minus, distance } => {
+                let m = self.query.len();
+                if m == 0 {
+                    return 0;
+                }
+                let last = 1 << (m - 1);
+                let equal = self.positions(c);
+
+                let x_vertical = equal | *minus;
+                let x_horizontal = ((equal & *plus).wrapping_add(*plus) ^ *plus) | equal;
+                let plus_horizontal = *minus | !(x_horizontal | *plus);
+                let minus_horizontal = *plus & x_horizontal;
+                if plus_horizontal & last != 0 {
+                    *distance += 1;
+                } else if minus_horizontal & last != 0 {
+                    *distance -= 1;
+                }
+
+                // Nothing is carried in at the top: a match can start anywhere.
+                let plus_horizontal = plus_horizontal << 1;
+                let minus_horizontal = minus_horizontal << 1;
+                *plus = minus_horizontal | !(x_vertical | plus_horizontal);
+                *minus = plus_horizontal & x_vertical;
+                *distance
+            }
+            Column::Cells(cells) => {
+                let mut diagonal = cells[0];
+                for (i, &q) in self.query.iter().enumerate() {
+                    let next = (cells[i + 1] + 1).min(cells[i] + 1).min(diagonal + usize::from(q != c));
+                    diagonal = cells[i + 1];
+                    cells[i + 1] = next;
+                }
+                cells[self.query.len()]
+            }
+        }
+    }
+
+    fn positions(&self, c: char) -> u64 {
+        if c.is_ascii() {
+            return self.ascii[c as usize];
+        }
+        self.others.iter().find(|&&(other, _)| other == c).map_or(0, |&(_, bits)| bits)
+    }
+
+    /// Where the longest match ending at byte `end` with `distance` typos
+    /// starts, found by filling in the table backwards from `end`.
+    fn start(&self, text: &str, end: usize, distance: usize) -> usize {
+        // cells[i] is the distance between the last i characters of the
+        // query and the text from the current place up to `end`.
+        let mut cells: Vec<usize> = (0..=self.query.len()).collect();
+        let mut start = end;
+
+        for (i, c) in text[..end].char_indices().rev() {
+            let c = if self.fold { casefold::simple(c) } else { c };
+            let mut diagonal = cells[0];
+            cells[0] += 1;
+            for (j, &q) in self.query.iter().rev().enumerate() {
+                let next = (cells[j + 1] + 1).min(cells[j] + 1).min(diagonal + usize::from(q != c));
+                diagonal = cells[j + 1];
+                cells[j + 1] = next;
+            }
+            if cells[self.query.len()] == distance {
+                start = i;
+            }
+            // Going further back can only make every cell worse.
+            if cells.iter().all(|&cell| cell > distance) {
+                break;
+            }
+        }
+        start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find<'a>(query: &str, max: usize, text: &'a str) -> Vec<&'a str> {
+        Fuzzy::new(query, max, false).find_iter(text).into_iter().map(|(start, end)| &text[start..end]).collect()
+    }
+
+    #[test]
+    fn distances() {
+        let fuzzy = Fuzzy::new("frog", 2, false);
+        assert_eq!(Some(0), fuzzy.distance("like a frog"));
+        assert_eq!(Some(1), fuzzy.distance("like a frg"));
+        assert_eq!(Some(1), fuzzy.distance("like a fog!"));
+        assert_eq!(Some(2), fuzzy.distance("like a grof"));
+        assert_eq!(Some(2), fuzzy.distance("like a dog"));
+        assert_eq!(None, Fuzzy::new("frog", 1, false).distance("like a dog"));
+        assert!(!fuzzy.is_match("toad"));
+        assert_eq!(Some(0), Fuzzy::new("FROG", 0, true).distance("Frog"));
+        assert_eq!(Some(1), Fuzzy::new("żółw", 1, false).distance("to zółw"));
+        // Deleting the whole query is a match too, if few enough typos.
+        assert_eq!(Some(2), Fuzzy::new("ab", 2, false).distance(""));
+    }
+
+    #[test]
+    fn match_ranges() {
+        assert_eq!(vec!["frog", "frg", "from"], find("frog", 1, "a frog, a frg and from"));
+        assert_eq!(vec!["hellx"], find("hello", 1, "hellx"));
+        assert_eq!(vec!["wörld"], find("world", 1, "hello wörld"));
+        // A close match right after an exact one doesn't hide it.
+        assert_eq!(vec!["to", "to", "ro"], find("to", 1, "to tomorrow"));
+        assert_eq!(vec!["to", "to"], find("to", 1, "toto"));
+    }
+
+    #[test]
+    fn long_queries() {
+        let query = "a".repeat(40) + &"b".repeat(40);
+        let text = "x".repeat(10) + &"a".repeat(39) + &"b".repeat(41) + "yyy";
+        let fuzzy = Fuzzy::new(&query, 3, false);
+        // One of the b's has to be an a.
+        assert_eq!(Some(1), fuzzy.distance(&text));
+        assert_eq!(vec![(10, 90)], fuzzy.find_iter(&text));
+    }
+}
@@ -0,0 +1,166 @@
+use std::error::Error;
+use std::fmt;
+
+/// A shell-style wildcard pattern for paths.
+///
+/// `?` matches one character and `*` any run of characters, neither of them
+/// crossing a `/`. `**` matches across directories, and `**/` also matches
+/// no directory at all, so `src/**/*.rs` finds `src/main.rs` too. `[abc]`,
+/// `[a-z]` and `[!a-z]` match one character from (or not from) a set, and
+/// `\` takes the next character literally.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Glob {
+    tokens: Vec<Token>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(char),
+    AnyChar,
+    Star,
+    /// `**`, which also matches `/`.
+    AnyPath,
+    /// `**/`: nothing, or any path ending in `/`.
+    AnyDirs,
+    Set { ranges: Vec<(char, char)>, negated: bool },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlobError {
+    pub pattern: String,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Glob, GlobError> {
+        let error = || GlobError { pattern: pattern.to_string() };
+        let mut tokens = Vec::new();
+        let mut chars = pattern.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '?' => Token::AnyChar,
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        Token::AnyDirs
+                    } else {
+                        Token::AnyPath
+                    }
+                }
+                '*' => Token::Star,
+                '\\' => Token::Literal(chars.next().ok_or_else(error)?),
+                '[' => {
+                    let negated = chars.next_if(|&c| c == '!' || c == '^').is_some();
+                    let mut ranges = Vec::new();
+                    loop {
+                        let lo = match chars.next() {
+                            Some(']') if !ranges.is_empty() => break,
+                            Some('\\') => chars.next().ok_or_else(error)?,
+                            Some(c) => c,
+                            None => return Err(error()),
+                        };
+                        let hi = if chars.next_if_eq(&'-').is_some() {
+                            match chars.next() {
+                                Some(']') | None => return Err(error()),
+                                Some(hi) => hi,
+                            }
+                        } else {
+                            lo
+                        };
+                        ranges.push((lo, hi));
+                    }
+                    Token::Set { ranges, negated }
+                }
+                c => Token::Literal(c),
+            };
+            tokens.push(token);
+        }
+
+        Ok(Glob { tokens })
+    }
+
+    /// Whether the whole of `path` matches. Paths use `/` as the separator.
+    pub fn matches(&self, path: &str) -> bool {
+        let text: Vec<char> = path.chars().collect();
+        // failed[t][i]: tokens from t can't match the text from i. Remembering
+        // dead ends keeps patterns full of stars from going exponential.
+        let mut failed = vec![vec![false; text.len() + 1]; self.tokens.len() + 1];
+        self.match_from(0, &text, 0, &mut failed)
+    }
+
+    fn match_from(&self, t: usize, text: &[char], i: usize, failed: &mut [Vec<bool>]) -> bool {
+        if failed[t][i] {
+            return false;
+        }
+
+        let matched = match self.tokens.get(t) {
+            None => i == text.len(),
+            Some(Token::Star) => (i..=text.len())
+                .take_while(|&j| j == i || text[j - 1] != '/')
+                .any(|j| self.match_from(t + 1, text, j, failed)),
+            Some(Token::AnyPath) => (i..=text.len()).any(|j| self.match_from(t + 1, text, j, failed)),
+            Some(Token::AnyDirs) => {
+                self.match_from(t + 1, text, i, failed)
+                    || (i..text.len()).any(|j| text[j] == '/' && self.match_from(t + 1, text, j + 1, failed))
+            }
+            Some(token) => match text.get(i) {
+                Some(&c) if token.matches(c) => self.match_from(t + 1, text, i + 1, failed),
+                _ => false,
+            },
+        };
+
+        if !matched {
+            failed[t][i] = true;
+        }
+        matched
+    }
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Literal(l) => *l == c,
+            Token::AnyChar => c != '/',
+            Token::Set { ranges, negated } => c != '/' && ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated,
+            Token::Star | Token::AnyPath | Token::AnyDirs => unreachable!("handled by Glob::match_from"),
+        }
+    }
+}
+
+impl fmt::Display for GlobError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid glob pattern: {}", self.pattern)
+    }
+}
+
+impl Error for GlobError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        Glob::new(pattern).unwrap().matches(path)
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*.rs", "lib.rs"));
+        assert!(!matches("*.rs", "src/lib.rs"));
+        assert!(matches("src/**/*.rs", "src/lib.rs"));
+        assert!(matches("src/**/*.rs", "src/regex/vm.rs"));
+        assert!(matches("**", "a/b/c"));
+        assert!(matches("poem.tx?", "poem.txt"));
+        assert!(matches("[a-c]*[!0-9]", "bar"));
+        assert!(!matches("[a-c]*[!0-9]", "bar1"));
+        assert!(matches("\\*", "*"));
+        assert!(!matches("a*a*a*a*a*b", &"a".repeat(60)));
+    }
+
+    #[test]
+    fn rejects_unclosed_sets() {
+        assert!(Glob::new("[abc").is_err());
+        assert!(Glob::new("[a-]").is_err());
+    }
+}
@@ -0,0 +1,331 @@
+use std::io::{self, BufRead, Read};
+
+/// How far back a DEFLATE stream can refer to what it already produced.
+const WINDOW: usize = 32 * 1024;
+
+/// Lengths and distances: the base value of each code and how many extra
+/// bits follow it.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// The order code length code lengths come in, in a dynamic block header.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Decompresses a raw DEFLATE stream (RFC 1951) as it's read.
+///
+/// Input is pulled from the inner reader only as far as it's needed, so
+/// whatever follows the stream, like a gzip trailer, is left in it.
+pub struct Inflater<R> {
+    input: Bits<R>,
+    /// The last `WINDOW` bytes handed out, for back references, followed
+    /// by what's been decoded but not read yet, from `pos` on.
+    out: Vec<u8>,
+    pos: usize,
+    block: Block,
+    /// Whether the current block is the last one.
+    last: bool,
+}
+
+enum Block {
+    /// The next block's header comes next.
+    Header,
+    /// Bytes of a stored block left to copy.
+    Stored(usize),
+    /// The literal/length and distance codes of a compressed block.
+    Huffman(Box<(Huffman, Huffman)>),
+    Done,
+}
+
+impl<R: BufRead> Inflater<R> {
+    pub fn new(inner: R) -> Inflater<R> {
+        Inflater { input: Bits { inner, bits: 0, count: 0 }, out: Vec::new(), pos: 0, block: Block::Header, last: false }
+    }
+
+    /// Get ready for another stream from the same reader.
+    pub fn reset(&mut self) {
+        self.input.bits = 0;
+        self.input.count = 0;
+        self.out.clear();
+        self.pos = 0;
+        self.block = Block::Header;
+        self.last = false;
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.input.inner
+    }
+
+    /// Decode until there's a window's worth of unread output or the
+    /// stream ends.
+    fn fill(&mut self) -> io::Result<()> {
+        if self.pos > 2 * WINDOW {
+            self.out.drain(..self.pos - WINDOW);
+            self.pos = WINDOW;
+        }
+
+        while self.out.len() - self.pos < WINDOW {
+            match &mut self.block {
+                Block::Done => break,
+                Block::Header if self.last => self.block = Block::Done,
+                Block::Header => {
+                    self.last = self.input.bits(1)? == 1;
+                    self.block = match self.input.bits(2)? {
+                        0 => self.input.stored()?,
+                        1 => Block::Huffman(Box::new(fixed())),
+                        2 => Block::Huffman(Box::new(self.input.dynamic()?)),
+                        _ => return Err(invalid("reserved block type")),
+                    };
+                }
+                Block::Stored(0) => self.block = Block::Header,
+                Block::Stored(left) => {
+                    let available = self.input.inner.fill_buf()?;
+                    if available.is_empty() {
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                    let n = available.len().min(*left);
+                    self.out.extend_from_slice(&available[..n]);
+                    self.input.inner.consume(n);
+                    *left -= n;
+                }
+                Block::Huffman(codes) => {
+                    let (literals, distances) = &**codes;
+                    let symbol = self.input.decode(literals)? as usize;
+                    if symbol < 256 {
+                        self.out.push(symbol as u8);
+                        continue;
+                    }
+                    if symbol == 256 {
+                        self.block = Block::Header;
+                        continue;
+                    }
+
+                    let code = symbol - 257;
+                    if code >= LENGTH_BASE.len() {
+                        return Err(invalid("invalid length code"));
+                    }
+                    let length = LENGTH_BASE[code] as usize + self.input.bits(LENGTH_EXTRA[code])? as usize;
+                    let code = self.input.decode(distances)? as usize;
+                    if code >= DISTANCE_BASE.len() {
+                        return Err(invalid("invalid distance code"));
+                    }
+                    let distance = DISTANCE_BASE[code] as usize + self.input.bits(DISTANCE_EXTRA[code])? as usize;
+                    if distance > self.out.len() {
+                        return Err(invalid("distance too far back"));
+                    }
+                    // The copy can overlap what it produces, so it goes a byte at a time.
+                    for _ in 0..length {
+                        self.out.push(self.out[self.out.len() - distance]);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for Inflater<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.out.len() {
+            self.fill()?;
+        }
+        let n = buf.len().min(self.out.len() - self.pos);
+        buf[..n].copy_from_slice(&self.out[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// A canonical Huffman code, as the number of codes of each length and the
+/// symbols in code order.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    /// The code with the given code length for each symbol, 0 meaning the
+    /// symbol isn't used.
+    fn new(lengths: &[u8]) -> io::Result<Huffman> {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // Codes of each length can only use what shorter ones left over.
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = left * 2 - count as i32;
+            if left < 0 {
+                return Err(invalid("over-subscribed code"));
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+}
+
+/// The codes of a block compressed with the fixed codes.
+fn fixed() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    let literals = Huffman::new(&lengths).expect("the fixed codes are valid");
+    let distances = Huffman::new(&[5; 30]).expect("the fixed codes are valid");
+    (literals, distances)
+}
+
+/// Input read a bit at a time, least significant bit first.
+struct Bits<R> {
+    inner: R,
+    bits: u32,
+    count: u32,
+}
+
+impl<R: BufRead> Bits<R> {
+    fn bits(&mut self, n: u8) -> io::Result<u32> {
+        let n = n as u32;
+        while self.count < n {
+            let mut byte = [0];
+            self.inner.read_exact(&mut byte)?;
+            self.bits |= (byte[0] as u32) << self.count;
+            self.count += 8;
+        }
+        let bits = self.bits & ((1 << n) - 1);
+        self.bits >>= n;
+        self.count -= n;
+        Ok(bits)
+    }
+
+    /// Read one symbol, a bit at a time, by walking the code lengths.
+    fn decode(&mut self, huffman: &Huffman) -> io::Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &huffman.counts[1..] {
+            code |= self.bits(1)? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(huffman.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("invalid Huffman code"))
+    }
+
+    /// Read a stored block's header, which starts at the next byte.
+    fn stored(&mut self) -> io::Result<Block> {
+        // Fewer than 8 bits are ever left over, all from the current byte.
+        self.bits = 0;
+        self.count = 0;
+        let mut header = [0; 4];
+        self.inner.read_exact(&mut header)?;
+        let length = u16::from_le_bytes([header[0], header[1]]);
+        if length != !u16::from_le_bytes([header[2], header[3]]) {
+            return Err(invalid("stored block length doesn't match its complement"));
+        }
+        Ok(Block::Stored(length as usize))
+    }
+
+    /// Read the codes a dynamic block's header describes.
+    fn dynamic(&mut self) -> io::Result<(Huffman, Huffman)> {
+        let literal_count = self.bits(5)? as usize + 257;
+        let distance_count = self.bits(5)? as usize + 1;
+        let code_length_count = self.bits(4)? as usize + 4;
+        if literal_count > 286 || distance_count > 30 {
+            return Err(invalid("too many length or distance codes"));
+        }
+
+        let mut code_lengths = [0u8; 19];
+        for &i in &CODE_LENGTH_ORDER[..code_length_count] {
+            code_lengths[i] = self.bits(3)? as u8;
+        }
+        let code_lengths = Huffman::new(&code_lengths)?;
+
+        // Both codes' lengths come as one sequence, which repeats can span.
+        let mut lengths = Vec::with_capacity(literal_count + distance_count);
+        while lengths.len() < literal_count + distance_count {
+            let (length, repeat) = match self.decode(&code_lengths)? {
+                length @ 0..=15 => (length as u8, 1),
+                16 => match lengths.last() {
+                    Some(&previous) => (previous, 3 + self.bits(2)?),
+                    None => return Err(invalid("repeat with no length to repeat")),
+                },
+                17 => (0, 3 + self.bits(3)?),
+                _ => (0, 11 + self.bits(7)?),
+            };
+            if lengths.len() + repeat as usize > literal_count + distance_count {
+                return Err(invalid("code lengths repeat past the end"));
+            }
+            lengths.extend(std::iter::repeat_n(length, repeat as usize));
+        }
+        if lengths[256] == 0 {
+            return Err(invalid("no code for the end of the block"));
+        }
+
+        let literals = Huffman::new(&lengths[..literal_count])?;
+        let distances = Huffman::new(&lengths[literal_count..])?;
+        Ok((literals, distances))
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid DEFLATE data: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inflate(data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        Inflater::new(data).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn stored_and_fixed_blocks() {
+        // A stored block of "abc", then an empty final block with fixed codes.
+        assert_eq!(b"abc", &inflate(&[0x00, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c', 0x03, 0x00]).unwrap()[..]);
+        // "aaaaaaaaaa" with fixed codes: a literal, then a copy of 9 from 1 back.
+        assert_eq!(b"aaaaaaaaaa", &inflate(&[0x4b, 0x4c, 0x84, 0x01, 0x00]).unwrap()[..]);
+    }
+
+    #[test]
+    fn dynamic_block_longer_than_the_window() {
+        // What zlib makes of 35,000 copies of "ab": a dynamic block of
+        // copies of 258, far more output than the window keeps.
+        let head = [0xed, 0xc2, 0x31, 0x0d, 0x00, 0x00, 0x00, 0x02, 0xa0, 0xac, 0xda, 0x3f, 0x84, 0x25, 0x3c, 0x19, 0xa4, 0x01];
+        let compressed = [&head[..], &[0; 67], &[0xce, 0x06]].concat();
+        assert_eq!(b"ab".repeat(35_000), inflate(&compressed).unwrap());
+    }
+
+    #[test]
+    fn rejects_broken_input() {
+        assert_eq!(io::ErrorKind::InvalidData, inflate(&[0x07]).unwrap_err().kind());
+        assert_eq!(io::ErrorKind::InvalidData, inflate(&[0x01, 0x03, 0x00, 0x00, 0x00]).unwrap_err().kind());
+        assert_eq!(io::ErrorKind::UnexpectedEof, inflate(&[0x01, 0x03, 0x00, 0xfc, 0xff, b'a']).unwrap_err().kind());
+        // "hello world" as a copy from a preset dictionary, which isn't there.
+        assert_eq!(io::ErrorKind::InvalidData, inflate(&[0xcb, 0x40, 0x30, 0x01]).unwrap_err().kind());
+    }
+}
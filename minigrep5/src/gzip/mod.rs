@@ -0,0 +1,197 @@
+//! Reading gzip files (RFC 1952), for `-z`.
+
+mod inflate;
+
+use std::io::{self, BufRead, Read};
+
+use inflate::Inflater;
+
+/// The first two bytes of every gzip member.
+const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// Header flags.
+const FHCRC: u8 = 1 << 1;
+const FEXTRA: u8 = 1 << 2;
+const FNAME: u8 = 1 << 3;
+const FCOMMENT: u8 = 1 << 4;
+
+/// Whether `bytes`, the start of some input, start the way gzip data does.
+pub fn is_gzip(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+/// Decompresses gzip data as it's read.
+///
+/// Members that follow one another, as `cat a.gz b.gz` makes, are read one
+/// after another. Each one's checksum and length are checked at its end.
+/// Anything after the last member that isn't another one is ignored, as
+/// gzip itself does.
+pub struct GzipReader<R> {
+    inflater: Inflater<R>,
+    state: State,
+    /// CRC-32 and length of the current member's output so far.
+    crc: u32,
+    size: u32,
+}
+
+enum State {
+    Header,
+    Body,
+    Done,
+}
+
+impl<R: BufRead> GzipReader<R> {
+    pub fn new(inner: R) -> GzipReader<R> {
+        GzipReader { inflater: Inflater::new(inner), state: State::Header, crc: 0, size: 0 }
+    }
+
+    fn header(&mut self) -> io::Result<()> {
+        let inner = self.inflater.get_mut();
+        let mut header = [0; 10];
+        inner.read_exact(&mut header)?;
+        if !is_gzip(&header) {
+            return Err(invalid("not gzip data"));
+        }
+        if header[2] != 8 {
+            return Err(invalid("unknown compression method"));
+        }
+
+        let flags = header[3];
+        if flags & FEXTRA != 0 {
+            let mut length = [0; 2];
+            inner.read_exact(&mut length)?;
+            io::copy(&mut inner.take(u16::from_le_bytes(length) as u64), &mut io::sink())?;
+        }
+        for flag in [FNAME, FCOMMENT] {
+            if flags & flag != 0 {
+                // Both are NUL-terminated.
+                let mut field = Vec::new();
+                if inner.read_until(0, &mut field)? == 0 || field.last() != Some(&0) {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+            }
+        }
+        if flags & FHCRC != 0 {
+            inner.read_exact(&mut [0; 2])?;
+        }
+
+        self.inflater.reset();
+        self.crc = 0;
+        self.size = 0;
+        Ok(())
+    }
+
+    /// Check the current member's trailer, and see whether another member
+    /// follows it.
+    fn trailer(&mut self) -> io::Result<State> {
+        let inner = self.inflater.get_mut();
+        let mut trailer = [0; 8];
+        inner.read_exact(&mut trailer)?;
+        if u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]) != self.crc {
+            return Err(invalid("checksum mismatch"));
+        }
+        if u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]) != self.size {
+            return Err(invalid("length mismatch"));
+        }
+        Ok(if is_gzip(inner.fill_buf()?) { State::Header } else { State::Done })
+    }
+}
+
+impl<R: BufRead> Read for GzipReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.state {
+                State::Header => {
+                    self.header()?;
+                    self.state = State::Body;
+                }
+                State::Body => {
+                    let n = self.inflater.read(buf)?;
+                    if n > 0 || buf.is_empty() {
+                        self.crc = crc32(self.crc, &buf[..n]);
+                        self.size = self.size.wrapping_add(n as u32);
+                        return Ok(n);
+                    }
+                    self.state = self.trailer()?;
+                }
+                State::Done => return Ok(0),
+            }
+        }
+    }
+}
+
+/// The CRC-32 remainders of every byte, for `crc32`.
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Continue the CRC-32 `crc` of some bytes with `bytes`.
+fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in bytes {
+        crc = CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid gzip data: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// "hello hello hello\n", compressed with the fixed codes.
+    const HELLO: &[u8] = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xcb\x48\xcd\xc9\xc9\x57\xc8\x40\x90\x5c\x00\x3b\x7c\x8a\xdf\x12\x00\x00\x00";
+    /// "stored\n" in a stored block, as zlib leaves it at level 0.
+    const STORED: &[u8] = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x04\x03\x01\x07\x00\xf8\xff\x73\x74\x6f\x72\x65\x64\x0a\xe2\x9c\x53\xa5\x07\x00\x00\x00";
+
+    fn gunzip(data: &[u8]) -> io::Result<String> {
+        let mut out = String::new();
+        GzipReader::new(data).read_to_string(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn checksums() {
+        assert_eq!(0, crc32(0, b""));
+        assert_eq!(0xcbf4_3926, crc32(0, b"123456789"));
+        assert_eq!(0xcbf4_3926, crc32(crc32(0, b"1234"), b"56789"));
+    }
+
+    #[test]
+    fn members_and_header_fields() {
+        assert_eq!("hello hello hello\n", gunzip(HELLO).unwrap());
+        assert_eq!("hello hello hello\nstored\n", gunzip(&[HELLO, STORED].concat()).unwrap());
+        assert_eq!("stored\n", gunzip(&[STORED, b"\0\0\0\0"].concat()).unwrap());
+
+        // The same member with a file name, a comment and an extra field.
+        let mut named = HELLO[..10].to_vec();
+        named[3] = FEXTRA | FNAME | FCOMMENT;
+        named.extend_from_slice(b"\x02\x00xyhello.txt\0a comment\0");
+        named.extend_from_slice(&HELLO[10..]);
+        assert_eq!("hello hello hello\n", gunzip(&named).unwrap());
+    }
+
+    #[test]
+    fn rejects_corruption() {
+        let mut corrupt = HELLO.to_vec();
+        corrupt[HELLO.len() - 8] ^= 1;
+        assert_eq!("invalid gzip data: checksum mismatch", gunzip(&corrupt).unwrap_err().to_string());
+        assert_eq!(io::ErrorKind::UnexpectedEof, gunzip(&HELLO[..20]).unwrap_err().kind());
+        assert_eq!(io::ErrorKind::InvalidData, gunzip(b"plain text").unwrap_err().kind());
+    }
+}
@@ -0,0 +1,431 @@
+//! A trigram index of a directory, made by `minigrep index` and used by
+//! `--index` to skip files that can't match.
+//!
+//! For every file the index keeps the set of trigrams, runs of three
+//! characters, that its lines contain once case-folded, along with the
+//! file's size and modification time. A literal query can only match in a
+//! file that has all of the query's trigrams, so the others don't have to
+//! be read at all. Folding first makes one index serve searches with and
+//! without `-i`.
+//!
+//! Files that have changed since they were indexed, or that are new, are
+//! always searched, so a stale index makes searches slower but never wrong.
+//! Running `minigrep index` again only reads the files that changed.
+//!
+//! The index lives in `.minigrep-index` at the top of the directory. It
+//! starts with a magic line and the time it was written, then has one
+//! record per file: its path relative to the directory, its modification
+//! time and size, and its trigrams, hashed to 32 bits and sorted, stored as
+//! the differences between neighbours. Every number is a LEB128 varint.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{casefold, decode, gzip, Config, Walker};
+
+pub const FILE_NAME: &str = ".minigrep-index";
+const MAGIC: &[u8] = b"minigrep index 1\n";
+
+/// A file modified this close to when it was indexed may have changed
+/// again without its modification time showing it, on file systems that
+/// only keep whole seconds, so it's read again rather than trusted.
+const RACY: Duration = Duration::from_secs(2);
+
+/// The trigrams some query needs: a file can match if it has all the
+/// trigrams of any one of the sets.
+pub type Requirements = Vec<Vec<u32>>;
+
+#[derive(Debug, Default)]
+pub struct Index {
+    /// When the index was written, as time since the Unix epoch.
+    written: Duration,
+    /// Keyed by the path relative to the indexed directory.
+    files: HashMap<String, Entry>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Entry {
+    modified: Duration,
+    size: u64,
+    /// Sorted trigrams, or `None` for files that aren't plain UTF-8, whose
+    /// text depends on how they're decoded.
+    trigrams: Option<Vec<u32>>,
+}
+
+/// What `update` did.
+#[derive(Debug, PartialEq)]
+pub struct Summary {
+    pub files: usize,
+    /// Files read because they were new or had changed.
+    pub read: usize,
+    /// Files that were in the old index but are gone.
+    pub removed: usize,
+}
+
+/// Bring the index of `dir` up to date with the files `walker` finds in
+/// it, creating it if there isn't one yet.
+pub fn update(dir: &str, walker: &Walker) -> io::Result<Summary> {
+    let root = Path::new(dir);
+    // An unreadable index is as good as none: it's rebuilt.
+    let mut old = match Index::load(root) {
+        Ok(index) => index.unwrap_or_default(),
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => Index::default(),
+        Err(e) => return Err(e),
+    };
+    let written = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut index = Index { written, files: HashMap::new() };
+    let mut read = 0;
+
+    // Files that can't be read are left out, and so always searched.
+    for path in walker.files(&[dir.to_string()]).into_iter().flatten() {
+        let Some(key) = key(root, &path) else { continue };
+        let Ok(metadata) = fs::metadata(&path) else { continue };
+        let Some(modified) = modified(&metadata) else { continue };
+
+        let entry = match old.files.remove(&key) {
+            Some(entry) if old.fresh(&entry, modified, metadata.len()) => entry,
+            _ => {
+                let Ok(trigrams) = file_trigrams(&path) else { continue };
+                read += 1;
+                Entry { modified, size: metadata.len(), trigrams }
+            }
+        };
+        index.files.insert(key, entry);
+    }
+
+    index.save(root)?;
+    // Whatever is left of the old index is for files that are gone.
+    Ok(Summary { files: index.files.len(), read, removed: old.files.len() })
+}
+
+/// The trigrams files need for `patterns` to match in them as `config`
+/// says, or `None` if the index can't tell. It can't for regular
+/// expressions, fuzzy or boolean queries, Turkish case folding or patterns
+/// shorter than three characters, nor when files without a match are
+/// wanted too, as with `-v`, `-L` and `-c`.
+pub fn requirements(patterns: &[String], config: &Config) -> Option<Requirements> {
+    if config.regex
+        || config.fuzzy.is_some()
+        || config.boolean
+        || config.turkic
+        || config.invert
+        || config.files_without_match
+        || config.count
+    {
+        return None;
+    }
+
+    let mut requirements = Vec::new();
+    for pattern in patterns {
+        let mut trigrams = Vec::new();
+        let mut last = Window::default();
+        for c in pattern.chars().flat_map(|c| casefold::full(c, false)) {
+            trigrams.extend(last.push(c));
+        }
+        if trigrams.is_empty() {
+            return None;
+        }
+        trigrams.sort_unstable();
+        trigrams.dedup();
+        requirements.push(trigrams);
+    }
+    Some(requirements)
+}
+
+/// Drop the files that the indexes of the directories in `roots` rule out.
+/// Directories without an index keep all their files.
+pub fn filter(roots: &[String], files: Vec<io::Result<PathBuf>>, requirements: &Requirements) -> Vec<io::Result<PathBuf>> {
+    let mut indexes = Vec::new();
+    for root in roots.iter().filter(|root| Path::new(root).is_dir()) {
+        match Index::load(Path::new(root)) {
+            Ok(Some(index)) => indexes.push((Path::new(root), index)),
+            Ok(None) => {}
+            Err(e) => eprintln!("Error while reading the index of {}, not using it: {}", root, e),
+        }
+    }
+
+    files
+        .into_iter()
+        .filter(|file| {
+            let Ok(path) = file else { return true };
+            indexes.iter().all(|(root, index)| {
+                key(root, path).is_none_or(|key| index.may_match(&key, path, requirements))
+            })
+        })
+        .collect()
+}
+
+impl Index {
+    /// The index of `dir`, if it has one.
+    pub fn load(dir: &Path) -> io::Result<Option<Index>> {
+        let data = match fs::read(dir.join(FILE_NAME)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut data = data.strip_prefix(MAGIC).ok_or_else(|| invalid("not a minigrep index"))?;
+
+        let written = duration(&mut data)?;
+        let count = varint(&mut data)?;
+        let mut files = HashMap::new();
+        for _ in 0..count {
+            let length = varint(&mut data)? as usize;
+            if data.len() < length {
+                return Err(invalid("truncated path"));
+            }
+            let (path, rest) = data.split_at(length);
+            let path = String::from_utf8(path.to_vec()).map_err(|_| invalid("path isn't UTF-8"))?;
+            data = rest;
+
+            let modified = duration(&mut data)?;
+            let size = varint(&mut data)?;
+            let trigrams = match varint(&mut data)? {
+                0 => None,
+                count => {
+                    let mut trigrams = Vec::new();
+                    let mut last = 0u32;
+                    for _ in 0..count - 1 {
+                        last = last.wrapping_add(varint(&mut data)? as u32);
+                        trigrams.push(last);
+                    }
+                    Some(trigrams)
+                }
+            };
+            files.insert(path, Entry { modified, size, trigrams });
+        }
+        Ok(Some(Index { written, files }))
+    }
+
+    /// Write the index into `dir`, replacing any old one in one step.
+    fn save(&self, dir: &Path) -> io::Result<()> {
+        let mut data = MAGIC.to_vec();
+        push_duration(&mut data, self.written);
+        push_varint(&mut data, self.files.len() as u64);
+
+        // In path order, so the same files always make the same index.
+        let mut paths: Vec<_> = self.files.keys().collect();
+        paths.sort();
+        for path in paths {
+            let entry = &self.files[path];
+            push_varint(&mut data, path.len() as u64);
+            data.extend_from_slice(path.as_bytes());
+            push_duration(&mut data, entry.modified);
+            push_varint(&mut data, entry.size);
+            // The count is off by one, so that 0 can mean there's no set.
+            match &entry.trigrams {
+                None => push_varint(&mut data, 0),
+                Some(trigrams) => {
+                    push_varint(&mut data, trigrams.len() as u64 + 1);
+                    let mut last = 0u32;
+                    for &trigram in trigrams {
+                        push_varint(&mut data, trigram.wrapping_sub(last) as u64);
+                        last = trigram;
+                    }
+                }
+            }
+        }
+
+        let path = dir.join(FILE_NAME);
+        let temp = dir.join(format!("{}.{}", FILE_NAME, process::id()));
+        let written = fs::write(&temp, data).and_then(|()| fs::rename(&temp, &path));
+        if written.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        written
+    }
+
+    /// Whether the file at `path`, known to the index as `key`, could
+    /// match, going by its entry if that's still up to date.
+    fn may_match(&self, key: &str, path: &Path, requirements: &Requirements) -> bool {
+        let Some(entry) = self.files.get(key) else { return true };
+        let Ok(metadata) = fs::metadata(path) else { return true };
+        let Some(modified) = modified(&metadata) else { return true };
+        if !self.fresh(entry, modified, metadata.len()) {
+            return true;
+        }
+        let Some(trigrams) = &entry.trigrams else { return true };
+
+        requirements
+            .iter()
+            .any(|needed| needed.iter().all(|trigram| trigrams.binary_search(trigram).is_ok()))
+    }
+
+    /// Whether `entry` still describes a file with the given time and size.
+    fn fresh(&self, entry: &Entry, modified: Duration, size: u64) -> bool {
+        entry.modified == modified && entry.size == size && modified + RACY < self.written
+    }
+}
+
+/// The last two characters seen, for making trigrams.
+#[derive(Default)]
+struct Window(Option<char>, Option<char>);
+
+impl Window {
+    /// Move on by `c`, returning the trigram it ends, if there are enough
+    /// characters for one yet.
+    fn push(&mut self, c: char) -> Option<u32> {
+        let trigram = match (self.0, self.1) {
+            (Some(a), Some(b)) => Some(trigram(a, b, c)),
+            _ => None,
+        };
+        *self = Window(self.1, Some(c));
+        trigram
+    }
+}
+
+fn trigram(a: char, b: char, c: char) -> u32 {
+    let packed = (a as u64) << 42 | (b as u64) << 21 | c as u64;
+    (packed.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32) as u32
+}
+
+/// The trigrams of every line of the file at `path`, decoded the way the
+/// searcher does, or `None` if it's UTF-16 or gzip-compressed.
+fn file_trigrams(path: &Path) -> io::Result<Option<Vec<u32>>> {
+    let mut reader = decode::buffer_start(BufReader::new(File::open(path)?), 2)?;
+    let start = reader.fill_buf()?;
+    if decode::utf16_bom(start).is_some() || gzip::is_gzip(start) {
+        return Ok(None);
+    }
+
+    let mut trigrams = HashSet::new();
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        let mut window = Window::default();
+        for c in String::from_utf8_lossy(&buf).chars().flat_map(|c| casefold::full(c, false)) {
+            trigrams.extend(window.push(c));
+        }
+    }
+
+    let mut trigrams: Vec<u32> = trigrams.into_iter().collect();
+    trigrams.sort_unstable();
+    Ok(Some(trigrams))
+}
+
+/// `path` relative to `root`, as the index knows it.
+fn key(root: &Path, path: &Path) -> Option<String> {
+    path.strip_prefix(root).ok()?.to_str().map(String::from)
+}
+
+fn modified(metadata: &Metadata) -> Option<Duration> {
+    metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()
+}
+
+fn push_varint(data: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        data.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    data.push(n as u8);
+}
+
+fn varint(data: &mut &[u8]) -> io::Result<u64> {
+    let mut n = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = data.split_first().ok_or_else(|| invalid("truncated"))?;
+        *data = rest;
+        n |= ((byte & 0x7f) as u64) << shift;
+        if byte < 0x80 {
+            return Ok(n);
+        }
+    }
+    Err(invalid("number too long"))
+}
+
+fn push_duration(data: &mut Vec<u8>, duration: Duration) {
+    push_varint(data, duration.as_secs());
+    push_varint(data, duration.subsec_nanos() as u64);
+}
+
+fn duration(data: &mut &[u8]) -> io::Result<Duration> {
+    let secs = varint(data)?;
+    let nanos = varint(data)?;
+    Ok(Duration::new(secs, nanos.min(999_999_999) as u32))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid index: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn config(args: &[&str]) -> Config {
+        Config::new(["minigrep"].iter().chain(args).map(|s| s.to_string())).unwrap()
+    }
+
+    /// Write `contents` to `path`, dated an hour back so the index trusts it.
+    fn write(path: &Path, contents: &str, age: u64) {
+        fs::write(path, contents).unwrap();
+        let file = File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(age)).unwrap();
+    }
+
+    fn candidates(root: &str, args: &[&str]) -> Vec<String> {
+        let config = config(args);
+        let files = Walker::new(&[], &[], false).unwrap().files(&[root.to_string()]);
+        let requirements = requirements(std::slice::from_ref(&config.query), &config).unwrap();
+        filter(&[root.to_string()], files, &requirements)
+            .into_iter()
+            .map(|path| path.unwrap().file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn query_requirements() {
+        assert_eq!(Some(1), requirements(&["frog".to_string()], &config(&["frog"])).map(|r| r.len()));
+        assert_eq!(Some(2), requirements(&["frog".to_string()], &config(&["frog"])).map(|r| r[0].len()));
+        // ß folds to ss, so it needs what "strasse" needs.
+        assert_eq!(
+            requirements(&["strasse".to_string()], &config(&["-i", "x"])),
+            requirements(&["STRAßE".to_string()], &config(&["-i", "x"]))
+        );
+        assert_eq!(None, requirements(&["fr".to_string()], &config(&["fr"])));
+        assert_eq!(None, requirements(&["frog".to_string()], &config(&["-E", "frog"])));
+        assert_eq!(None, requirements(&["frog".to_string()], &config(&["-v", "frog"])));
+    }
+
+    #[test]
+    fn narrows_and_updates_by_modification_time() {
+        let root = env::temp_dir().join(format!("minigrep-index-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sub")).unwrap();
+        write(&root.join("frog.txt"), "How public, like a FROG\n", 3600);
+        write(&root.join("sub/bog.txt"), "To an admiring bog!\n", 3600);
+        write(&root.join("gone.txt"), "frog\n", 3600);
+        let dir = root.to_str().unwrap();
+        let walker = Walker::new(&[], &[], false).unwrap();
+
+        assert_eq!(Summary { files: 3, read: 3, removed: 0 }, update(dir, &walker).unwrap());
+        assert_eq!(vec!["frog.txt", "gone.txt"], candidates(dir, &["-i", "frog"]));
+        // Case is folded in the index, so it only narrows case-blind.
+        assert_eq!(vec!["frog.txt", "gone.txt"], candidates(dir, &["FROG"]));
+        assert_eq!(Vec::<String>::new(), candidates(dir, &["toad"]));
+        // The index itself is never searched.
+        assert!(!candidates(dir, &["minigrep"]).contains(&FILE_NAME.to_string()));
+
+        // A changed file is searched even before the index is updated.
+        write(&root.join("sub/bog.txt"), "a toad\n", 1800);
+        assert_eq!(vec!["bog.txt"], candidates(dir, &["toad"]));
+        fs::remove_file(root.join("gone.txt")).unwrap();
+        write(&root.join("new.txt"), "toad\n", 3600);
+        assert_eq!(Summary { files: 3, read: 2, removed: 1 }, update(dir, &walker).unwrap());
+        assert_eq!(vec!["new.txt", "bog.txt"], candidates(dir, &["toad"]));
+
+        // Files too recently changed to trust aren't ruled out.
+        write(&root.join("frog.txt"), "now\n", 0);
+        update(dir, &walker).unwrap();
+        assert_eq!(vec!["frog.txt"], candidates(dir, &["frog"]));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+//! Linux's inotify, called straight through the C library, for `--watch`.
+
+use std::ffi::{CString, OsString};
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::raw::{c_char, c_int};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::Path;
+
+// Event masks, from <sys/inotify.h>.
+pub const IN_MODIFY: u32 = 0x2;
+pub const IN_MOVED_FROM: u32 = 0x40;
+pub const IN_MOVED_TO: u32 = 0x80;
+pub const IN_CREATE: u32 = 0x100;
+pub const IN_DELETE: u32 = 0x200;
+/// Events were dropped because the queue was full.
+pub const IN_Q_OVERFLOW: u32 = 0x4000;
+/// The watch is gone, because its directory is or because it was removed.
+pub const IN_IGNORED: u32 = 0x8000;
+pub const IN_ONLYDIR: u32 = 0x0100_0000;
+pub const IN_ISDIR: u32 = 0x4000_0000;
+
+/// The same as `O_CLOEXEC`.
+const IN_CLOEXEC: c_int = 0o2_000_000;
+
+/// The fixed part of `struct inotify_event`: the watch, mask, cookie and
+/// name length, each 32 bits. The name follows, padded with NULs.
+const HEADER: usize = 16;
+
+extern "C" {
+    fn inotify_init1(flags: c_int) -> c_int;
+    fn inotify_add_watch(fd: c_int, pathname: *const c_char, mask: u32) -> c_int;
+    fn inotify_rm_watch(fd: c_int, wd: c_int) -> c_int;
+}
+
+/// An inotify instance, which is closed when dropped.
+pub struct Inotify {
+    file: File,
+    buf: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Event {
+    /// The watch it's for, as `add_watch` returned it.
+    pub watch: i32,
+    pub mask: u32,
+    /// The same in both halves of a rename, and 0 in other events.
+    pub cookie: u32,
+    /// The name of the file in the watched directory it's about, if any.
+    pub name: Option<OsString>,
+}
+
+impl Inotify {
+    pub fn new() -> io::Result<Inotify> {
+        // SAFETY: inotify_init1 takes no pointers, and the descriptor it
+        // returns on success is ours alone to close.
+        let file = unsafe {
+            let fd = inotify_init1(IN_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            File::from_raw_fd(fd)
+        };
+        Ok(Inotify { file, buf: vec![0; 64 * 1024] })
+    }
+
+    /// Start watching `path` for the events in `mask`, returning the watch
+    /// its events will name. Watching a path again changes its mask.
+    pub fn add_watch(&self, path: &Path, mask: u32) -> io::Result<i32> {
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        // SAFETY: the path is NUL-terminated and outlives the call.
+        let watch = unsafe { inotify_add_watch(self.file.as_raw_fd(), path.as_ptr(), mask) };
+        if watch < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(watch)
+    }
+
+    pub fn remove_watch(&self, watch: i32) -> io::Result<()> {
+        // SAFETY: no pointers are involved.
+        if unsafe { inotify_rm_watch(self.file.as_raw_fd(), watch) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Wait for events, and return all those that have happened.
+    pub fn read_events(&mut self) -> io::Result<Vec<Event>> {
+        let read = loop {
+            match self.file.read(&mut self.buf) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                read => break read?,
+            }
+        };
+
+        let mut events = Vec::new();
+        let mut data = &self.buf[..read];
+        while data.len() >= HEADER {
+            let field = |i: usize| u32::from_ne_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+            let (watch, mask, cookie, length) = (field(0) as i32, field(4), field(8), field(12) as usize);
+            if data.len() < HEADER + length {
+                break;
+            }
+            let name = data[HEADER..HEADER + length].split(|&b| b == 0).next().unwrap_or_default();
+            let name = (!name.is_empty()).then(|| OsString::from_vec(name.to_vec()));
+            events.push(Event { watch, mask, cookie, name });
+            data = &data[HEADER + length..];
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    #[test]
+    fn reports_changes_in_a_directory() {
+        let dir = env::temp_dir().join(format!("minigrep-inotify-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut inotify = Inotify::new().unwrap();
+        let watch = inotify.add_watch(&dir, IN_CREATE | IN_MODIFY | IN_DELETE | IN_ONLYDIR).unwrap();
+        fs::write(dir.join("log"), "line\n").unwrap();
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::remove_file(dir.join("log")).unwrap();
+
+        let name = |name: &str| Some(OsString::from(name));
+        assert_eq!(
+            vec![
+                Event { watch, mask: IN_CREATE, cookie: 0, name: name("log") },
+                Event { watch, mask: IN_MODIFY, cookie: 0, name: name("log") },
+                Event { watch, mask: IN_CREATE | IN_ISDIR, cookie: 0, name: name("sub") },
+                Event { watch, mask: IN_DELETE, cookie: 0, name: name("log") },
+            ],
+            inotify.read_events().unwrap()
+        );
+
+        inotify.remove_watch(watch).unwrap();
+        assert_eq!(vec![Event { watch, mask: IN_IGNORED, cookie: 0, name: None }], inotify.read_events().unwrap());
+        assert!(inotify.add_watch(&dir.join("sub/none"), IN_CREATE).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
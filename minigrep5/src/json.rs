@@ -0,0 +1,124 @@
+//! The events `--json` prints instead of lines, one JSON object per line,
+//! as the search goes.
+//!
+//! Every event has a `type` and the `path` of the file it's about, which is
+//! `(standard input)` for standard input:
+//!
+//! - `begin`: the file is about to be searched.
+//! - `match`: a selected line. `line_number` counts from 1, `offset` is the
+//!   byte offset of the start of the line in the file and `text` is the
+//!   line without its line ending, decoded as the search decoded it.
+//!   `submatches` lists the matches in the line, each with its `start` and
+//!   `end` byte offsets in `text` and the `text` it covers. With `-v` the
+//!   selected lines don't match, so their `submatches` are empty. With
+//!   `--fuzzy`, `score` is the number of typos in the line's closest
+//!   match, so lower is better; otherwise it's left out.
+//! - `context`: a line around a selected one, for `-A`, `-B` and `-C`, with
+//!   the same fields as a `match`.
+//! - `end`: the file has been searched. `matches` is the number of selected
+//!   lines and `binary` whether the file was binary, in which case the lines
+//!   from the first NUL byte on weren't reported.
+//!
+//! For example, `minigrep --json -n frog poem.txt` prints
+//!
+//! ```text
+//! {"type":"begin","path":"poem.txt"}
+//! {"type":"match","path":"poem.txt","line_number":7,"offset":148,"text":"How public, like a frog","submatches":[{"start":19,"end":23,"text":"frog"}]}
+//! {"type":"end","path":"poem.txt","matches":1,"binary":false}
+//! ```
+//!
+//! The events of a file are never interleaved with another file's, even
+//! when searching on several threads. New fields may be added, but the
+//! existing ones won't change.
+
+use std::fmt::Write;
+use std::path::Path;
+
+pub fn begin(path: &Path) -> String {
+    let mut event = String::from("{\"type\":\"begin\",\"path\":");
+    string(&mut event, &path.display().to_string());
+    event.push('}');
+    event
+}
+
+/// A `match` event, or a `context` one when `context` is set.
+pub fn line(
+    context: bool,
+    path: &Path,
+    number: usize,
+    offset: usize,
+    text: &str,
+    ranges: &[(usize, usize)],
+    score: Option<usize>,
+) -> String {
+    let mut event = format!("{{\"type\":\"{}\",\"path\":", if context { "context" } else { "match" });
+    string(&mut event, &path.display().to_string());
+    let _ = write!(event, ",\"line_number\":{},\"offset\":{},\"text\":", number, offset);
+    string(&mut event, text);
+
+    event.push_str(",\"submatches\":[");
+    for (i, &(start, end)) in ranges.iter().enumerate() {
+        if i > 0 {
+            event.push(',');
+        }
+        let _ = write!(event, "{{\"start\":{},\"end\":{},\"text\":", start, end);
+        string(&mut event, &text[start..end]);
+        event.push('}');
+    }
+    event.push(']');
+    if let Some(score) = score {
+        let _ = write!(event, ",\"score\":{}", score);
+    }
+    event.push('}');
+    event
+}
+
+pub fn end(path: &Path, matches: usize, binary: bool) -> String {
+    let mut event = String::from("{\"type\":\"end\",\"path\":");
+    string(&mut event, &path.display().to_string());
+    let _ = write!(event, ",\"matches\":{},\"binary\":{}}}", matches, binary);
+    event
+}
+
+/// Append `s` to `out` as a JSON string.
+fn string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' || c == '\u{7f}' => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events() {
+        let path = Path::new("dir/a \"b\".txt");
+        assert_eq!(r#"{"type":"begin","path":"dir/a \"b\".txt"}"#, begin(path));
+        assert_eq!(
+            r#"{"type":"match","path":"dir/a \"b\".txt","line_number":3,"offset":10,"text":"żółw\t\\ \u0000","submatches":[{"start":0,"end":2,"text":"ż"},{"start":8,"end":9,"text":"\\"}]}"#,
+            line(false, path, 3, 10, "żółw\t\\ \0", &[(0, 2), (8, 9)], None)
+        );
+        assert_eq!(
+            r#"{"type":"context","path":"x","line_number":1,"offset":0,"text":"","submatches":[]}"#,
+            line(true, Path::new("x"), 1, 0, "", &[], None)
+        );
+        assert_eq!(
+            r#"{"type":"match","path":"x","line_number":1,"offset":0,"text":"frg","submatches":[{"start":0,"end":3,"text":"frg"}],"score":1}"#,
+            line(false, Path::new("x"), 1, 0, "frg", &[(0, 3)], Some(1))
+        );
+        assert_eq!(r#"{"type":"end","path":"x","matches":2,"binary":true}"#, end(Path::new("x"), 2, true));
+    }
+}
@@ -1,7 +1,54 @@
-use std::fs;
+use std::collections::BTreeMap;
+use std::fs::File;
 use std::error::Error;
-use std::process;
 use std::env;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use args::Arg;
+use expr::Expr;
+use follow::Follower;
+#[cfg(target_os = "linux")]
+use follow::Watcher;
+use pool::ThreadPool;
+
+mod args;
+mod casefold;
+mod colors;
+mod decode;
+mod expr;
+mod follow;
+mod fuzzy;
+mod glob;
+mod gzip;
+mod index;
+#[cfg(target_os = "linux")]
+mod inotify;
+mod json;
+mod literal;
+mod pool;
+mod printer;
+mod regex;
+mod replace;
+mod searcher;
+mod walk;
+
+pub use args::{usage, ArgsError};
+pub use casefold::Caseless;
+pub use colors::Colors;
+pub use expr::ExprError;
+pub use fuzzy::Fuzzy;
+pub use glob::{Glob, GlobError};
+pub use literal::{AhoCorasick, Finder, Horspool};
+use regex::is_word_char;
+pub use printer::Printer;
+pub use regex::{Regex, RegexError};
+pub use replace::Replacer;
+pub use searcher::Searcher;
+pub use walk::Walker;
 
 //Working with Environment Variables
 
@@ -18,13 +65,141 @@ use std::env;
 //Look down at the end of lib.rs!
 
 impl Config {
-    pub fn new(args: &Vec<String>) -> Result<Config, &'static str> {
-        if args.len() < 3 {
-            return Err("Not enough arguments provided!");
+    /// Build the configuration from the command line, program name first.
+    ///
+    /// With `--help` or `--version` no query is needed, and `help` or
+    /// `version` is set instead.
+    pub fn new<I: IntoIterator<Item = String>>(args: I) -> Result<Config, ArgsError> {
+        Config::from_args(args, |name| env::var(name).ok())
+    }
+
+    /// Like `new`, but with environment variables looked up through `var`,
+    /// so what they do can be tried without touching the real ones.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I, var: impl Fn(&str) -> Option<String>) -> Result<Config, ArgsError> {
+        let mut config = Config::default();
+        let mut ignore_case = None;
+        let mut positional = Vec::new();
+
+        // `minigrep index` takes directories rather than a query.
+        let mut args = args.into_iter().skip(1).peekable();
+        if args.peek().is_some_and(|arg| arg == "index") {
+            args.next();
+            config.build_index = true;
+        }
+
+        for arg in args::parse(args)? {
+            let (name, value) = match arg {
+                Arg::Positional(arg) => {
+                    positional.push(arg);
+                    continue;
+                }
+                Arg::Opt(name, value) => (name, value),
+            };
+
+            match name {
+                "extended-regexp" => config.regex = true,
+                "ignore-case" => ignore_case = Some(true),
+                "no-ignore-case" => ignore_case = Some(false),
+                "turkic" => config.turkic = true,
+                "regexp" => config.patterns.extend(value),
+                "boolean" => config.boolean = true,
+                "fuzzy" => config.fuzzy = Some(args::number(name, value)?),
+                "text" => config.text = true,
+                "decompress" => config.decompress = true,
+                // -x asks for more than -w, so it wins whatever the order.
+                "word-regexp" if config.bounds != Bounds::Line => config.bounds = Bounds::Words,
+                "word-regexp" => {}
+                "line-regexp" => config.bounds = Bounds::Line,
+                "file" => config.pattern_files.extend(value),
+                "replace" => config.replace = value,
+                "write" => config.write = true,
+                "invert-match" => config.invert = true,
+                "max-count" => config.max_count = Some(args::number(name, value)?),
+                "line-number" => config.line_number = true,
+                "byte-offset" => config.byte_offset = true,
+                "after-context" => config.after_context = args::number(name, value)?,
+                "before-context" => config.before_context = args::number(name, value)?,
+                "context" => {
+                    let lines = args::number(name, value)?;
+                    config.before_context = lines;
+                    config.after_context = lines;
+                }
+                "count" => config.count = true,
+                "files-with-matches" => config.files_with_matches = true,
+                "files-without-match" => config.files_without_match = true,
+                "include" => config.include.extend(value),
+                "exclude" => config.exclude.extend(value),
+                "gitignore" => config.gitignore = true,
+                "index" => config.use_index = true,
+                "follow" => config.follow = true,
+                "watch" => config.watch = true,
+                "threads" => {
+                    config.threads = args::number(name, value)?;
+                    if config.threads == 0 {
+                        return Err(ArgsError::InvalidValue("--threads".to_string(), "0".to_string()));
+                    }
+                }
+                "sort-files" => config.sort_files = true,
+                "json" => config.json = true,
+                "color" => {
+                    config.color = match value.as_deref() {
+                        None | Some("auto") => Color::Auto,
+                        Some("always") => Color::Always,
+                        Some("never") => Color::Never,
+                        _ => return Err(ArgsError::InvalidValue("--color".to_string(), value.unwrap_or_default())),
+                    }
+                }
+                "help" => config.help = true,
+                "version" => config.version = true,
+                _ => unreachable!("every option in args::OPTIONS is handled"),
+            }
         }
-        let query = args[1].clone();
-        let file_name = args[2].clone();
-        let case_sensitive = env::var("CASE_INSENSITIVE").is_err();
+
+        if config.help || config.version {
+            return Ok(config);
+        }
+        if config.build_index {
+            config.paths = if positional.is_empty() { vec![".".to_string()] } else { positional };
+            return Ok(config);
+        }
+        if config.regex && config.fuzzy.is_some() {
+            return Err(ArgsError::Conflicts("--extended-regexp".to_string(), "--fuzzy".to_string()));
+        }
+        if config.write && config.replace.is_none() {
+            return Err(ArgsError::Requires("--write".to_string(), "--replace".to_string()));
+        }
+        if config.follow || config.watch {
+            // These are about whole files, and followed files never end.
+            let mode = if config.follow { "--follow" } else { "--watch" };
+            let whole_files = [
+                (config.follow && config.watch, "--watch"),
+                (config.count, "--count"),
+                (config.files_with_matches, "--files-with-matches"),
+                (config.files_without_match, "--files-without-match"),
+                (config.max_count.is_some(), "--max-count"),
+                (config.replace.is_some(), "--replace"),
+                (config.decompress, "--decompress"),
+            ];
+            if let Some((_, other)) = whole_files.iter().find(|(set, _)| *set) {
+                return Err(ArgsError::Conflicts(mode.to_string(), other.to_string()));
+            }
+        }
+        // Patterns from -e and -f take the place of the query.
+        if config.patterns.is_empty() && config.pattern_files.is_empty() {
+            if positional.is_empty() {
+                return Err(ArgsError::MissingQuery);
+            }
+            config.query = positional.remove(0);
+        }
+        // Without any paths, standard input is searched.
+        config.paths = if positional.is_empty() { vec!["-".to_string()] } else { positional };
+
+        // A flag beats the environment variable, whichever way it points.
+        if let Some(ignore_case) = ignore_case {
+            config.case_sensitive = !ignore_case;
+            return Ok(config);
+        }
+        config.case_sensitive = var("CASE_INSENSITIVE").is_none();
         //Here, we create a new variable case_sensitive. 
         //To set its value, we call the env::var function and pass it the name of the CASE_INSENSITIVE environment variable. 
         //The env::var function returns a Result that will be the successful Ok variant that contains the value of the environment variable if the environment variable is set. 
@@ -34,7 +209,7 @@ impl Config {
         //We don’t care about the value of the environment variable, just whether it’s set or unset, 
         //so we’re checking is_err rather than using unwrap, expect, or any of the other methods we’ve seen on Result.
         //We pass the value in the case_sensitive variable to the Config instance so the run function can read that value and decide whether to call search or search_case_insensitive
-        Ok(Config {query, file_name, case_sensitive})
+        Ok(config)
     }
     //First, we’ll run our program without the environment variable set and with the query 'to', 
     //which should match any line that contains the word “to” in all lowercase.
@@ -49,64 +224,551 @@ impl Config {
     //Now you know how to manage options set using either command line arguments or environment variables.
 }
 
-pub fn run(config: &Config) -> Result<(), Box<dyn Error>>{
-    let contents = fs::read_to_string(&config.file_name).unwrap_or_else(|err| {
-        println!("Error while trying to open the file: {}", err);
-        process::exit(1);
-    });
+/// When to colour the output.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Color {
+    /// Only when writing to a terminal.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// How much of a line a match has to cover.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Bounds {
+    /// Any part of it.
+    #[default]
+    Any,
+    /// Whole words, with no word character right before or after, for `-w`.
+    Words,
+    /// The whole line, for `-x`.
+    Line,
+}
+
+/// How a search went, which decides the exit code, following grep.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Matched,
+    NoMatch,
+    /// Some file couldn't be searched. This wins over finding matches.
+    Error,
+}
+
+impl Status {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Status::Matched => 0,
+            Status::NoMatch => 1,
+            Status::Error => 2,
+        }
+    }
+}
 
-    let results = if config.case_sensitive {
-        search(&config.query, &contents)
-    }else {
-        search_case_insensitive(&config.query, &contents)
+pub fn run(config: &Config) -> Result<Status, Box<dyn Error>>{
+    let walker = Walker::new(&config.include, &config.exclude, config.gitignore)?;
+    if config.build_index {
+        return build_indexes(config, &walker);
+    }
+    let patterns = if config.patterns.is_empty() && config.pattern_files.is_empty() {
+        vec![config.query.clone()]
+    } else {
+        let mut patterns = config.patterns.clone();
+        patterns.extend(read_patterns(&config.pattern_files)?);
+        patterns
     };
+    let matcher = Matcher::with_patterns(&patterns, config)?;
+
+    // "-" stands for standard input; everything else may be a directory.
+    let mut files = Vec::new();
+    for path in &config.paths {
+        if path == "-" {
+            files.push(Ok(PathBuf::from("-")));
+        } else if !config.watch {
+            // --watch walks the directories itself as it starts watching.
+            files.extend(walker.files(std::slice::from_ref(path)));
+        }
+    }
 
-    for line in results {
-        println!("{}", line);
+    if config.use_index {
+        if let Some(requirements) = index::requirements(&patterns, config) {
+            files = index::filter(&config.paths, files, &requirements);
+        }
     }
-    Ok(())
+
+    // Matches are prefixed with their path as soon as there's more than one
+    // file they could have come from.
+    let with_path = files.len() > 1 || config.paths.iter().any(|path| Path::new(path).is_dir());
+    let stdout = io::stdout();
+    let mut printer = Printer::new(stdout.lock(), config, with_path);
+    let colored = match config.color {
+        Color::Always => true,
+        Color::Never => false,
+        Color::Auto => stdout.is_terminal() && env::var("TERM").map_or(true, |term| term != "dumb"),
+    };
+    if colored && !config.json {
+        printer = printer.with_colors(Colors::parse(&env::var("GREP_COLORS").unwrap_or_default()));
+    }
+
+    if config.replace.is_some() {
+        return replace_files(config, &matcher, files);
+    }
+    if config.follow {
+        return follow_files(config, &matcher, files, &mut printer);
+    }
+    if config.watch {
+        return watch_dirs(config, &matcher, &walker, &mut printer);
+    }
+    search_files(config, matcher, files, &mut printer)
 }
 
-pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    let mut results = Vec::new();
-    for lines in contents.lines() {
-        if lines.contains(query) {
-            results.push(lines);
+/// Bring the index of each path up to date, for `minigrep index`.
+fn build_indexes(config: &Config, walker: &Walker) -> Result<Status, Box<dyn Error>> {
+    let mut failed = false;
+    for dir in &config.paths {
+        if !Path::new(dir).is_dir() {
+            eprintln!("Error while indexing: {}: not a directory", dir);
+            failed = true;
+            continue;
+        }
+        match index::update(dir, walker) {
+            Ok(summary) => println!(
+                "{}: {} files indexed, {} read, {} removed",
+                dir, summary.files, summary.read, summary.removed
+            ),
+            Err(err) => {
+                eprintln!("Error while indexing: {}: {}", dir, err);
+                failed = true;
+            }
         }
     }
-    results
+    Ok(if failed { Status::Error } else { Status::Matched })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Search `files`, and then what's added to them for as long as the
+/// program runs, for `--follow`.
+fn follow_files<W: Write>(
+    config: &Config,
+    matcher: &Matcher,
+    files: Vec<io::Result<PathBuf>>,
+    printer: &mut Printer<W>,
+) -> Result<Status, Box<dyn Error>> {
+    let mut follower = Follower::new(Searcher::new(matcher, config));
+    let mut failed = false;
+    for file in files {
+        let added = file.and_then(|path| {
+            follower.add(&path).map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))
+        });
+        if let Err(err) = added {
+            eprintln!("Error while trying to open the file: {}", err);
+            failed = true;
+        }
+    }
+    if follower.is_empty() {
+        return Ok(if failed { Status::Error } else { Status::NoMatch });
+    }
 
-    #[test]
-    fn case_sensitive() {
-        let query = "duct";
-        let contents = "\
-Rust:
-safe, fast, productive.
-Pick three.
-Duct tape.";
+    loop {
+        follower.poll(printer)?;
+        thread::sleep(follow::INTERVAL);
+    }
+}
 
-        assert_eq!(vec!["safe, fast, productive."], search(query, contents));
+/// Search what's written under the directories in `config.paths` for as
+/// long as the program runs, for `--watch`.
+#[cfg(target_os = "linux")]
+fn watch_dirs<W: Write>(config: &Config, matcher: &Matcher, walker: &Walker, printer: &mut Printer<W>) -> Result<Status, Box<dyn Error>> {
+    let mut watcher = Watcher::new(Searcher::new(matcher, config), walker, &config.paths)?;
+    loop {
+        watcher.wait(printer)?;
     }
+}
 
-    #[test]
-    fn case_insensitive() {
-        let query = "RuSt";
-        let contents = "\
-Rust:
-safe, fast, productive.
-Pick three.
-Trust me.";
-        assert_eq!(
-            vec!["Rust:", "Trust me."],
-            search_case_insensitive(query, contents)
-        );
-        
+#[cfg(not(target_os = "linux"))]
+fn watch_dirs<W: Write>(_: &Config, _: &Matcher, _: &Walker, _: &mut Printer<W>) -> Result<Status, Box<dyn Error>> {
+    Err("--watch needs Linux's inotify".into())
+}
+
+/// Replace the matches in `files` one after another, for `--replace`.
+fn replace_files(config: &Config, matcher: &Matcher, files: Vec<io::Result<PathBuf>>) -> Result<Status, Box<dyn Error>> {
+    let replacer = Replacer::new(matcher, config);
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut failed = false;
+    let mut replaced = false;
+
+    for file in files {
+        let changed = file.and_then(|path| {
+            replacer
+                .replace(&path, &mut out)
+                .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))
+        });
+        match changed {
+            Ok(changed) => replaced |= changed,
+            Err(err) => {
+                eprintln!("Error while replacing: {}", err);
+                failed = true;
+            }
+        }
     }
+
+    Ok(if failed {
+        Status::Error
+    } else if replaced {
+        Status::Matched
+    } else {
+        Status::NoMatch
+    })
+}
+
+/// Search `files` one after another, or `config.threads` at a time on a
+/// pool of workers. Either way the output of each file is printed in one
+/// piece, and with `sort_files` the files come out in the order given.
+fn search_files<W: Write>(
+    config: &Config,
+    matcher: Matcher,
+    files: Vec<io::Result<PathBuf>>,
+    printer: &mut Printer<W>,
+) -> Result<Status, Box<dyn Error>> {
+    let mut failed = false;
+    let mut matched = false;
+    let mut record = |searched: io::Result<bool>| match searched {
+        Ok(found) => matched |= found,
+        Err(err) => {
+            eprintln!("Error while trying to open the file: {}", err);
+            failed = true;
+        }
+    };
+
+    if config.threads <= 1 {
+        let searcher = Searcher::new(&matcher, config);
+        for file in files {
+            record(file.and_then(|path| search_file(&path, &searcher, printer, config)));
+        }
+    } else {
+        let pool = ThreadPool::new(config.threads)?;
+        let matcher = Arc::new(matcher);
+        let shared = Arc::new(config.clone());
+        let (sender, receiver) = mpsc::channel();
+
+        for (i, file) in files.into_iter().enumerate() {
+            let (matcher, config, sender) = (Arc::clone(&matcher), Arc::clone(&shared), sender.clone());
+            let mut buffer = printer.buffer();
+            pool.execute(move || {
+                // A search that panics fails its file, rather than leaving
+                // a gap that every file sorted after it would wait on.
+                let name = file.as_ref().map_or(String::new(), |path| format!("{}: ", path.display()));
+                let searched = panic::catch_unwind(AssertUnwindSafe(|| {
+                    let searcher = Searcher::new(&matcher, &config);
+                    file.and_then(|path| search_file(&path, &searcher, &mut buffer, &config))
+                }))
+                .unwrap_or_else(|_| Err(io::Error::other(format!("{}the search failed unexpectedly", name))));
+                // The receiver is only gone once printing has failed anyway.
+                let _ = sender.send((i, searched, buffer));
+            });
+        }
+        drop(sender);
+
+        // Files that finish ahead of their turn wait here when sorting.
+        let mut waiting = BTreeMap::new();
+        let mut next = 0;
+        for (i, searched, buffer) in receiver {
+            if !config.sort_files {
+                printer.append(buffer)?;
+                record(searched);
+                continue;
+            }
+            waiting.insert(i, (searched, buffer));
+            while let Some((searched, buffer)) = waiting.remove(&next) {
+                printer.append(buffer)?;
+                record(searched);
+                next += 1;
+            }
+        }
+    }
+
+    Ok(if failed {
+        Status::Error
+    } else if matched {
+        Status::Matched
+    } else {
+        Status::NoMatch
+    })
+}
+
+/// Every line of the given pattern files, `-` being standard input.
+fn read_patterns(files: &[String]) -> io::Result<Vec<String>> {
+    let mut patterns = Vec::new();
+    for file in files {
+        let reader: Box<dyn BufRead> = if file == "-" {
+            Box::new(io::stdin().lock())
+        } else {
+            let file = File::open(file).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", file, e)))?;
+            Box::new(BufReader::new(file))
+        };
+        for line in reader.lines() {
+            patterns.push(line?);
+        }
+    }
+    Ok(patterns)
+}
+
+/// Search one file, or standard input for `-`, and print whatever the
+/// options ask for. Returns whether the file counts towards success.
+fn search_file<W: Write>(path: &Path, searcher: &Searcher, printer: &mut Printer<W>, config: &Config) -> io::Result<bool> {
+    let (reader, label): (Box<dyn BufRead>, _) = if path == Path::new("-") {
+        (Box::new(io::stdin().lock()), Path::new("(standard input)"))
+    } else {
+        (Box::new(BufReader::new(File::open(path)?)), path)
+    };
+    let count = searcher
+        .search(reader, label, printer)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", label.display(), err)))?;
+
+    // With -L, success means some file was listed.
+    if config.files_with_matches {
+        if count > 0 {
+            printer.path()?;
+        }
+    } else if config.files_without_match {
+        if count == 0 {
+            printer.path()?;
+        }
+        return Ok(count == 0);
+    } else if config.count {
+        printer.count(count)?;
+    }
+    Ok(count > 0)
+}
+
+/// What to look for in each line.
+pub enum Matcher {
+    Literal(Finder),
+    /// Several literals, as read with `-f`, searched for in one pass.
+    Literals(AhoCorasick),
+    /// Literals compared case-insensitively, with full case folding.
+    CaseInsensitive(Vec<Caseless>),
+    Regex(Regex),
+    /// Literals matched with typos, for `--fuzzy`.
+    Fuzzy(Vec<Fuzzy>),
+    /// A boolean expression of other matchers, for `--boolean`.
+    Expression(Box<Expr<Matcher>>),
+    /// Literals whose matches have to cover whole words or lines. Every
+    /// occurrence is tried, overlapping ones too, since a longer one that
+    /// doesn't fit can hide a shorter one that does.
+    Bounded(Box<Matcher>, Bounds),
+}
+
+impl Matcher {
+    /// Match the query, or the patterns given with `-e`.
+    pub fn new(config: &Config) -> Result<Matcher, Box<dyn Error>> {
+        match config.patterns.is_empty() {
+            true => Matcher::with_patterns(std::slice::from_ref(&config.query), config),
+            false => Matcher::with_patterns(&config.patterns, config),
+        }
+    }
+
+    /// Match lines that any of `patterns` matches, the way `config` says.
+    ///
+    /// With `boolean` set, each pattern is an expression, whose patterns
+    /// are matched the way `config` says one at a time.
+    pub fn with_patterns(patterns: &[String], config: &Config) -> Result<Matcher, Box<dyn Error>> {
+        if !config.boolean || patterns.is_empty() {
+            return Ok(Matcher::plain(patterns, config)?);
+        }
+
+        let mut exprs = Vec::new();
+        for pattern in patterns {
+            exprs.push(expr::parse(pattern)?.try_map(&mut |pattern| Matcher::plain(&[pattern], config))?);
+        }
+        let expr = if exprs.len() == 1 { exprs.remove(0) } else { Expr::Or(exprs) };
+        Ok(Matcher::Expression(Box::new(expr)))
+    }
+
+    fn plain(patterns: &[String], config: &Config) -> Result<Matcher, RegexError> {
+        // Like grep, an empty pattern file matches nothing, in any mode.
+        if patterns.is_empty() {
+            return Ok(Matcher::Literals(AhoCorasick::new(patterns)));
+        }
+
+        let pattern = match patterns {
+            [pattern] => pattern.clone(),
+            _ => patterns.iter().map(|p| format!("(?:{})", p)).collect::<Vec<_>>().join("|"),
+        };
+        let matcher = match (config.regex, config.case_sensitive) {
+            _ if config.fuzzy.is_some() => Matcher::Fuzzy(
+                patterns.iter().map(|p| Fuzzy::new(p, config.fuzzy.unwrap_or(0), !config.case_sensitive)).collect(),
+            ),
+            (true, case_sensitive) => return Ok(Matcher::Regex(Regex::with_bounds(&pattern, !case_sensitive, config.bounds)?)),
+            (false, true) if patterns.len() == 1 => Matcher::Literal(Finder::new(&patterns[0])),
+            (false, true) => Matcher::Literals(AhoCorasick::new(patterns)),
+            (false, false) => {
+                Matcher::CaseInsensitive(patterns.iter().map(|p| Caseless::new(p, config.turkic)).collect())
+            }
+        };
+        Ok(match config.bounds {
+            Bounds::Any => matcher,
+            bounds => Matcher::Bounded(Box::new(matcher), bounds),
+        })
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        match self {
+            Matcher::Literal(finder) => finder.is_match(line),
+            Matcher::Literals(automaton) => automaton.is_match(line),
+            Matcher::CaseInsensitive(caseless) => caseless.iter().any(|caseless| caseless.is_match(line)),
+            Matcher::Regex(regex) => regex.is_match(line),
+            Matcher::Fuzzy(fuzzy) => fuzzy.iter().any(|fuzzy| fuzzy.is_match(line)),
+            Matcher::Expression(expr) => expr.eval(&mut |matcher| matcher.is_match(line)),
+            Matcher::Bounded(matcher, bounds) => matcher.occurrences(line).into_iter().any(|range| fits(line, range, *bounds)),
+        }
+    }
+
+    /// Byte ranges of the matches in `line`.
+    pub fn find(&self, line: &str) -> Vec<(usize, usize)> {
+        match self {
+            Matcher::Literal(finder) => finder.find_iter(line).collect(),
+            Matcher::Literals(automaton) => automaton.find_iter(line).collect(),
+            Matcher::CaseInsensitive(caseless) => match &caseless[..] {
+                [caseless] => caseless.find_iter(line).collect(),
+                _ => leftmost_longest(caseless.iter().flat_map(|caseless| caseless.find_iter(line)).collect()),
+            },
+            Matcher::Regex(regex) => regex.find_iter(line).collect(),
+            Matcher::Fuzzy(fuzzy) => match &fuzzy[..] {
+                [fuzzy] => fuzzy.find_iter(line),
+                _ => leftmost_longest(fuzzy.iter().flat_map(|fuzzy| fuzzy.find_iter(line)).collect()),
+            },
+            // Patterns under a NOT don't match a line the expression holds
+            // for, or if they do, that's despite it.
+            Matcher::Expression(expr) => {
+                leftmost_longest(expr.positive().into_iter().flat_map(|matcher| matcher.find(line)).collect())
+            }
+            Matcher::Bounded(matcher, bounds) => {
+                leftmost_longest(matcher.occurrences(line).into_iter().filter(|&range| fits(line, range, *bounds)).collect())
+            }
+        }
+    }
+
+    /// How good the best match in `line` is, for matchers that can tell:
+    /// with `--fuzzy`, the fewest typos it has, so lower is better.
+    pub fn score(&self, line: &str) -> Option<usize> {
+        match self {
+            Matcher::Fuzzy(fuzzy) => fuzzy.iter().filter_map(|fuzzy| fuzzy.distance(line)).min(),
+            Matcher::Bounded(matcher, _) => matcher.score(line),
+            Matcher::Expression(expr) => expr.positive().into_iter().filter_map(|matcher| matcher.score(line)).min(),
+            _ => None,
+        }
+    }
+
+    /// `line` with every match replaced by `replacement`, or `None` if
+    /// there are none. Only regular expressions have groups for `$1` and
+    /// `${name}` to refer to; otherwise the replacement is used as it is.
+    pub fn replace(&self, line: &str, replacement: &str) -> Option<String> {
+        if let Matcher::Regex(regex) = self {
+            return regex.is_match(line).then(|| regex.replace_all(line, replacement));
+        }
+
+        let ranges = self.find(line);
+        if ranges.is_empty() {
+            return None;
+        }
+        let mut replaced = String::with_capacity(line.len());
+        let mut last = 0;
+        for (start, end) in ranges {
+            replaced.push_str(&line[last..start]);
+            replaced.push_str(replacement);
+            last = end;
+        }
+        replaced.push_str(&line[last..]);
+        Some(replaced)
+    }
+
+    /// Every match of a literal matcher in `line`, overlapping ones too.
+    fn occurrences(&self, line: &str) -> Vec<(usize, usize)> {
+        match self {
+            Matcher::Literal(finder) => literal::overlapping(line, |line, at| finder.find_at(line, at)).collect(),
+            Matcher::Literals(automaton) => automaton.find_overlapping(line),
+            Matcher::CaseInsensitive(caseless) => caseless
+                .iter()
+                .flat_map(|caseless| literal::overlapping(line, |line, at| caseless.find_at(line, at)))
+                .collect(),
+            Matcher::Fuzzy(fuzzy) => fuzzy.iter().flat_map(|fuzzy| fuzzy.occurrences(line)).collect(),
+            Matcher::Regex(_) | Matcher::Expression(_) | Matcher::Bounded(..) => unreachable!("only literals are bounded after the fact"),
+        }
+    }
+}
+
+/// Whether the match `start..end` covers what `bounds` asks of `line`.
+fn fits(line: &str, (start, end): (usize, usize), bounds: Bounds) -> bool {
+    match bounds {
+        Bounds::Any => true,
+        Bounds::Words => {
+            !line[..start].chars().next_back().is_some_and(is_word_char)
+                && !line[end..].chars().next().is_some_and(is_word_char)
+        }
+        Bounds::Line => start == 0 && end == line.len(),
+    }
+}
+
+/// Drop ranges that overlap one found further left, or a longer one found
+/// at the same place, as a single search for every pattern would have.
+fn leftmost_longest(mut ranges: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    ranges.sort_by_key(|&(start, end)| (start, std::cmp::Reverse(end)));
+    let mut kept: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in ranges {
+        if kept.last().is_none_or(|&(last_start, last_end)| start >= last_end && start > last_start) {
+            kept.push((start, end));
+        }
+    }
+    kept
+}
+
+/// A matching line and where it was found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match<'a> {
+    pub line: &'a str,
+    /// Counted from 1.
+    pub line_number: usize,
+    /// Byte offset of the start of the line in the searched text.
+    pub offset: usize,
+    /// Byte ranges of the matches within `line`.
+    pub ranges: Vec<(usize, usize)>,
+}
+
+/// Each line of `contents` with its number and byte offset. Line endings are
+/// stripped just like `str::lines` does.
+pub fn lines(contents: &str) -> impl Iterator<Item = (usize, usize, &str)> {
+    let mut offset = 0;
+    contents.split_inclusive('\n').enumerate().map(move |(i, line)| {
+        let start = offset;
+        offset += line.len();
+        let line = line.strip_suffix('\n').map_or(line, |line| line.strip_suffix('\r').unwrap_or(line));
+        (i + 1, start, line)
+    })
+}
+
+/// Collect the lines `find` reports match ranges for.
+fn search_with<'a, F>(contents: &'a str, mut find: F) -> Vec<Match<'a>>
+where
+    F: FnMut(&str) -> Vec<(usize, usize)>,
+{
+    let mut results = Vec::new();
+    for (line_number, offset, line) in lines(contents) {
+        let ranges = find(line);
+        if !ranges.is_empty() {
+            results.push(Match { line, line_number, offset, ranges });
+        }
+    }
+    results
+}
+
+pub fn search<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
+    let finder = Finder::new(query);
+    search_with(contents, |line| finder.find_iter(line).collect())
+}
+
+pub fn search_regex<'a>(regex: &Regex, contents: &'a str) -> Vec<Match<'a>> {
+    search_with(contents, |line| regex.find_iter(line).collect())
 }
 
 //Note that we’ve edited the old test’s contents too. 
@@ -120,16 +782,14 @@ Trust me.";
 //The only difference is that we’ll lowercase the query and each line so whatever the case of the input arguments, 
 //they’ll be the same case when we check whether the line contains the query.
 
-pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    let query = query.to_lowercase();
-    let mut results = Vec::new();
-    for line in contents.lines() {
-        if line.to_lowercase().contains(&query) {
-            results.push(line);
-        }
-    }
-    results
+/// Rather than lowercasing, which the notes below admit isn't accurate, the
+/// query and each line are compared by their Unicode case folding, so `ß`
+/// matches `SS` too. See the `casefold` module.
+pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
+    let caseless = Caseless::new(query, false);
+    search_with(contents, |line| caseless.find_iter(line).collect())
 }
+
 //First, we lowercase the query string and store it in a shadowed variable with the same name. Calling to_lowercase on the query is necessary 
 //so no matter whether the user’s query is "rust", "RUST", "Rust", or "rUsT", we’ll treat the query as if it were "rust" and be insensitive to the case.
 //to_lowercase will handle basic Unicode, it won’t be 100% accurate. 
@@ -148,10 +808,69 @@ pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a st
 //First, we’ll add a configuration option to the Config struct to switch between case-sensitive and case-insensitive search.
 //Adding this field will cause compiler errors because we aren’t initializing this field anywhere yet:
 
+#[derive(Debug, Clone, Default)]
 pub struct Config {
     pub query: String,
-    pub file_name: String,
+    pub paths: Vec<String>,
     pub case_sensitive: bool,
+    /// Fold the dotted and dotless I the way Turkish and Azeri do.
+    pub turkic: bool,
+    /// Patterns given with `-e`, which take the place of the query.
+    pub patterns: Vec<String>,
+    /// Read each pattern as a boolean expression of patterns.
+    pub boolean: bool,
+    /// Match with up to this many typos, and print how many each line has.
+    pub fuzzy: Option<usize>,
+    /// Files to read patterns from, one per line, instead of the query.
+    pub pattern_files: Vec<String>,
+    /// Whether matches have to be whole words or whole lines.
+    pub bounds: Bounds,
+    /// Search binary files as if they were text.
+    pub text: bool,
+    /// Decompress files that turn out to be gzip-compressed, whatever
+    /// they're called.
+    pub decompress: bool,
+    /// Replace matches with this, showing a diff of the result.
+    pub replace: Option<String>,
+    /// With `replace`, change the files in place instead.
+    pub write: bool,
+    pub regex: bool,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub gitignore: bool,
+    pub line_number: bool,
+    pub byte_offset: bool,
+    pub before_context: usize,
+    pub after_context: usize,
+    /// Stop after this many matching lines.
+    pub max_count: Option<usize>,
+    /// Select the lines that don't match instead.
+    pub invert: bool,
+    /// Print how many lines were selected in each file instead of the lines.
+    pub count: bool,
+    /// Print only the paths of files with a selected line.
+    pub files_with_matches: bool,
+    /// Print only the paths of files without one.
+    pub files_without_match: bool,
+    pub color: Color,
+    /// Print the events the `json` module describes instead of lines.
+    pub json: bool,
+    /// Build or update the index of each path instead of searching, for
+    /// `minigrep index`.
+    pub build_index: bool,
+    /// Skip the files the indexes of the paths rule out.
+    pub use_index: bool,
+    /// Keep searching what's added to the files, for `--follow`.
+    pub follow: bool,
+    /// Search what's written under the directories from now on, for `--watch`.
+    pub watch: bool,
+    /// How many files to search at once, on worker threads when more than one.
+    pub threads: usize,
+    /// With several threads, print files in the order given rather than as
+    /// they finish.
+    pub sort_files: bool,
+    pub help: bool,
+    pub version: bool,
 }
 
 //Note that we added the case_sensitive field that holds a Boolean. 
@@ -171,3 +890,276 @@ pub struct Config {
 //Decide whether the command line argument or the environment variable should take precedence if the program is run with one set to case sensitive and one set to case insensitive.
 //The std::env module contains many more useful features for dealing with environment variables: check out its documentation to see what is available.
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn case_sensitive() {
+        let query = "duct";
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.";
+
+        let results = search(query, contents);
+        assert_eq!(vec!["safe, fast, productive."], results.iter().map(|m| m.line).collect::<Vec<_>>());
+        assert_eq!(2, results[0].line_number);
+        assert_eq!(6, results[0].offset);
+        assert_eq!(vec![(15, 19)], results[0].ranges);
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let query = "RuSt";
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+        assert_eq!(
+            vec!["Rust:", "Trust me."],
+            search_case_insensitive(query, contents).iter().map(|m| m.line).collect::<Vec<_>>()
+        );
+        
+    }
+
+    #[test]
+    fn regex() {
+        let regex = Regex::new("^[A-Z].*e\\.$|t{2,}").unwrap();
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.
+Butter.";
+        let lines: Vec<_> = search_regex(&regex, contents).iter().map(|m| m.line).collect();
+        assert_eq!(vec!["Pick three.", "Duct tape.", "Butter."], lines);
+    }
+
+    #[test]
+    fn regex_flag() {
+        let config = Config::new(["minigrep", "-E", "a|b", "poem.txt"].map(String::from)).unwrap();
+        assert!(config.regex);
+        assert_eq!("a|b", config.query);
+        assert_eq!(vec!["poem.txt"], config.paths);
+    }
+
+    #[test]
+    fn case_insensitive_ranges() {
+        // 'İ' lowercases to two characters, which shifts everything after it.
+        let results = search_case_insensitive("STANBUL", "İstanbul, ISTANBUL\r\n");
+        assert_eq!(vec![(2, 9), (12, 19)], results[0].ranges);
+        assert_eq!("İstanbul, ISTANBUL", results[0].line);
+    }
+
+    #[test]
+    fn case_folding() {
+        let contents = "Straße\nSTRASSE\nstrasse\nStrase";
+        let lines = |query| search_case_insensitive(query, contents).into_iter().map(|m| m.line).collect::<Vec<_>>();
+        assert_eq!(vec!["Straße", "STRASSE", "strasse"], lines("STRAẞE"));
+        assert_eq!(vec!["Straße", "STRASSE", "strasse"], lines("strasse"));
+
+        let config = Config::new(["minigrep", "-i", "--turkic", "ılık"].map(String::from)).unwrap();
+        let matcher = Matcher::new(&config).unwrap();
+        assert!(matcher.is_match("ILIK SU"));
+        assert!(!matcher.is_match("ilik su"));
+    }
+
+    #[test]
+    fn replacements() {
+        let matcher = |args: &[&str]| {
+            let config = Config::new(["minigrep"].iter().chain(args).map(|s| s.to_string())).unwrap();
+            Matcher::new(&config).unwrap()
+        };
+
+        assert_eq!(Some("a $1 c $1".to_string()), matcher(&["--replace=$1", "b"]).replace("a b c b", "$1"));
+        assert_eq!(Some("x b x".to_string()), matcher(&["-iw", "A"]).replace("a b a", "x"));
+        assert_eq!(Some("<b>".to_string()), matcher(&["-E", "(b)"]).replace("b", "<$1>"));
+        assert_eq!(None, matcher(&["-E", "(b)"]).replace("a", "<$1>"));
+        assert_eq!(
+            Err(ArgsError::Requires("--write".to_string(), "--replace".to_string())),
+            Config::new(["minigrep", "--write", "x"].map(String::from)).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn exit_codes() {
+        let config = |args: &[&str]| {
+            Config::new(["minigrep"].iter().chain(args).map(|s| s.to_string())).unwrap()
+        };
+
+        assert_eq!(Status::Matched, run(&config(&["-c", "nobody", "poem.txt"])).unwrap());
+        assert_eq!(Status::NoMatch, run(&config(&["-l", "Rust", "poem.txt"])).unwrap());
+        assert_eq!(Status::Matched, run(&config(&["-L", "Rust", "poem.txt"])).unwrap());
+        assert_eq!(Status::Error, run(&config(&["-c", "nobody", "poem.txt", "missing.txt"])).unwrap());
+        assert!(run(&config(&["-E", "(", "poem.txt"])).is_err());
+        assert_eq!(2, Status::Error.exit_code());
+    }
+
+    #[test]
+    fn case_flags_beat_environment() {
+        let set = |name: &str| (name == "CASE_INSENSITIVE").then(|| "1".to_string());
+        let config = |args: [&str; 3], var: &dyn Fn(&str) -> Option<String>| {
+            Config::from_args(args.map(String::from), var).unwrap()
+        };
+        assert!(!config(["minigrep", "rust", "poem.txt"], &set).case_sensitive);
+        assert!(config(["minigrep", "--no-ignore-case", "rust"], &set).case_sensitive);
+        assert!(config(["minigrep", "rust", "poem.txt"], &|_| None).case_sensitive);
+        assert!(!config(["minigrep", "-i", "rust"], &|_| None).case_sensitive);
+
+        let config = Config::new(["minigrep", "--help"].map(String::from)).unwrap();
+        assert!(config.help);
+        assert_eq!(Err(ArgsError::MissingQuery), Config::new(["minigrep", "-n"].map(String::from)).map(|_| ()));
+    }
+
+    #[test]
+    fn parallel_search_in_path_order() {
+        let root = env::temp_dir().join(format!("minigrep-parallel-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        for i in 0..40 {
+            let contents = "needle\nhay\n".repeat(i % 7) + "hay\nneedle\n";
+            std::fs::write(root.join(format!("{:02}.txt", i)), contents).unwrap();
+        }
+
+        let output = |args: &[&str]| {
+            let args = ["minigrep"].iter().chain(args).map(|s| s.to_string());
+            let mut config = Config::new(args).unwrap();
+            config.paths = vec![root.to_string_lossy().into_owned(), "missing.txt".to_string()];
+            let files = Walker::new(&[], &[], false).unwrap().files(&config.paths);
+            let mut printer = Printer::new(Vec::new(), &config, true);
+            let status = search_files(&config, Matcher::new(&config).unwrap(), files, &mut printer).unwrap();
+            (status, String::from_utf8(printer.into_inner()).unwrap())
+        };
+
+        let (status, sequential) = output(&["-n", "-C1", "needle"]);
+        assert_eq!(Status::Error, status);
+        assert_eq!((status, sequential.clone()), output(&["-n", "-C1", "-j", "4", "--sort-files", "needle"]));
+
+        // Unsorted, each file's lines still come out together.
+        let (_, unsorted) = output(&["-c", "--threads=4", "needle"]);
+        let mut lines: Vec<_> = unsorted.lines().collect();
+        lines.sort();
+        assert_eq!(output(&["-c", "needle"]).1.lines().collect::<Vec<_>>(), lines);
+
+        assert!(Config::new(["minigrep", "-j0", "x"].map(String::from)).is_err());
+    }
+
+    #[test]
+    fn pattern_files() {
+        let path = env::temp_dir().join(format!("minigrep-patterns-{}", std::process::id()));
+        std::fs::write(&path, "frog\nJune\nbog\n").unwrap();
+        let config = |args: &[&str]| {
+            let args = ["minigrep", "-f", path.to_str().unwrap()].into_iter().chain(args.iter().copied()).map(String::from);
+            Config::new(args).unwrap()
+        };
+
+        let sensitive = config(&["poem.txt"]);
+        assert_eq!(vec!["poem.txt"], sensitive.paths);
+        let patterns = read_patterns(&sensitive.pattern_files).unwrap();
+        let matcher = Matcher::with_patterns(&patterns, &sensitive).unwrap();
+        assert!(matches!(matcher, Matcher::Literals(_)));
+        assert_eq!(vec![(8, 12), (35, 38)], matcher.find("To tell June the name, an admiring bog!"));
+
+        let insensitive = config(&["-i"]);
+        let matcher = Matcher::with_patterns(&patterns, &insensitive).unwrap();
+        assert_eq!(vec![(0, 4), (5, 8), (9, 13)], matcher.find("FROG BOG june"));
+        let regex = Matcher::with_patterns(&["b.g".to_string(), "fr?og".to_string()], &config(&["-E"])).unwrap();
+        assert_eq!(vec![(0, 3), (4, 7)], regex.find("fog bag"));
+        assert!(!Matcher::with_patterns(&[], &sensitive).unwrap().is_match("anything"));
+    }
+
+    #[test]
+    fn repeated_patterns_and_expressions() {
+        let config = |args: &[&str]| Config::new(["minigrep"].iter().chain(args).map(|s| s.to_string())).unwrap();
+
+        let repeated = config(&["-e", "frog", "--regexp=bog", "poem.txt"]);
+        assert_eq!(vec!["poem.txt"], repeated.paths);
+        let matcher = Matcher::new(&repeated).unwrap();
+        assert_eq!(vec![(2, 6), (10, 13)], matcher.find("a frog, a bog"));
+
+        let matcher = Matcher::new(&config(&["--boolean", "-i", "frog AND NOT (bog OR pond)"])).unwrap();
+        assert!(matcher.is_match("How public, like a Frog"));
+        assert!(!matcher.is_match("a frog in a BOG"));
+        assert_eq!(vec![(2, 6)], matcher.find("a frog"));
+
+        // Each -e is an expression of its own, and any of them will do.
+        let matcher = Matcher::new(&config(&["--boolean", "-E", "-e", "^a AND b$", "-e", "c"])).unwrap();
+        assert!(matcher.is_match("a b"));
+        assert!(matcher.is_match("xcx"));
+        assert!(!matcher.is_match("b a"));
+
+        assert_eq!(
+            "unclosed ( at position 0",
+            Matcher::new(&config(&["--boolean", "(frog"])).err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn whole_words_and_lines() {
+        let find = |args: &[&str], line: &str| {
+            let config = Config::new(["minigrep"].iter().chain(args).map(|s| s.to_string())).unwrap();
+            Matcher::new(&config).unwrap().find(line)
+        };
+
+        // The tutorial's "to" no longer turns up in "tomorrow" or "into".
+        assert_eq!(vec![(20, 22)], find(&["-w", "to"], "tomorrow, into, and to"));
+        assert_eq!(vec![(0, 7), (8, 15)], find(&["-w", "-i", "STRASSE"], "Straße strasse Straßen"));
+        assert_eq!(vec![(0, 2), (7, 9)], find(&["-w", "-E", "t[a-z]"], "to tom to"));
+        assert_eq!(Vec::<(usize, usize)>::new(), find(&["-x", "-w", "to"], "to be"));
+        assert_eq!(vec![(0, 5)], find(&["-w", "-x", "-i", "TO BE"], "to be"));
+        assert_eq!(vec![(0, 5)], find(&["-x", "-E", "to|to be"], "to be"));
+        // Fuzzy matches keep the exact one, and pick out the whole words.
+        assert_eq!(vec![(0, 2), (3, 5), (8, 10)], find(&["--fuzzy=1", "to"], "to tomorrow"));
+        assert_eq!(vec![(0, 2)], find(&["-w", "--fuzzy=1", "to"], "to tomorrow"));
+        assert_eq!(vec![(3, 6), (8, 13)], find(&["-w", "--fuzzy=1", "frog"], "a, frg, frogs, frogsy"));
+
+        // A longer pattern that isn't a whole word can't hide a shorter one.
+        let config = Config::new(["minigrep", "-w", "-f", "-"].map(String::from)).unwrap();
+        let matcher = Matcher::with_patterns(&["foo".to_string(), "foo-b".to_string()], &config).unwrap();
+        assert_eq!(vec![(0, 3)], matcher.find("foo-bar"));
+        assert!(!matcher.is_match("food-bar"));
+    }
+
+    #[test]
+    fn paths_and_globs() {
+        let args = ["minigrep", "--include=*.rs", "fn", "src", "--exclude", "target", "tests", "--gitignore"];
+        let config = Config::new(args.map(String::from)).unwrap();
+        assert_eq!("fn", config.query);
+        assert_eq!(vec!["src", "tests"], config.paths);
+        assert_eq!(vec!["*.rs"], config.include);
+        assert_eq!(vec!["target"], config.exclude);
+        assert!(config.gitignore);
+    }
+
+    #[test]
+    fn index_command() {
+        let config = Config::new(["minigrep", "index", "--gitignore"].map(String::from)).unwrap();
+        assert!(config.build_index);
+        assert_eq!(vec!["."], config.paths);
+        let config = Config::new(["minigrep", "index", "src", "tests"].map(String::from)).unwrap();
+        assert_eq!(vec!["src", "tests"], config.paths);
+
+        // Only as the first argument is it the command.
+        let config = Config::new(["minigrep", "--index", "index", "src"].map(String::from)).unwrap();
+        assert!(!config.build_index && config.use_index);
+        assert_eq!("index", config.query);
+    }
+
+    #[test]
+    fn follow_conflicts() {
+        let config = Config::new(["minigrep", "--follow", "-n", "error", "app.log"].map(String::from)).unwrap();
+        assert!(config.follow && !config.watch);
+        for other in ["--count", "--max-count=1", "--watch"] {
+            let args = ["minigrep", "--follow", other, "error"].map(String::from);
+            assert!(matches!(Config::new(args), Err(ArgsError::Conflicts(..))), "{}", other);
+        }
+        let args = ["minigrep", "--watch", "-l", "error"].map(String::from);
+        assert_eq!(
+            "options --watch and --files-with-matches can't be used together",
+            Config::new(args).unwrap_err().to_string()
+        );
+    }
+}
@@ -0,0 +1,231 @@
+/// Many patterns at once, searched for with an Aho-Corasick automaton.
+///
+/// The patterns are put in a trie whose nodes also link to the longest
+/// proper suffix of their path that's in the trie too, where the search
+/// falls back to on a mismatch. Every byte of the text is then looked at
+/// once, and matches come out leftmost-longest, the way grep reports them.
+#[derive(Debug, Clone)]
+pub struct AhoCorasick {
+    nodes: Vec<Node>,
+    /// The root's transitions, for every byte, since it's visited most.
+    root: Box<[u32; 256]>,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    /// Transitions to children, sorted by byte.
+    next: Vec<(u8, u32)>,
+    fail: u32,
+    depth: u32,
+    /// Whether some pattern ends here.
+    end: bool,
+    /// The nearest node along the failure links where a pattern ends.
+    output: Option<u32>,
+}
+
+const ROOT: u32 = 0;
+
+impl AhoCorasick {
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> AhoCorasick {
+        let mut nodes = vec![Node::new(0)];
+
+        for pattern in patterns {
+            let mut node = ROOT;
+            for &b in pattern.as_ref().as_bytes() {
+                node = match child(&nodes, node, b) {
+                    Some(next) => next,
+                    None => {
+                        let next = nodes.len() as u32;
+                        let depth = nodes[node as usize].depth + 1;
+                        nodes.push(Node::new(depth));
+                        let transitions = &mut nodes[node as usize].next;
+                        let i = transitions.partition_point(|&(c, _)| c < b);
+                        transitions.insert(i, (b, next));
+                        next
+                    }
+                };
+            }
+            nodes[node as usize].end = true;
+        }
+
+        // Breadth first, so every failure link points at a finished node.
+        let mut queue = std::collections::VecDeque::from([ROOT]);
+        while let Some(node) = queue.pop_front() {
+            for (b, next) in nodes[node as usize].next.clone() {
+                let fail = if node == ROOT {
+                    ROOT
+                } else {
+                    let mut fail = nodes[node as usize].fail;
+                    loop {
+                        if let Some(target) = child(&nodes, fail, b) {
+                            break target;
+                        }
+                        if fail == ROOT {
+                            break ROOT;
+                        }
+                        fail = nodes[fail as usize].fail;
+                    }
+                };
+                let target = &nodes[fail as usize];
+                let output = if target.end { Some(fail) } else { target.output };
+                nodes[next as usize].fail = fail;
+                nodes[next as usize].output = output;
+                queue.push_back(next);
+            }
+        }
+
+        let mut root = Box::new([ROOT; 256]);
+        for &(b, next) in &nodes[ROOT as usize].next {
+            root[b as usize] = next;
+        }
+        AhoCorasick { nodes, root }
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        if self.nodes[ROOT as usize].end {
+            return true;
+        }
+        let mut state = ROOT;
+        text.bytes().any(|b| {
+            state = self.step(state, b);
+            let node = &self.nodes[state as usize];
+            node.end || node.output.is_some()
+        })
+    }
+
+    /// The leftmost match starting at or after byte offset `start`, and the
+    /// longest of the patterns that match there.
+    pub fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+        let mut best = None;
+        self.consider(ROOT, start, &mut best);
+
+        let mut state = ROOT;
+        for (i, &b) in text.as_bytes().iter().enumerate().skip(start) {
+            // Whatever matches later starts at or after the text the state
+            // stands for, so once that's past the best start, it's final.
+            let earliest = i - self.nodes[state as usize].depth as usize;
+            if best.is_some_and(|(best_start, _)| earliest > best_start) {
+                break;
+            }
+            state = self.step(state, b);
+            self.consider(state, i + 1, &mut best);
+        }
+        best
+    }
+
+    /// Every match of every pattern, overlapping ones too, in the order
+    /// they end.
+    pub fn find_overlapping(&self, text: &str) -> Vec<(usize, usize)> {
+        let mut found: Vec<_> = self.ending(ROOT, 0).collect();
+        let mut state = ROOT;
+        for (i, b) in text.bytes().enumerate() {
+            state = self.step(state, b);
+            // Only the empty pattern can end inside a character.
+            if text.is_char_boundary(i + 1) {
+                found.extend(self.ending(state, i + 1));
+            }
+        }
+        found
+    }
+
+    /// Successive non-overlapping matches, as `(start, end)` byte offsets.
+    pub fn find_iter<'a>(&'a self, text: &'a str) -> impl Iterator<Item = (usize, usize)> + 'a {
+        super::find_iter(text, move |text, at| self.find_at(text, at))
+    }
+
+    fn step(&self, mut state: u32, b: u8) -> u32 {
+        loop {
+            if state == ROOT {
+                return self.root[b as usize];
+            }
+            if let Some(next) = child(&self.nodes, state, b) {
+                return next;
+            }
+            state = self.nodes[state as usize].fail;
+        }
+    }
+
+    /// Keep whichever of `best` and the patterns ending at `end` in
+    /// `state` starts first, or is longest when they start together.
+    fn consider(&self, state: u32, end: usize, best: &mut Option<(usize, usize)>) {
+        for (start, end) in self.ending(state, end) {
+            if best.is_none_or(|(best_start, best_end)| start < best_start || (start == best_start && end > best_end)) {
+                *best = Some((start, end));
+            }
+        }
+    }
+
+    /// The matches of the patterns that end at `end`, in `state`.
+    fn ending(&self, state: u32, end: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let node = &self.nodes[state as usize];
+        let mut matched = if node.end { Some(state) } else { node.output };
+        std::iter::from_fn(move || {
+            let m = &self.nodes[matched? as usize];
+            matched = m.output;
+            Some((end - m.depth as usize, end))
+        })
+    }
+}
+
+impl Node {
+    fn new(depth: u32) -> Node {
+        Node { next: Vec::new(), fail: ROOT, depth, end: false, output: None }
+    }
+}
+
+fn child(nodes: &[Node], node: u32, b: u8) -> Option<u32> {
+    let next = &nodes[node as usize].next;
+    next.binary_search_by_key(&b, |&(c, _)| c).ok().map(|i| next[i].1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(patterns: &[&str], text: &str) -> Vec<(usize, usize)> {
+        AhoCorasick::new(patterns).find_iter(text).collect()
+    }
+
+    #[test]
+    fn finds_leftmost_longest() {
+        assert_eq!(vec![(0, 4)], find(&["bc", "abcd"], "abcd"));
+        assert_eq!(vec![(0, 3), (4, 6)], find(&["he", "she", "his", "hers"], "she he"));
+        assert_eq!(vec![(0, 6)], find(&["hers", "ushers", "she"], "ushers"));
+        assert_eq!(vec![(0, 2), (2, 4)], find(&["a", "ab"], "abab"));
+        assert_eq!(Vec::<(usize, usize)>::new(), find(&["xyz", "zy"], "xyxzz"));
+        assert_eq!(vec![(3, 9)], find(&["déjà", "vu", "é"], "le déjà"));
+    }
+
+    #[test]
+    fn agrees_with_naive_search() {
+        let patterns: Vec<String> = (0..500).map(|i| format!("w{}x", i * 7)).collect();
+        let text: String = (0..3000).map(|i| format!("w{}x ", i)).collect();
+        let automaton = AhoCorasick::new(&patterns);
+
+        let mut expected = Vec::new();
+        for (i, _) in text.char_indices() {
+            if let Some(p) = patterns.iter().filter(|p| text[i..].starts_with(p.as_str())).max_by_key(|p| p.len()) {
+                if expected.last().is_none_or(|&(_, end)| i >= end) {
+                    expected.push((i, i + p.len()));
+                }
+            }
+        }
+        assert_eq!(expected, automaton.find_iter(&text).collect::<Vec<_>>());
+        assert!(automaton.is_match("a w3493x b"));
+        assert!(!automaton.is_match("a w3494x b"));
+    }
+
+    #[test]
+    fn finds_overlapping() {
+        let automaton = AhoCorasick::new(&["he", "she", "his", "hers"]);
+        assert_eq!(vec![(1, 4), (2, 4), (2, 6)], automaton.find_overlapping("ushers"));
+        assert_eq!(vec![(0, 0), (1, 1), (3, 3)], AhoCorasick::new(&[""]).find_overlapping("aé"));
+    }
+
+    #[test]
+    fn empty_patterns() {
+        assert!(!AhoCorasick::new::<&str>(&[]).is_match("anything"));
+        assert!(AhoCorasick::new(&["", "x"]).is_match("abc"));
+        assert_eq!(vec![(0, 0), (1, 2), (2, 2)], find(&["", "x"], "ax"));
+    }
+}
@@ -0,0 +1,78 @@
+/// One pattern, searched for with the Boyer-Moore-Horspool algorithm.
+///
+/// The window is compared from its last byte, and after a mismatch it
+/// moves by how far that last byte is from the end of the pattern, or by
+/// the whole length when the byte doesn't occur in the pattern at all.
+#[derive(Debug, Clone)]
+pub struct Horspool {
+    needle: Vec<u8>,
+    shift: Box<[usize; 256]>,
+}
+
+impl Horspool {
+    pub fn new(needle: &str) -> Horspool {
+        let needle = needle.as_bytes().to_vec();
+        let mut shift = Box::new([needle.len(); 256]);
+        // The last byte is left out: it's the one a shift is decided on.
+        for (i, &b) in needle.iter().enumerate().take(needle.len().saturating_sub(1)) {
+            shift[b as usize] = needle.len() - 1 - i;
+        }
+        Horspool { needle, shift }
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find_at(text, 0).is_some()
+    }
+
+    /// The first match starting at or after byte offset `start`.
+    pub fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+        let haystack = text.as_bytes();
+        let n = self.needle.len();
+        let Some(&last) = self.needle.last() else {
+            return Some((start, start));
+        };
+
+        let mut at = start;
+        while at + n <= haystack.len() {
+            let end = haystack[at + n - 1];
+            if end == last && haystack[at..at + n - 1] == self.needle[..n - 1] {
+                return Some((at, at + n));
+            }
+            at += self.shift[end as usize];
+        }
+        None
+    }
+
+    /// Successive non-overlapping matches, as `(start, end)` byte offsets.
+    pub fn find_iter<'a>(&'a self, text: &'a str) -> impl Iterator<Item = (usize, usize)> + 'a {
+        super::find_iter(text, move |text, at| self.find_at(text, at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(needle: &str, text: &str) -> Vec<(usize, usize)> {
+        Horspool::new(needle).find_iter(text).collect()
+    }
+
+    #[test]
+    fn finds_like_match_indices() {
+        let text = "abracadabra, cadabra! abra";
+        for needle in ["a", "abra", "cadabra", "ra,", "abracadabra, cadabra! abra", "zebra", "aa"] {
+            let expected: Vec<_> = text.match_indices(needle).map(|(i, m)| (i, i + m.len())).collect();
+            assert_eq!(expected, find(needle, text), "{}", needle);
+        }
+        assert_eq!(vec![(0, 2), (2, 4)], find("aa", "aaaaa"));
+        assert_eq!(vec![(0, 0), (1, 1), (3, 3)], find("", "aé"));
+    }
+
+    #[test]
+    fn stays_on_char_boundaries() {
+        // 'é' and 'ê' share their first byte, so shifts land mid-character.
+        assert_eq!(vec![(10, 14)], find("êé", "éééééêé"));
+        assert_eq!(Some((6, 12)), Horspool::new("日本").find_at("本日日本", 0));
+        assert!(!Horspool::new("日本").is_match("本日"));
+    }
+}
@@ -0,0 +1,97 @@
+//! Substring search for fixed strings.
+//!
+//! A single long pattern is found with Boyer-Moore-Horspool, which skips
+//! ahead by up to the length of the pattern after each mismatch, so the
+//! longer the pattern the less of the text is looked at. Short patterns
+//! don't skip far enough to beat the standard library's Two-Way search,
+//! which is vectorised, so they're left to it. Many patterns at once,
+//! as read with `-f`, go through an Aho-Corasick automaton that finds all
+//! of them in one pass, however many there are.
+//!
+//! Both work on the bytes of the text. Patterns are valid UTF-8 too, so a
+//! match can only start and end on character boundaries.
+
+mod aho_corasick;
+mod horspool;
+
+pub use aho_corasick::AhoCorasick;
+pub use horspool::Horspool;
+
+/// Patterns from this long on are faster with Horspool, going by
+/// `benches/literal.rs`.
+const HORSPOOL_MIN_LEN: usize = 16;
+
+/// A single pattern, searched for whichever way is faster for its length.
+#[derive(Debug, Clone)]
+pub enum Finder {
+    TwoWay(String),
+    Horspool(Horspool),
+}
+
+impl Finder {
+    pub fn new(needle: &str) -> Finder {
+        if needle.len() >= HORSPOOL_MIN_LEN {
+            Finder::Horspool(Horspool::new(needle))
+        } else {
+            Finder::TwoWay(needle.to_string())
+        }
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        match self {
+            Finder::TwoWay(needle) => text.contains(needle.as_str()),
+            Finder::Horspool(horspool) => horspool.is_match(text),
+        }
+    }
+
+    /// The first match starting at or after byte offset `start`.
+    pub fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+        match self {
+            Finder::TwoWay(needle) => {
+                let i = start + text[start..].find(needle.as_str())?;
+                Some((i, i + needle.len()))
+            }
+            Finder::Horspool(horspool) => horspool.find_at(text, start),
+        }
+    }
+
+    /// Successive non-overlapping matches, as `(start, end)` byte offsets.
+    pub fn find_iter<'a>(&'a self, text: &'a str) -> Box<dyn Iterator<Item = (usize, usize)> + 'a> {
+        match self {
+            Finder::TwoWay(needle) => Box::new(text.match_indices(needle.as_str()).map(|(i, m)| (i, i + m.len()))),
+            Finder::Horspool(horspool) => Box::new(horspool.find_iter(text)),
+        }
+    }
+}
+
+/// Every match, overlapping ones too, given the first match at or after an
+/// offset: each search starts again a character after the last match did.
+pub fn overlapping<'a, F>(text: &'a str, find_at: F) -> impl Iterator<Item = (usize, usize)> + 'a
+where
+    F: Fn(&str, usize) -> Option<(usize, usize)> + 'a,
+{
+    let mut at = Some(0);
+    std::iter::from_fn(move || {
+        let (start, end) = find_at(text, at?)?;
+        at = text[start..].chars().next().map(|c| start + c.len_utf8());
+        Some((start, end))
+    })
+}
+
+/// Successive non-overlapping matches, given the first match at or after
+/// an offset. An empty match moves on by a character.
+fn find_iter<'a, F>(text: &'a str, find_at: F) -> impl Iterator<Item = (usize, usize)> + 'a
+where
+    F: Fn(&str, usize) -> Option<(usize, usize)> + 'a,
+{
+    let mut at = Some(0);
+    std::iter::from_fn(move || {
+        let (start, end) = find_at(text, at?)?;
+        at = match text[end..].chars().next() {
+            Some(c) if start == end => Some(end + c.len_utf8()),
+            None if start == end => None,
+            _ => Some(end),
+        };
+        Some((start, end))
+    })
+}
@@ -1,20 +1,32 @@
 use std::env;
 use std::process;
-use minigrep5::{Config, run};
+use minigrep5::{usage, Config, run};
 
 
 fn main() {
     
-    let args: Vec<String> = env::args().collect();
-
-    let config = Config::new(&args).unwrap_or_else(|err| {
+    let config = Config::new(env::args()).unwrap_or_else(|err| {
         eprintln!("Error while reading arguments: {}", err);
-        process::exit(1);
+        eprintln!("Try 'minigrep --help' for more information.");
+        process::exit(2);
     });
 
-    if let Err(e) = run(&config) {
-        eprintln!("Error while trying to open a file: {}", e);
-        process::exit(1);
+    if config.help {
+        print!("{}", usage());
+        return;
+    }
+    if config.version {
+        println!("minigrep {}", env!("CARGO_PKG_VERSION"));
+        return;
+    }
+
+    // 0 if something was selected, 1 if nothing was and 2 on errors, like grep.
+    match run(&config) {
+        Ok(status) => process::exit(status.exit_code()),
+        Err(e) => {
+            eprintln!("Error while searching: {}", e);
+            process::exit(2);
+        }
     }
 }
 
@@ -0,0 +1,140 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+/// A fixed set of worker threads that run jobs in the order they're given,
+/// built the same way as the hello server's pool, minus the logging.
+pub struct ThreadPool {
+    workers: Vec<Worker>,
+    sender: mpsc::Sender<Message>,
+}
+
+#[derive(Debug)]
+pub struct PoolCreationError {}
+
+struct Worker {
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+enum Message {
+    NewJob(Job),
+    Terminate,
+}
+
+impl ThreadPool {
+    /// Create a new ThreadPool.
+    ///
+    /// The size is the number of threads in the pool.
+    ///
+    /// # Errors
+    ///
+    /// The `new` function will return `PoolCreationError` error type if the size is zero.
+    pub fn new(size: usize) -> Result<ThreadPool, PoolCreationError> {
+        if size == 0 {
+            return Err(PoolCreationError {});
+        }
+
+        let (sender, receiver) = mpsc::channel();
+
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let mut workers = Vec::with_capacity(size);
+
+        for _ in 0..size {
+            workers.push(Worker::new(Arc::clone(&receiver)));
+        }
+
+        Ok(ThreadPool { workers, sender })
+    }
+
+    pub fn execute<F>(&self, closure: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let f = Box::new(closure);
+
+        self.sender.send(Message::NewJob(f)).unwrap();
+    }
+}
+
+impl Worker {
+    fn new(receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Worker {
+        let thread = thread::spawn(move || loop {
+            let message = receiver.lock().unwrap().recv().unwrap();
+
+            match message {
+                Message::NewJob(job) => job(),
+                Message::Terminate => break,
+            }
+        });
+
+        Worker { thread: Some(thread) }
+    }
+}
+
+impl fmt::Display for PoolCreationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Number of threads must be greater than 0")
+    }
+}
+
+impl Error for PoolCreationError {}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // Workers that a panicking job took down are already gone, and
+        // with all of them gone, so is the receiver.
+        for _ in &self.workers {
+            let _ = self.sender.send(Message::Terminate);
+        }
+
+        for worker in &mut self.workers {
+            if let Some(thread) = worker.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_every_job() {
+        let (sender, receiver) = mpsc::channel();
+        {
+            let pool = ThreadPool::new(3).unwrap();
+            for i in 0..10 {
+                let sender = sender.clone();
+                pool.execute(move || sender.send(i).unwrap());
+            }
+        }
+        drop(sender);
+
+        let mut done: Vec<i32> = receiver.iter().collect();
+        done.sort();
+        assert_eq!((0..10).collect::<Vec<_>>(), done);
+        assert!(ThreadPool::new(0).is_err());
+    }
+
+    #[test]
+    fn outlives_panicking_jobs() {
+        let (sender, receiver) = mpsc::channel();
+        {
+            let pool = ThreadPool::new(2).unwrap();
+            pool.execute(|| panic!("a job that fails"));
+            pool.execute(move || sender.send(1).unwrap());
+        }
+        assert_eq!(vec![1], receiver.iter().collect::<Vec<_>>());
+
+        // Dropping a pool whose workers have all died doesn't panic either.
+        let pool = ThreadPool::new(1).unwrap();
+        pool.execute(|| panic!("a job that fails"));
+        thread::sleep(std::time::Duration::from_millis(50));
+        drop(pool);
+    }
+}
@@ -0,0 +1,377 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{json, Colors, Config};
+
+/// Writes matching lines the way GNU grep does.
+///
+/// Each line can be prefixed with its path, line number and byte offset,
+/// separated by `:` on matching lines and `-` on context lines. With context
+/// turned on, groups of lines that aren't adjacent are separated by `--`.
+///
+/// Lines are fed in one at a time, so only the before-context has to be
+/// kept around. With colours, the match ranges passed along with each line
+/// are highlighted.
+///
+/// With `json` set, the lines are printed as the events `json` describes
+/// instead, and counts and file lists are left to the `end` events.
+pub struct Printer<W: Write> {
+    out: W,
+    with_path: bool,
+    line_number: bool,
+    byte_offset: bool,
+    before: usize,
+    after: usize,
+    printed_any: bool,
+    path: PathBuf,
+    /// Number of the last line printed from the current file.
+    last_printed: Option<usize>,
+    after_left: usize,
+    /// The most recent unprinted lines.
+    buffered: VecDeque<Buffered>,
+    colors: Option<Colors>,
+    json: bool,
+    /// Whether the current file turned out to be binary.
+    binary: bool,
+}
+
+struct Buffered {
+    number: usize,
+    offset: usize,
+    line: String,
+    ranges: Vec<(usize, usize)>,
+}
+
+/// Picks one of the colours.
+type Pick = fn(&Colors) -> &str;
+
+impl<W: Write> Printer<W> {
+    pub fn new(out: W, config: &Config, with_path: bool) -> Printer<W> {
+        Printer {
+            out,
+            with_path,
+            line_number: config.line_number,
+            byte_offset: config.byte_offset,
+            before: config.before_context,
+            after: config.after_context,
+            printed_any: false,
+            path: PathBuf::new(),
+            last_printed: None,
+            after_left: 0,
+            buffered: VecDeque::new(),
+            colors: None,
+            json: config.json,
+            binary: false,
+        }
+    }
+
+    /// Colour the output with `colors`.
+    pub fn with_colors(mut self, colors: Colors) -> Printer<W> {
+        self.colors = Some(colors);
+        self
+    }
+
+    /// A printer with the same settings that prints into memory, so a file
+    /// can be searched on another thread and printed in one piece later.
+    pub fn buffer(&self) -> Printer<Vec<u8>> {
+        Printer {
+            out: Vec::new(),
+            with_path: self.with_path,
+            line_number: self.line_number,
+            byte_offset: self.byte_offset,
+            before: self.before,
+            after: self.after,
+            printed_any: false,
+            path: PathBuf::new(),
+            last_printed: None,
+            after_left: 0,
+            buffered: VecDeque::new(),
+            colors: self.colors.clone(),
+            json: self.json,
+            binary: false,
+        }
+    }
+
+    /// Print what a printer made by `buffer` printed, as if this one had.
+    pub fn append(&mut self, other: Printer<Vec<u8>>) -> io::Result<()> {
+        if (self.before > 0 || self.after > 0) && !self.json && self.printed_any && other.printed_any {
+            let separator = self.paint(|c| &c.separator, "--");
+            writeln!(self.out, "{}", separator)?;
+        }
+        self.out.write_all(&other.out)?;
+        self.printed_any |= other.printed_any;
+        Ok(())
+    }
+
+    /// Whether match ranges are used, so they're worth finding.
+    pub fn highlights(&self) -> bool {
+        self.colors.is_some() || self.json
+    }
+
+    /// Get ready for the lines of another file.
+    pub fn start(&mut self, path: &Path) -> io::Result<()> {
+        self.path = path.to_path_buf();
+        self.last_printed = None;
+        self.after_left = 0;
+        self.buffered.clear();
+        self.binary = false;
+        if self.json {
+            writeln!(self.out, "{}", json::begin(&self.path))?;
+        }
+        Ok(())
+    }
+
+    /// Done with the current file, in which `count` lines were selected.
+    pub fn finish(&mut self, count: usize) -> io::Result<()> {
+        if self.json {
+            writeln!(self.out, "{}", json::end(&self.path, count, self.binary))?;
+        }
+        Ok(())
+    }
+
+    /// Print a selected line, after whatever context comes before it. Its
+    /// `score`, if it has one, comes first, so the lines can be sorted by it.
+    pub fn matched(
+        &mut self,
+        number: usize,
+        offset: usize,
+        line: &str,
+        ranges: &[(usize, usize)],
+        score: Option<usize>,
+    ) -> io::Result<()> {
+        let first = self.buffered.front().map_or(number, |buffered| buffered.number);
+        let gap = self.last_printed.is_none_or(|last| first > last + 1);
+        if (self.before > 0 || self.after > 0) && !self.json && self.printed_any && gap {
+            let separator = self.paint(|c| &c.separator, "--");
+            writeln!(self.out, "{}", separator)?;
+        }
+
+        while let Some(buffered) = self.buffered.pop_front() {
+            self.line(buffered.number, buffered.offset, &buffered.line, &buffered.ranges, None, '-')?;
+        }
+        self.line(number, offset, line, ranges, score, ':')?;
+
+        self.last_printed = Some(number);
+        self.after_left = self.after;
+        self.printed_any = true;
+        Ok(())
+    }
+
+    /// Pass a line that wasn't selected, which is printed if it's context.
+    pub fn unmatched(&mut self, number: usize, offset: usize, line: &str, ranges: &[(usize, usize)]) -> io::Result<()> {
+        if self.after_left > 0 {
+            self.after_left -= 1;
+            self.last_printed = Some(number);
+            return self.line(number, offset, line, ranges, None, '-');
+        }
+
+        if self.before > 0 {
+            if self.buffered.len() == self.before {
+                self.buffered.pop_front();
+            }
+            self.buffered.push_back(Buffered { number, offset, line: line.to_string(), ranges: ranges.to_vec() });
+        }
+        Ok(())
+    }
+
+    /// Print the number of selected lines in the current file, for `-c`.
+    pub fn count(&mut self, count: usize) -> io::Result<()> {
+        if self.json {
+            return Ok(());
+        }
+        if self.with_path {
+            let path = self.paint(|c| &c.file_name, &self.path.display().to_string());
+            write!(self.out, "{}{}", path, self.paint(|c| &c.separator, ":"))?;
+        }
+        writeln!(self.out, "{}", count)
+    }
+
+    /// Print just the current file's path, for `-l` and `-L`.
+    pub fn path(&mut self) -> io::Result<()> {
+        if self.json {
+            return Ok(());
+        }
+        let path = self.paint(|c| &c.file_name, &self.path.display().to_string());
+        writeln!(self.out, "{}", path)
+    }
+
+    /// Say that the current file, which is binary, has a selected line.
+    pub fn binary(&mut self) -> io::Result<()> {
+        if self.json {
+            self.binary = true;
+            return Ok(());
+        }
+        writeln!(self.out, "Binary file {} matches", self.path.display())
+    }
+
+    /// Whether lines after the last match would still be printed.
+    pub fn wants_context(&self) -> bool {
+        self.after_left > 0
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn line(
+        &mut self,
+        number: usize,
+        offset: usize,
+        line: &str,
+        ranges: &[(usize, usize)],
+        score: Option<usize>,
+        separator: char,
+    ) -> io::Result<()> {
+        let context = separator == '-';
+        if self.json {
+            return writeln!(self.out, "{}", json::line(context, &self.path, number, offset, line, ranges, score));
+        }
+        let separator = self.paint(|c| &c.separator, &separator.to_string());
+        let mut out = String::new();
+
+        if let Some(score) = score {
+            out += &self.paint(|c| &c.line_number, &score.to_string());
+            out += &separator;
+        }
+        if self.with_path {
+            out += &self.paint(|c| &c.file_name, &self.path.display().to_string());
+            out += &separator;
+        }
+        if self.line_number {
+            out += &self.paint(|c| &c.line_number, &number.to_string());
+            out += &separator;
+        }
+        if self.byte_offset {
+            out += &self.paint(|c| &c.byte_offset, &offset.to_string());
+            out += &separator;
+        }
+
+        let (line_color, match_color): (Pick, Pick) = if context {
+            (|c| &c.context_line, |c| &c.context_match)
+        } else {
+            (|c| &c.selected_line, |c| &c.selected_match)
+        };
+        let mut at = 0;
+        for &(start, end) in ranges.iter().filter(|_| self.colors.is_some()) {
+            if start < at || start == end {
+                continue;
+            }
+            out += &self.paint(line_color, &line[at..start]);
+            out += &self.paint(match_color, &line[start..end]);
+            at = end;
+        }
+        out += &self.paint(line_color, &line[at..]);
+
+        writeln!(self.out, "{}", out)
+    }
+
+    /// `text` in the colour `pick` chooses, when colouring at all.
+    fn paint(&self, pick: Pick, text: &str) -> String {
+        match &self.colors {
+            Some(colors) => colors.paint(pick(colors), text),
+            None => text.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Matcher, Searcher};
+
+    fn print(args: &[&str], contents: &str) -> String {
+        print_with(args, contents, false, None)
+    }
+
+    fn print_with(args: &[&str], contents: &str, with_path: bool, colors: Option<Colors>) -> String {
+        let config = Config::new(["minigrep"].iter().chain(args).map(|s| s.to_string())).unwrap();
+        let matcher = Matcher::new(&config).unwrap();
+
+        let mut printer = Printer::new(Vec::new(), &config, with_path);
+        if let Some(colors) = colors {
+            printer = printer.with_colors(colors);
+        }
+        Searcher::new(&matcher, &config).search(contents.as_bytes(), Path::new("poem.txt"), &mut printer).unwrap();
+        String::from_utf8(printer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn prefixes() {
+        let contents = "one\ntwo\nthree\n";
+        assert_eq!("2:4:two\n", print(&["-n", "-b", "tw", "poem.txt"], contents));
+        assert_eq!("poem.txt:two\n", print_with(&["tw", "poem.txt"], contents, true, None));
+    }
+
+    #[test]
+    fn context_and_separators() {
+        let contents = "a\nb match\nc\nd\ne\nf\ng match\nh match\ni\n";
+        assert_eq!(
+            "1-a\n2:b match\n3-c\n--\n6-f\n7:g match\n8:h match\n9-i\n",
+            print(&["-n", "-C", "1", "match", "poem.txt"], contents)
+        );
+        // Windows that touch are merged without a separator.
+        assert_eq!(
+            "b match\nc\nd\ne\nf\ng match\nh match\n",
+            print(&["-A2", "-B", "2", "match", "poem.txt"], "b match\nc\nd\ne\nf\ng match\nh match\n")
+        );
+    }
+
+    #[test]
+    fn appends_buffers() {
+        let config = Config::new(["minigrep", "-C1", "x"].map(String::from)).unwrap();
+        let matcher = Matcher::new(&config).unwrap();
+        let searcher = Searcher::new(&matcher, &config);
+        let mut printer = Printer::new(Vec::new(), &config, true);
+
+        for (path, contents) in [("a", "x\n"), ("b", "y\n"), ("c", "y\nx\n")] {
+            let mut buffer = printer.buffer();
+            searcher.search(contents.as_bytes(), Path::new(path), &mut buffer).unwrap();
+            printer.append(buffer).unwrap();
+        }
+        assert_eq!("a:x\n--\nc-y\nc:x\n", String::from_utf8(printer.into_inner()).unwrap());
+    }
+
+    #[test]
+    fn json_events() {
+        let events = print_with(&["--json", "-B1", "-m1", "o", "poem.txt"], "one\ntwo\nthree\nfour\n", true, None);
+        assert_eq!(
+            concat!(
+                "{\"type\":\"begin\",\"path\":\"poem.txt\"}\n",
+                "{\"type\":\"match\",\"path\":\"poem.txt\",\"line_number\":1,\"offset\":0,\"text\":\"one\",",
+                "\"submatches\":[{\"start\":0,\"end\":1,\"text\":\"o\"}]}\n",
+                "{\"type\":\"end\",\"path\":\"poem.txt\",\"matches\":1,\"binary\":false}\n",
+            ),
+            events
+        );
+
+        let events = print(&["--json", "-v", "-C1", "x", "poem.txt"], "a x\nb\n");
+        assert!(events.contains("\"type\":\"context\",\"path\":\"poem.txt\",\"line_number\":1,\"offset\":0,\"text\":\"a x\",\"submatches\":[{\"start\":2,"));
+        assert!(events.contains("\"type\":\"match\",\"path\":\"poem.txt\",\"line_number\":2,\"offset\":4,\"text\":\"b\",\"submatches\":[]}"));
+
+        let events = print(&["--json", "x", "poem.txt"], "x\0\n");
+        assert!(events.ends_with("{\"type\":\"end\",\"path\":\"poem.txt\",\"matches\":1,\"binary\":true}\n"));
+    }
+
+    #[test]
+    fn fuzzy_scores() {
+        let contents = "a frog\na frg\na dog\n";
+        assert_eq!("0:1:a frog\n1:2:a frg\n", print(&["--fuzzy=1", "-n", "frog", "poem.txt"], contents));
+        assert_eq!("0:a frg\n", print(&["--fuzzy", "1", "-A1", "-m1", "frg", "poem.txt"], "a dog\na frg\n"));
+        assert_eq!("a dog\n", print(&["--fuzzy=1", "-v", "frog", "poem.txt"], contents));
+    }
+
+    #[test]
+    fn colors() {
+        let colors = Colors::parse("ms=1:mc=4:ln=2:fn=:se=3:ne");
+        assert_eq!(
+            "poem.txt\x1b[3m:\x1b[m\x1b[2m1\x1b[m\x1b[3m:\x1b[ma \x1b[1mx\x1b[m b \x1b[1mx\x1b[m\n",
+            print_with(&["-n", "x", "poem.txt"], "a x b x\n", true, Some(colors.clone()))
+        );
+        // With -v the context lines are the ones that match.
+        assert_eq!(
+            "\x1b[4mx\x1b[m\na\n\x1b[3m--\x1b[m\n\x1b[4mx\x1b[m\nb\n",
+            print_with(&["-v", "-B1", "x", "poem.txt"], "x\na\nx\nx\nb\n", false, Some(colors))
+        );
+    }
+}
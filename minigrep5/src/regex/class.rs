@@ -0,0 +1,163 @@
+use crate::casefold;
+
+/// A set of characters, as written with `[...]`, `\d`, `\p{...}` and friends.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Class {
+    pub items: Vec<ClassItem>,
+    pub negated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClassItem {
+    Range(char, char),
+    Property(Property, bool),
+}
+
+/// Named character properties. The Unicode ones are answered by the
+/// standard library, scripts by the code point blocks they live in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Property {
+    Digit,
+    Word,
+    Space,
+    Letter,
+    Uppercase,
+    Lowercase,
+    Number,
+    Alphanumeric,
+    Control,
+    Punctuation,
+    HexDigit,
+    Script(Script),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Arabic,
+    Hebrew,
+    Han,
+    Hiragana,
+    Katakana,
+}
+
+impl Class {
+    pub fn matches(&self, c: char) -> bool {
+        self.items.iter().any(|item| item.matches(c)) != self.negated
+    }
+
+    /// Whether the class matches `c` or any character that folds like it.
+    pub fn matches_ignoring_case(&self, c: char) -> bool {
+        casefold::variants(c).any(|v| self.items.iter().any(|item| item.matches(v))) != self.negated
+    }
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match *self {
+            ClassItem::Range(lo, hi) => lo <= c && c <= hi,
+            ClassItem::Property(property, negated) => property.matches(c) != negated,
+        }
+    }
+}
+
+impl Property {
+    /// Look up the name used in `\p{...}` or `[[:...:]]`.
+    pub fn from_name(name: &str) -> Option<Property> {
+        let property = match name {
+            "L" | "Letter" | "Alphabetic" | "alpha" => Property::Letter,
+            "Lu" | "Uppercase_Letter" | "Uppercase" | "upper" => Property::Uppercase,
+            "Ll" | "Lowercase_Letter" | "Lowercase" | "lower" => Property::Lowercase,
+            "N" | "Number" => Property::Number,
+            "Nd" | "digit" => Property::Digit,
+            "White_Space" | "Space" | "space" => Property::Space,
+            "Cc" | "Control" | "cntrl" => Property::Control,
+            "alnum" => Property::Alphanumeric,
+            "punct" => Property::Punctuation,
+            "xdigit" => Property::HexDigit,
+            "word" => Property::Word,
+            "Latin" => Property::Script(Script::Latin),
+            "Greek" => Property::Script(Script::Greek),
+            "Cyrillic" => Property::Script(Script::Cyrillic),
+            "Arabic" => Property::Script(Script::Arabic),
+            "Hebrew" => Property::Script(Script::Hebrew),
+            "Han" => Property::Script(Script::Han),
+            "Hiragana" => Property::Script(Script::Hiragana),
+            "Katakana" => Property::Script(Script::Katakana),
+            _ => return None,
+        };
+        Some(property)
+    }
+
+    pub fn matches(self, c: char) -> bool {
+        match self {
+            Property::Digit => c.is_ascii_digit(),
+            Property::Word => is_word_char(c),
+            Property::Space => c.is_whitespace(),
+            Property::Letter => c.is_alphabetic(),
+            Property::Uppercase => c.is_uppercase(),
+            Property::Lowercase => c.is_lowercase(),
+            Property::Number => c.is_numeric(),
+            Property::Alphanumeric => c.is_alphanumeric(),
+            Property::Control => c.is_control(),
+            Property::Punctuation => c.is_ascii_punctuation(),
+            Property::HexDigit => c.is_ascii_hexdigit(),
+            Property::Script(script) => script.ranges().iter().any(|&(lo, hi)| lo <= c && c <= hi),
+        }
+    }
+}
+
+impl Script {
+    fn ranges(self) -> &'static [(char, char)] {
+        match self {
+            Script::Latin => &[
+                ('A', 'Z'),
+                ('a', 'z'),
+                ('\u{aa}', '\u{aa}'),
+                ('\u{ba}', '\u{ba}'),
+                ('\u{c0}', '\u{d6}'),
+                ('\u{d8}', '\u{f6}'),
+                ('\u{f8}', '\u{24f}'),
+                ('\u{1e00}', '\u{1eff}'),
+                ('\u{2c60}', '\u{2c7f}'),
+                ('\u{a720}', '\u{a7ff}'),
+                ('\u{ab30}', '\u{ab6f}'),
+                ('\u{ff21}', '\u{ff3a}'),
+                ('\u{ff41}', '\u{ff5a}'),
+            ],
+            Script::Greek => &[('\u{370}', '\u{3ff}'), ('\u{1f00}', '\u{1fff}')],
+            Script::Cyrillic => &[
+                ('\u{400}', '\u{52f}'),
+                ('\u{1c80}', '\u{1c8f}'),
+                ('\u{2de0}', '\u{2dff}'),
+                ('\u{a640}', '\u{a69f}'),
+            ],
+            Script::Arabic => &[
+                ('\u{600}', '\u{6ff}'),
+                ('\u{750}', '\u{77f}'),
+                ('\u{8a0}', '\u{8ff}'),
+                ('\u{fb50}', '\u{fdff}'),
+                ('\u{fe70}', '\u{feff}'),
+            ],
+            Script::Hebrew => &[('\u{591}', '\u{5f4}'), ('\u{fb1d}', '\u{fb4f}')],
+            Script::Han => &[
+                ('\u{2e80}', '\u{2fdf}'),
+                ('\u{3005}', '\u{3007}'),
+                ('\u{3021}', '\u{3029}'),
+                ('\u{3400}', '\u{4dbf}'),
+                ('\u{4e00}', '\u{9fff}'),
+                ('\u{f900}', '\u{faff}'),
+                ('\u{20000}', '\u{323af}'),
+            ],
+            Script::Hiragana => &[('\u{3041}', '\u{309f}')],
+            Script::Katakana => &[('\u{30a0}', '\u{30ff}'), ('\u{31f0}', '\u{31ff}')],
+        }
+    }
+}
+
+/// Letters, digits and underscore, in any script.
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
@@ -0,0 +1,154 @@
+use super::class::Class;
+use super::parse::{Ast, Look};
+use super::RegexError;
+use crate::casefold;
+
+/// Largest program a pattern may compile to, so `(a{1000}){1000}` fails
+/// up front instead of eating all memory.
+const MAX_INSTS: usize = 100_000;
+
+#[derive(Debug, Clone)]
+pub enum Inst {
+    Char(char),
+    /// A character compared ignoring case, by its simple case folding.
+    CharFold(char),
+    Class { class: Class, fold: bool },
+    Any,
+    Look(Look),
+    /// Record the current position in a capture slot.
+    Save(usize),
+    /// Continue at both targets, preferring the first.
+    Split(usize, usize),
+    Jmp(usize),
+    Match,
+}
+
+struct Compiler {
+    insts: Vec<Inst>,
+}
+
+pub fn compile(ast: &Ast) -> Result<Vec<Inst>, RegexError> {
+    let mut compiler = Compiler { insts: Vec::new() };
+
+    compiler.push(Inst::Save(0))?;
+    compiler.compile(ast)?;
+    compiler.push(Inst::Save(1))?;
+    compiler.push(Inst::Match)?;
+
+    Ok(compiler.insts)
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> Result<usize, RegexError> {
+        if self.insts.len() >= MAX_INSTS {
+            return Err(RegexError { message: "pattern too large".to_string(), position: 0 });
+        }
+        self.insts.push(inst);
+        Ok(self.insts.len() - 1)
+    }
+
+    /// Point the `Split` or `Jmp` at `pc` to `target`. Splits are patched
+    /// in their second branch.
+    fn patch(&mut self, pc: usize, target: usize) {
+        match &mut self.insts[pc] {
+            Inst::Split(_, second) => *second = target,
+            Inst::Jmp(to) => *to = target,
+            _ => unreachable!("only jumps are patched"),
+        }
+    }
+
+    fn compile(&mut self, ast: &Ast) -> Result<(), RegexError> {
+        match ast {
+            Ast::Empty => {}
+            Ast::Literal { c, fold: true } if casefold::variants(*c).nth(1).is_some() => {
+                self.push(Inst::CharFold(casefold::simple(*c)))?;
+            }
+            Ast::Literal { c, .. } => {
+                self.push(Inst::Char(*c))?;
+            }
+            Ast::Class { class, fold } => {
+                self.push(Inst::Class { class: class.clone(), fold: *fold })?;
+            }
+            Ast::Any => {
+                self.push(Inst::Any)?;
+            }
+            Ast::Look(look) => {
+                self.push(Inst::Look(*look))?;
+            }
+            Ast::Group { ast, index: Some(index) } => {
+                self.push(Inst::Save(index * 2))?;
+                self.compile(ast)?;
+                self.push(Inst::Save(index * 2 + 1))?;
+            }
+            Ast::Group { ast, index: None } => self.compile(ast)?,
+            Ast::Concat(items) => {
+                for item in items {
+                    self.compile(item)?;
+                }
+            }
+            Ast::Alternate(branches) => {
+                let mut jumps = Vec::new();
+
+                for (i, branch) in branches.iter().enumerate() {
+                    if i + 1 == branches.len() {
+                        self.compile(branch)?;
+                        break;
+                    }
+                    let split = self.push(Inst::Split(0, 0))?;
+                    self.insts[split] = Inst::Split(split + 1, 0);
+                    self.compile(branch)?;
+                    jumps.push(self.push(Inst::Jmp(0))?);
+                    let next = self.insts.len();
+                    self.patch(split, next);
+                }
+
+                let end = self.insts.len();
+                for jump in jumps {
+                    self.patch(jump, end);
+                }
+            }
+            Ast::Repeat { ast, min, max, greedy } => self.compile_repeat(ast, *min, *max, *greedy)?,
+        }
+
+        Ok(())
+    }
+
+    fn compile_repeat(&mut self, ast: &Ast, min: u32, max: Option<u32>, greedy: bool) -> Result<(), RegexError> {
+        for _ in 0..min {
+            self.compile(ast)?;
+        }
+
+        match max {
+            // x* after the required copies: L: split body, end; body; jmp L
+            None => {
+                let split = self.push(Inst::Split(0, 0))?;
+                self.compile(ast)?;
+                self.push(Inst::Jmp(split))?;
+                let end = self.insts.len();
+                self.insts[split] = self.split(split + 1, end, greedy);
+            }
+            // Each optional copy may be skipped, which skips all later ones too.
+            Some(max) => {
+                let mut splits = Vec::new();
+                for _ in min..max {
+                    splits.push(self.push(Inst::Split(0, 0))?);
+                    self.compile(ast)?;
+                }
+                let end = self.insts.len();
+                for split in splits {
+                    self.insts[split] = self.split(split + 1, end, greedy);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn split(&self, body: usize, skip: usize, greedy: bool) -> Inst {
+        if greedy {
+            Inst::Split(body, skip)
+        } else {
+            Inst::Split(skip, body)
+        }
+    }
+}
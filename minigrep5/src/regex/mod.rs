@@ -0,0 +1,344 @@
+//! A small regular expression engine for `-E`.
+//!
+//! Patterns are parsed into a syntax tree, compiled to a Thompson NFA and run
+//! with a Pike VM, which walks every possible path through the NFA at once.
+//! That keeps matching linear in the length of the text, however the
+//! pattern is written: there is no backtracking to blow up.
+//!
+//! Supported syntax: literals and `\` escapes, `.`, `[...]` classes with
+//! ranges, negation and `[:alpha:]`-style POSIX classes, `\d \w \s` and their
+//! negations, Unicode classes like `\pL` or `\p{Greek}`, the anchors `^ $`,
+//! word boundaries `\b \B`, groups `(...)`, `(?:...)` and `(?P<name>...)`,
+//! alternation `|`, the quantifiers `* + ? {m} {m,} {m,n}` with lazy `?`
+//! variants, and the `(?i)` flag.
+
+mod class;
+mod compile;
+mod parse;
+mod vm;
+
+use std::error::Error;
+use std::fmt;
+
+use crate::Bounds;
+use compile::Inst;
+use parse::{Ast, Look};
+
+pub(crate) use class::is_word_char;
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    prog: Vec<Inst>,
+    names: Vec<Option<String>>,
+}
+
+/// Why a pattern was rejected, and the byte offset in the pattern it happened at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegexError {
+    pub message: String,
+    pub position: usize,
+}
+
+/// The groups of one match.
+#[derive(Debug)]
+pub struct Captures<'t> {
+    text: &'t str,
+    slots: vm::Slots,
+    names: &'t [Option<String>],
+}
+
+/// Successive non-overlapping matches, as `(start, end)` byte offsets.
+pub struct Matches<'r, 't> {
+    regex: &'r Regex,
+    text: &'t str,
+    at: usize,
+    last_end: Option<usize>,
+    /// How many capture slots to fill in: just the whole match's two, for
+    /// plain matches.
+    slot_count: usize,
+}
+
+/// The groups of successive non-overlapping matches.
+pub struct CaptureMatches<'t>(Matches<'t, 't>);
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, RegexError> {
+        Regex::with_bounds(pattern, false, Bounds::Any)
+    }
+
+    /// Like `new`, as if the pattern started with `(?i)`.
+    pub fn case_insensitive(pattern: &str) -> Result<Regex, RegexError> {
+        Regex::with_bounds(pattern, true, Bounds::Any)
+    }
+
+    /// Like `new` or `case_insensitive`, but only matching whole words or
+    /// whole lines of the text, as `-w` and `-x` ask for. The pattern is
+    /// wrapped in assertions rather than rewritten, so when its longest
+    /// match isn't a whole word a shorter one that is still counts.
+    pub fn with_bounds(pattern: &str, case_insensitive: bool, bounds: Bounds) -> Result<Regex, RegexError> {
+        let parsed = parse::parse(pattern, case_insensitive)?;
+        let ast = match bounds {
+            Bounds::Any => parsed.ast,
+            Bounds::Words => Ast::Concat(vec![Ast::Look(Look::NotAfterWord), parsed.ast, Ast::Look(Look::NotBeforeWord)]),
+            Bounds::Line => Ast::Concat(vec![Ast::Look(Look::Start), parsed.ast, Ast::Look(Look::End)]),
+        };
+        let prog = compile::compile(&ast)?;
+        Ok(Regex { prog, names: parsed.names })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find_at(text, 0).is_some()
+    }
+
+    /// The first match starting at or after byte offset `start`.
+    pub fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+        let slots = vm::search(&self.prog, text, start, 2)?;
+        Some((slots[0]?, slots[1]?))
+    }
+
+    pub fn find_iter<'r, 't>(&'r self, text: &'t str) -> Matches<'r, 't> {
+        Matches { regex: self, text, at: 0, last_end: None, slot_count: 2 }
+    }
+
+    pub fn captures<'t>(&'t self, text: &'t str) -> Option<Captures<'t>> {
+        let slots = vm::search(&self.prog, text, 0, self.names.len() * 2)?;
+        Some(Captures { text, slots, names: &self.names })
+    }
+
+    pub fn captures_iter<'t>(&'t self, text: &'t str) -> CaptureMatches<'t> {
+        CaptureMatches(Matches { regex: self, text, at: 0, last_end: None, slot_count: self.names.len() * 2 })
+    }
+
+    /// `text` with every match replaced by `replacement`, in which `$1` or
+    /// `${1}` stands for a group, `$name` or `${name}` for a named one and
+    /// `$$` for a dollar sign. Groups that didn't take part are empty.
+    pub fn replace_all(&self, text: &str, replacement: &str) -> String {
+        let mut replaced = String::with_capacity(text.len());
+        let mut last = 0;
+        for caps in self.captures_iter(text) {
+            let (start, end) = (caps.slots[0].unwrap_or(last), caps.slots[1].unwrap_or(last));
+            replaced.push_str(&text[last..start]);
+            caps.expand(replacement, &mut replaced);
+            last = end;
+        }
+        replaced.push_str(&text[last..]);
+        replaced
+    }
+}
+
+impl<'t> Captures<'t> {
+    /// Group `i`, where group 0 is the whole match.
+    pub fn get(&self, i: usize) -> Option<&'t str> {
+        let start = (*self.slots.get(i * 2)?)?;
+        let end = (*self.slots.get(i * 2 + 1)?)?;
+        Some(&self.text[start..end])
+    }
+
+    pub fn name(&self, name: &str) -> Option<&'t str> {
+        let i = self.names.iter().position(|n| n.as_deref() == Some(name))?;
+        self.get(i)
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Append `replacement` to `dst`, with the groups it refers to filled
+    /// in, as `Regex::replace_all` describes.
+    pub fn expand(&self, replacement: &str, dst: &mut String) {
+        let mut rest = replacement;
+        while let Some(i) = rest.find('$') {
+            dst.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+
+            let (reference, after) = if let Some(braced) = rest.strip_prefix('{') {
+                match braced.split_once('}') {
+                    Some((reference, after)) => (reference, after),
+                    None => ("", rest),
+                }
+            } else {
+                let len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+                (&rest[..len], &rest[len..])
+            };
+
+            if reference.is_empty() {
+                // `$$` is a dollar sign, and so is a `$` referring to nothing.
+                dst.push('$');
+                rest = rest.strip_prefix('$').unwrap_or(rest);
+                continue;
+            }
+            let group = match reference.parse::<usize>() {
+                Ok(i) => self.get(i),
+                Err(_) => self.name(reference),
+            };
+            dst.push_str(group.unwrap_or(""));
+            rest = after;
+        }
+        dst.push_str(rest);
+    }
+}
+
+impl<'r, 't> Matches<'r, 't> {
+    fn next_slots(&mut self) -> Option<vm::Slots> {
+        loop {
+            if self.at > self.text.len() {
+                return None;
+            }
+            let slots = vm::search(&self.regex.prog, self.text, self.at, self.slot_count)?;
+            let (start, end) = (slots[0]?, slots[1]?);
+
+            if start == end {
+                // Step past an empty match, but never report one right
+                // where the previous match ended.
+                self.at = end + self.text[end..].chars().next().map_or(1, char::len_utf8);
+                if self.last_end == Some(end) {
+                    continue;
+                }
+            } else {
+                self.at = end;
+            }
+
+            self.last_end = Some(end);
+            return Some(slots);
+        }
+    }
+}
+
+impl<'r, 't> Iterator for Matches<'r, 't> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        let slots = self.next_slots()?;
+        Some((slots[0]?, slots[1]?))
+    }
+}
+
+impl<'t> Iterator for CaptureMatches<'t> {
+    type Item = Captures<'t>;
+
+    fn next(&mut self) -> Option<Captures<'t>> {
+        let slots = self.0.next_slots()?;
+        Some(Captures { text: self.0.text, slots, names: &self.0.regex.names })
+    }
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl Error for RegexError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find<'t>(pattern: &str, text: &'t str) -> Option<&'t str> {
+        let (start, end) = Regex::new(pattern).unwrap().find_at(text, 0)?;
+        Some(&text[start..end])
+    }
+
+    #[test]
+    fn literals_classes_and_anchors() {
+        assert_eq!(Some("fast"), find("f[a-z]st", "safe, fast, productive."));
+        assert_eq!(Some("Rust"), find("^R\\w+", "Rust:"));
+        assert_eq!(None, find("^fast", "safe, fast"));
+        assert_eq!(Some("three."), find("t.*\\.$", "Pick three."));
+        assert_eq!(Some("42"), find("[[:digit:]]+", "answer: 42"));
+        assert_eq!(Some("x-y"), find("[a-z-]+", "x-y"));
+        assert_eq!(Some("b"), find("[^a]", "aab"));
+        assert_eq!(Some("duct"), find("\\bduct\\b", "productive duct"));
+    }
+
+    #[test]
+    fn repetition_and_alternation() {
+        assert_eq!(Some("aaa"), find("a{2,3}", "aaaa"));
+        assert_eq!(Some("aa"), find("a{2,3}?", "aaaa"));
+        assert_eq!(Some("<b>"), find("<.+?>", "<b>bold</b>"));
+        assert_eq!(Some("cat"), find("dog|cat", "a cat and a dog"));
+        assert_eq!(Some("a"), find("a|ab", "ab"));
+        assert_eq!(Some("ab"), find("ab|a", "ab"));
+        assert_eq!(Some("abab"), find("(?:ab)+", "ababa"));
+        assert_eq!(Some("a{b"), find("a{b", "a{b"));
+    }
+
+    #[test]
+    fn unicode_and_case() {
+        assert_eq!(Some("λόγος"), find("\\p{Greek}+", "the λόγος"));
+        assert_eq!(Some("Ünïcode"), find("\\pL+", "Ünïcode!"));
+        assert_eq!(Some("RuSt"), find("(?i)rust", "RuSt"));
+        assert!(Regex::case_insensitive("αθήνα").unwrap().is_match("ΑΘΉΝΑ"));
+        assert_eq!(Some("ΣΟΦΟΣ"), find("(?i)σοφος", "ΣΟΦΟΣ"));
+        assert_eq!(Some("\u{212A}elvin"), find("(?i)[a-z]+", "\u{212A}elvin"));
+        assert_eq!(Some("ß"), find("(?i)ẞ", "ß"));
+        assert_eq!(Some("Rust"), find("(?i:r)ust", "Rust"));
+        assert_eq!(None, find("(?i:r)ust", "RUST"));
+    }
+
+    #[test]
+    fn whole_words_and_lines() {
+        let find = |pattern, bounds, text| {
+            let (start, end) = Regex::with_bounds(pattern, false, bounds).unwrap().find_at(text, 0)?;
+            Some(&text[start..end])
+        };
+        assert_eq!(None, find("to", Bounds::Words, "tomorrow, into"));
+        assert_eq!(Some("to"), find("to", Bounds::Words, "into to"));
+        assert_eq!(Some("foo"), find("foo|foo-b", Bounds::Words, "foo-bar"));
+        assert_eq!(Some("-x-"), find("-x-", Bounds::Words, "a -x- b"));
+        assert_eq!(Some("üna"), find("\\w+a", Bounds::Words, "sünax üna"));
+        assert_eq!(None, find("a|b", Bounds::Line, "ab"));
+        assert_eq!(Some("ab"), find("a|ab", Bounds::Line, "ab"));
+    }
+
+    #[test]
+    fn replaces() {
+        let regex = Regex::new("(?P<key>\\w+)=(\\w*)").unwrap();
+        assert_eq!("lang: rust, ed: 2021", regex.replace_all("lang=rust, ed=2021", "$key: $2"));
+        assert_eq!("[lang]x $ $", regex.replace_all("lang=rust", "[${1}]x $$ $"));
+        assert_eq!("=", regex.replace_all("a=b", "$3${nope}=$x"));
+        assert_eq!("-a-b-", Regex::new("x*").unwrap().replace_all("ab", "-"));
+        assert_eq!("ça va", Regex::new("(?i)Ç").unwrap().replace_all("Ça va", "ç"));
+    }
+
+    #[test]
+    fn captures() {
+        let regex = Regex::new("(?P<key>\\w+)=(\\w*)").unwrap();
+        let caps = regex.captures("set lang=rust").unwrap();
+        assert_eq!(Some("lang=rust"), caps.get(0));
+        assert_eq!(Some("lang"), caps.name("key"));
+        assert_eq!(Some("rust"), caps.get(2));
+
+        let regex = Regex::new("(a)|(b)").unwrap();
+        let caps = regex.captures("b").unwrap();
+        assert_eq!(None, caps.get(1));
+        assert_eq!(Some("b"), caps.get(2));
+    }
+
+    #[test]
+    fn finds_every_match() {
+        let regex = Regex::new("a*").unwrap();
+        let matches: Vec<_> = regex.find_iter("baaab").collect();
+        assert_eq!(vec![(0, 0), (1, 4), (5, 5)], matches);
+    }
+
+    #[test]
+    fn runs_in_linear_time() {
+        // Catastrophic for a backtracking engine.
+        let text = "a".repeat(5000);
+        assert!(!Regex::new("(a*)*b").unwrap().is_match(&text));
+        assert!(!Regex::new("(a|aa)+$x").unwrap().is_match(&text));
+    }
+
+    #[test]
+    fn rejects_bad_patterns() {
+        for pattern in ["(", "a)", "[a", "*a", "a{3,2}", "a{1001}", "\\q", "\\p{Klingon}", "(?P<x>a)(?P<x>b)"] {
+            assert!(Regex::new(pattern).is_err(), "{} should be rejected", pattern);
+        }
+        assert_eq!(1, Regex::new("a)").unwrap_err().position);
+    }
+}
@@ -0,0 +1,414 @@
+use super::class::{Class, ClassItem, Property};
+use super::RegexError;
+
+/// Most repetitions `{m,n}` may ask for.
+const MAX_REPEAT: u32 = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ast {
+    Empty,
+    Literal { c: char, fold: bool },
+    Class { class: Class, fold: bool },
+    /// `.`, anything but a line break.
+    Any,
+    Look(Look),
+    Group { ast: Box<Ast>, index: Option<usize> },
+    Concat(Vec<Ast>),
+    Alternate(Vec<Ast>),
+    Repeat { ast: Box<Ast>, min: u32, max: Option<u32>, greedy: bool },
+}
+
+/// Zero-width assertions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Look {
+    Start,
+    End,
+    WordBoundary,
+    NotWordBoundary,
+    /// No word character right before, as at the start of a `-w` match.
+    NotAfterWord,
+    /// No word character right after, as at the end of one.
+    NotBeforeWord,
+}
+
+/// The parsed pattern and the names of its capture groups.
+pub struct Parsed {
+    pub ast: Ast,
+    /// Index 0 is the whole match, which has no name.
+    pub names: Vec<Option<String>>,
+}
+
+struct Parser<'a> {
+    pattern: &'a str,
+    chars: Vec<(usize, char)>,
+    pos: usize,
+    names: Vec<Option<String>>,
+}
+
+pub fn parse(pattern: &str, case_insensitive: bool) -> Result<Parsed, RegexError> {
+    let mut parser = Parser {
+        pattern,
+        chars: pattern.char_indices().collect(),
+        pos: 0,
+        names: vec![None],
+    };
+
+    let ast = parser.parse_alternation(case_insensitive)?;
+    if let Some(c) = parser.peek() {
+        return Err(parser.error(&format!("unexpected '{}'", c)));
+    }
+
+    Ok(Parsed { ast, names: parser.names })
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).map(|&(_, c)| c)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn offset(&self) -> usize {
+        self.chars.get(self.pos).map_or(self.pattern.len(), |&(i, _)| i)
+    }
+
+    fn error(&self, message: &str) -> RegexError {
+        RegexError { message: message.to_string(), position: self.offset() }
+    }
+
+    fn parse_alternation(&mut self, fold: bool) -> Result<Ast, RegexError> {
+        // An inline (?i) lasts until the end of the enclosing group, across `|`.
+        let mut fold = fold;
+        let mut branches = vec![self.parse_concat(&mut fold)?];
+
+        while self.eat('|') {
+            branches.push(self.parse_concat(&mut fold)?);
+        }
+
+        Ok(if branches.len() == 1 { branches.pop().unwrap() } else { Ast::Alternate(branches) })
+    }
+
+    fn parse_concat(&mut self, fold: &mut bool) -> Result<Ast, RegexError> {
+        let mut items = Vec::new();
+
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+
+            let atom = match self.parse_atom(fold)? {
+                Some(atom) => atom,
+                None => continue,
+            };
+            let atom = self.parse_repeat(atom)?;
+            items.push(atom);
+        }
+
+        Ok(match items.len() {
+            0 => Ast::Empty,
+            1 => items.pop().unwrap(),
+            _ => Ast::Concat(items),
+        })
+    }
+
+    /// Parse one atom. Returns `None` for flag groups like `(?i)`, which only
+    /// change how the rest of the group is parsed.
+    fn parse_atom(&mut self, fold: &mut bool) -> Result<Option<Ast>, RegexError> {
+        let start = self.pos;
+        let c = self.next().unwrap();
+
+        let ast = match c {
+            '.' => Ast::Any,
+            '^' => Ast::Look(Look::Start),
+            '$' => Ast::Look(Look::End),
+            '[' => Ast::Class { class: self.parse_class()?, fold: *fold },
+            '(' => return self.parse_group(fold),
+            '\\' => self.parse_escape(*fold)?,
+            '*' | '+' | '?' => {
+                self.pos = start;
+                return Err(self.error("repetition operator with nothing to repeat"));
+            }
+            '{' if self.looks_like_repeat() => {
+                self.pos = start;
+                return Err(self.error("repetition operator with nothing to repeat"));
+            }
+            c => Ast::Literal { c, fold: *fold },
+        };
+
+        Ok(Some(ast))
+    }
+
+    fn parse_group(&mut self, fold: &mut bool) -> Result<Option<Ast>, RegexError> {
+        let mut index = None;
+        let mut inner_fold = *fold;
+
+        if self.eat('?') {
+            if self.eat(':') {
+                // Non-capturing group.
+            } else if self.eat('P') || self.peek() == Some('<') {
+                if !self.eat('<') {
+                    return Err(self.error("expected '<' after (?P"));
+                }
+                let mut name = String::new();
+                loop {
+                    match self.next() {
+                        Some('>') => break,
+                        Some(c) if c.is_alphanumeric() || c == '_' => name.push(c),
+                        _ => return Err(self.error("invalid capture group name")),
+                    }
+                }
+                if name.is_empty() || self.names.iter().any(|n| n.as_deref() == Some(name.as_str())) {
+                    return Err(self.error("empty or duplicate capture group name"));
+                }
+                index = Some(self.names.len());
+                self.names.push(Some(name));
+            } else {
+                let enable = !self.eat('-');
+                if !self.eat('i') {
+                    return Err(self.error("only the (?i) and (?-i) flags are supported"));
+                }
+                if self.eat(')') {
+                    *fold = enable;
+                    return Ok(None);
+                }
+                if !self.eat(':') {
+                    return Err(self.error("expected ')' or ':' after flags"));
+                }
+                inner_fold = enable;
+            }
+        } else {
+            index = Some(self.names.len());
+            self.names.push(None);
+        }
+
+        let ast = self.parse_alternation(inner_fold)?;
+        if !self.eat(')') {
+            return Err(self.error("unclosed group"));
+        }
+
+        Ok(Some(Ast::Group { ast: Box::new(ast), index }))
+    }
+
+    fn parse_repeat(&mut self, mut ast: Ast) -> Result<Ast, RegexError> {
+        loop {
+            let start = self.pos;
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') if self.looks_like_repeat() => {
+                    self.pos += 1;
+                    self.parse_counts()?
+                }
+                _ => return Ok(ast),
+            };
+            if self.pos == start {
+                self.pos += 1;
+            }
+
+            if let Ast::Look(_) | Ast::Empty = ast {
+                self.pos = start;
+                return Err(self.error("repetition operator with nothing to repeat"));
+            }
+
+            let greedy = !self.eat('?');
+            ast = Ast::Repeat { ast: Box::new(ast), min, max, greedy };
+        }
+    }
+
+    /// Whether a `{` starts a counted repetition rather than being a literal.
+    fn looks_like_repeat(&self) -> bool {
+        let rest: String = self.chars[self.pos..].iter().map(|&(_, c)| c).take_while(|&c| c != '}').collect();
+        let rest = rest.strip_prefix('{').unwrap_or(&rest);
+        self.chars[self.pos..].iter().any(|&(_, c)| c == '}')
+            && !rest.is_empty()
+            && rest.chars().all(|c| c.is_ascii_digit() || c == ',')
+            && rest.starts_with(|c: char| c.is_ascii_digit())
+    }
+
+    fn parse_counts(&mut self) -> Result<(u32, Option<u32>), RegexError> {
+        let min = self.parse_number()?;
+        let max = if self.eat(',') {
+            if self.peek() == Some('}') { None } else { Some(self.parse_number()?) }
+        } else {
+            Some(min)
+        };
+
+        if !self.eat('}') {
+            return Err(self.error("unclosed counted repetition"));
+        }
+        if max.is_some_and(|max| max < min) {
+            return Err(self.error("invalid repetition range"));
+        }
+        if min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT) {
+            return Err(self.error("repetition count too large"));
+        }
+
+        Ok((min, max))
+    }
+
+    fn parse_number(&mut self) -> Result<u32, RegexError> {
+        let mut digits = String::new();
+        while let Some(c) = self.peek().filter(char::is_ascii_digit) {
+            digits.push(c);
+            self.pos += 1;
+        }
+        digits.parse().map_err(|_| self.error("expected a number"))
+    }
+
+    fn parse_escape(&mut self, fold: bool) -> Result<Ast, RegexError> {
+        let c = self.next().ok_or_else(|| self.error("trailing backslash"))?;
+
+        let property = |property, negated| Ast::Class {
+            class: Class { items: vec![ClassItem::Property(property, negated)], negated: false },
+            fold: false,
+        };
+
+        Ok(match c {
+            'd' => property(Property::Digit, false),
+            'D' => property(Property::Digit, true),
+            'w' => property(Property::Word, false),
+            'W' => property(Property::Word, true),
+            's' => property(Property::Space, false),
+            'S' => property(Property::Space, true),
+            'p' | 'P' => property(self.parse_property_name()?, c == 'P'),
+            'b' => Ast::Look(Look::WordBoundary),
+            'B' => Ast::Look(Look::NotWordBoundary),
+            'A' => Ast::Look(Look::Start),
+            'z' => Ast::Look(Look::End),
+            _ => Ast::Literal { c: self.escaped_char(c)?, fold },
+        })
+    }
+
+    /// The character an escape such as `\t` or `\.` stands for.
+    fn escaped_char(&mut self, c: char) -> Result<char, RegexError> {
+        match c {
+            't' => Ok('\t'),
+            'n' => Ok('\n'),
+            'r' => Ok('\r'),
+            'x' => {
+                let braced = self.eat('{');
+                let mut hex = String::new();
+                while let Some(c) = self.peek().filter(char::is_ascii_hexdigit) {
+                    if !braced && hex.len() == 2 {
+                        break;
+                    }
+                    hex.push(c);
+                    self.pos += 1;
+                }
+                if braced && !self.eat('}') {
+                    return Err(self.error("unclosed \\x{...} escape"));
+                }
+                u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| self.error("invalid \\x escape"))
+            }
+            c if c.is_ascii_alphanumeric() => Err(self.error(&format!("unknown escape \\{}", c))),
+            c => Ok(c),
+        }
+    }
+
+    fn parse_property_name(&mut self) -> Result<Property, RegexError> {
+        let name = if self.eat('{') {
+            let mut name = String::new();
+            loop {
+                match self.next() {
+                    Some('}') => break,
+                    Some(c) => name.push(c),
+                    None => return Err(self.error("unclosed \\p{...}")),
+                }
+            }
+            name
+        } else {
+            self.next().map(String::from).unwrap_or_default()
+        };
+
+        Property::from_name(&name).ok_or_else(|| self.error(&format!("unknown Unicode class {}", name)))
+    }
+
+    fn parse_class(&mut self) -> Result<Class, RegexError> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+
+        loop {
+            let c = self.next().ok_or_else(|| self.error("unclosed character class"))?;
+
+            let lo = match c {
+                ']' if !first => break,
+                '[' if self.peek() == Some(':') => {
+                    self.pos += 1;
+                    let mut name = String::new();
+                    while let Some(c) = self.next() {
+                        if c == ':' && self.eat(']') {
+                            break;
+                        }
+                        name.push(c);
+                    }
+                    let property = Property::from_name(&name)
+                        .ok_or_else(|| self.error(&format!("unknown class [:{}:]", name)))?;
+                    items.push(ClassItem::Property(property, false));
+                    first = false;
+                    continue;
+                }
+                '\\' => {
+                    let c = self.next().ok_or_else(|| self.error("trailing backslash"))?;
+                    let property = match c {
+                        'd' | 'D' => Some((Property::Digit, c == 'D')),
+                        'w' | 'W' => Some((Property::Word, c == 'W')),
+                        's' | 'S' => Some((Property::Space, c == 'S')),
+                        'p' | 'P' => Some((self.parse_property_name()?, c == 'P')),
+                        _ => None,
+                    };
+                    if let Some((property, negated)) = property {
+                        items.push(ClassItem::Property(property, negated));
+                        first = false;
+                        continue;
+                    }
+                    self.escaped_char(c)?
+                }
+                c => c,
+            };
+            first = false;
+
+            // A '-' right before the closing bracket is a literal.
+            let is_range = self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&(_, c)| c != ']');
+            if !is_range {
+                items.push(ClassItem::Range(lo, lo));
+                continue;
+            }
+
+            self.pos += 1;
+            let hi = match self.next() {
+                Some('\\') => {
+                    let c = self.next().ok_or_else(|| self.error("trailing backslash"))?;
+                    self.escaped_char(c)?
+                }
+                Some(c) => c,
+                None => return Err(self.error("unclosed character class")),
+            };
+            if hi < lo {
+                return Err(self.error("invalid character class range"));
+            }
+            items.push(ClassItem::Range(lo, hi));
+        }
+
+        Ok(Class { items, negated })
+    }
+}
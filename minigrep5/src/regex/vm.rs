@@ -0,0 +1,127 @@
+use super::class::is_word_char;
+use super::compile::Inst;
+use crate::casefold;
+use super::parse::Look;
+
+/// Capture slots: the start and end offset of each group, whole match first.
+pub type Slots = Vec<Option<usize>>;
+
+/// The threads alive at one position, in priority order. A program counter
+/// is only ever added once per position, which is what keeps the search
+/// linear in the length of the text.
+struct Threads {
+    list: Vec<(usize, Slots)>,
+    seen: Vec<usize>,
+    generation: usize,
+}
+
+impl Threads {
+    fn new(len: usize) -> Threads {
+        Threads { list: Vec::new(), seen: vec![usize::MAX; len], generation: 0 }
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        self.generation += 1;
+    }
+
+    /// Add the thread at `pc`, following jumps, splits, saves and
+    /// assertions until it reaches instructions that consume a character.
+    fn add(&mut self, prog: &[Inst], text: &str, at: usize, pc: usize, slots: Slots) {
+        let mut stack = vec![(pc, slots)];
+
+        while let Some((pc, mut slots)) = stack.pop() {
+            if self.seen[pc] == self.generation {
+                continue;
+            }
+            self.seen[pc] = self.generation;
+
+            match &prog[pc] {
+                Inst::Jmp(to) => stack.push((*to, slots)),
+                Inst::Split(first, second) => {
+                    // Pushed in reverse so the preferred branch is explored first.
+                    stack.push((*second, slots.clone()));
+                    stack.push((*first, slots));
+                }
+                Inst::Save(slot) => {
+                    if *slot < slots.len() {
+                        slots[*slot] = Some(at);
+                    }
+                    stack.push((pc + 1, slots));
+                }
+                Inst::Look(look) => {
+                    if holds(*look, text, at) {
+                        stack.push((pc + 1, slots));
+                    }
+                }
+                _ => self.list.push((pc, slots)),
+            }
+        }
+    }
+}
+
+/// Find the leftmost-first match starting at or after `start`, filling in
+/// `slot_count` capture slots.
+pub fn search(prog: &[Inst], text: &str, start: usize, slot_count: usize) -> Option<Slots> {
+    let mut current = Threads::new(prog.len());
+    let mut next = Threads::new(prog.len());
+    let mut matched = None;
+    let mut at = start;
+
+    loop {
+        // A new attempt starts here, with lower priority than the ones
+        // already under way, until something has matched.
+        if matched.is_none() {
+            current.add(prog, text, at, 0, vec![None; slot_count]);
+        }
+        if current.list.is_empty() && matched.is_some() {
+            break;
+        }
+
+        let c = text[at..].chars().next();
+        let next_at = at + c.map_or(0, char::len_utf8);
+        next.clear();
+
+        for (pc, slots) in current.list.drain(..) {
+            let step = match (&prog[pc], c) {
+                (Inst::Match, _) => {
+                    // Every thread after this one has lower priority.
+                    matched = Some(slots);
+                    break;
+                }
+                (_, None) => false,
+                (Inst::Char(expected), Some(c)) => *expected == c,
+                (Inst::CharFold(folded), Some(c)) => casefold::simple(c) == *folded,
+                (Inst::Class { class, fold: false }, Some(c)) => class.matches(c),
+                (Inst::Class { class, fold: true }, Some(c)) => class.matches_ignoring_case(c),
+                (Inst::Any, Some(c)) => c != '\n',
+                _ => unreachable!("only consuming instructions are queued"),
+            };
+            if step {
+                next.add(prog, text, next_at, pc + 1, slots);
+            }
+        }
+
+        if c.is_none() {
+            break;
+        }
+        std::mem::swap(&mut current, &mut next);
+        at = next_at;
+    }
+
+    matched
+}
+
+fn holds(look: Look, text: &str, at: usize) -> bool {
+    let before = text[..at].chars().next_back().is_some_and(is_word_char);
+    let after = text[at..].chars().next().is_some_and(is_word_char);
+
+    match look {
+        Look::Start => at == 0,
+        Look::End => at == text.len(),
+        Look::WordBoundary => before != after,
+        Look::NotWordBoundary => before == after,
+        Look::NotAfterWord => !before,
+        Look::NotBeforeWord => !after,
+    }
+}
@@ -0,0 +1,212 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process;
+
+use crate::{Config, Matcher};
+
+/// Replaces matches in whole files, for `--replace`.
+///
+/// By default nothing is changed: what would be is printed as a unified
+/// diff instead. With `--write` each file is rewritten in place, through a
+/// temporary file next to it that's renamed over the original, so readers
+/// see either the old contents or the new ones and never half of each.
+pub struct Replacer<'m> {
+    matcher: &'m Matcher,
+    replacement: &'m str,
+    write: bool,
+    text: bool,
+}
+
+/// A line that the replacement changed.
+struct Change<'c> {
+    /// Counted from 1.
+    number: usize,
+    old: &'c str,
+    new: String,
+}
+
+impl<'m> Replacer<'m> {
+    pub fn new(matcher: &'m Matcher, config: &'m Config) -> Replacer<'m> {
+        Replacer {
+            matcher,
+            replacement: config.replace.as_deref().unwrap_or_default(),
+            write: config.write,
+            text: config.text,
+        }
+    }
+
+    /// Replace the matches in the file at `path`, or in standard input for
+    /// `-`, and print the diff to `out` unless writing. Returns whether
+    /// anything was replaced.
+    ///
+    /// Binary files are skipped unless `-a` was given. Files that aren't
+    /// valid UTF-8 are never changed, since they couldn't be written back
+    /// as they were.
+    pub fn replace<W: Write>(&self, path: &Path, out: &mut W) -> io::Result<bool> {
+        let stdin = path == Path::new("-");
+        if stdin && self.write {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "can't write to standard input"));
+        }
+
+        let mut bytes = Vec::new();
+        if stdin {
+            io::stdin().lock().read_to_end(&mut bytes)?;
+        } else {
+            File::open(path)?.read_to_end(&mut bytes)?;
+        }
+        if !self.text && bytes.contains(&0) {
+            return Ok(false);
+        }
+        let contents = String::from_utf8(bytes)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not valid UTF-8, leaving it alone"))?;
+
+        let (replaced, changes) = self.apply(&contents);
+        if changes.is_empty() {
+            return Ok(false);
+        }
+        if self.write {
+            write_atomically(path, &replaced)?;
+        } else {
+            let label = if stdin { Path::new("(standard input)") } else { path };
+            diff(out, label, &changes)?;
+        }
+        Ok(true)
+    }
+
+    /// `contents` with every match replaced, and the lines that changed.
+    /// Line endings are kept as they were.
+    fn apply<'c>(&self, contents: &'c str) -> (String, Vec<Change<'c>>) {
+        let mut replaced = String::with_capacity(contents.len());
+        let mut changes = Vec::new();
+
+        for (i, line) in contents.split_inclusive('\n').enumerate() {
+            let old = line.strip_suffix('\n').map_or(line, |line| line.strip_suffix('\r').unwrap_or(line));
+            let ending = &line[old.len()..];
+
+            match self.matcher.replace(old, self.replacement) {
+                Some(new) if new != old => {
+                    replaced.push_str(&new);
+                    changes.push(Change { number: i + 1, old, new });
+                }
+                _ => replaced.push_str(old),
+            }
+            replaced.push_str(ending);
+        }
+        (replaced, changes)
+    }
+}
+
+/// Print `changes` to the file labelled `label` as a unified diff, one hunk
+/// per line. A replacement with newlines in it turns one line into several,
+/// which moves the new line numbers of later hunks along.
+fn diff<W: Write>(out: &mut W, label: &Path, changes: &[Change]) -> io::Result<()> {
+    writeln!(out, "--- {}", label.display())?;
+    writeln!(out, "+++ {}", label.display())?;
+
+    let mut added = 0;
+    for change in changes {
+        let new_lines: Vec<_> = change.new.split('\n').collect();
+        let new_start = change.number + added;
+        match new_lines.len() {
+            1 => writeln!(out, "@@ -{} +{} @@", change.number, new_start)?,
+            len => writeln!(out, "@@ -{} +{},{} @@", change.number, new_start, len)?,
+        }
+        writeln!(out, "-{}", change.old)?;
+        for line in &new_lines {
+            writeln!(out, "+{}", line)?;
+        }
+        added += new_lines.len() - 1;
+    }
+    Ok(())
+}
+
+/// Replace the file at `path` with `contents` in one step, keeping its
+/// permissions. A symbolic link is followed, so the link stays a link and
+/// the file it points to is what changes.
+fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let path = fs::canonicalize(path)?;
+    let permissions = fs::metadata(&path)?.permissions();
+    // The temporary file has to be on the same file system for the rename
+    // to be atomic, so it goes in the same directory.
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{}.minigrep-{}", name, process::id()));
+
+    let written = (|| {
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&temp)?;
+        file.write_all(contents.as_bytes())?;
+        file.set_permissions(permissions)?;
+        file.sync_all()?;
+        fs::rename(&temp, &path)
+    })();
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+
+    fn config(args: &[&str]) -> Config {
+        Config::new(["minigrep"].iter().chain(args).map(|s| s.to_string())).unwrap()
+    }
+
+    fn preview(args: &[&str], contents: &str) -> (bool, String) {
+        let config = config(args);
+        let matcher = Matcher::new(&config).unwrap();
+        let path = scratch(&format!("preview-{}", args.len()), contents);
+
+        let mut out = Vec::new();
+        let replaced = Replacer::new(&matcher, &config).replace(&path, &mut out).unwrap();
+        // A preview leaves the file alone.
+        assert_eq!(contents, fs::read_to_string(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+        (replaced, String::from_utf8(out).unwrap().replace(&path.display().to_string(), "f"))
+    }
+
+    fn scratch(name: &str, contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("minigrep-replace-{}-{}", name, process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn previews_a_diff() {
+        assert_eq!(
+            (true, "--- f\n+++ f\n@@ -2 +2 @@\n-key = value\n+value: key\n".to_string()),
+            preview(&["-E", "--replace=$2: $1", "(\\w+) = (\\w+)", "f"], "[table]\r\nkey = value\r\n")
+        );
+        assert_eq!(
+            (true, "--- f\n+++ f\n@@ -1 +1,2 @@\n-a b\n+a\n+b\n@@ -3 +4,2 @@\n-c d\n+c\n+d\n".to_string()),
+            preview(&["--replace", "\n", " ", "f"], "a b\nc\nc d\n")
+        );
+        assert_eq!((false, String::new()), preview(&["--replace=x", "missing", "f"], "a b\n"));
+    }
+
+    #[test]
+    fn writes_in_place_keeping_permissions() {
+        let config = config(&["-E", "-i", "--replace=${1}s", "--write", "(cat)", "f"]);
+        let matcher = Matcher::new(&config).unwrap();
+        let path = scratch("write", "Cat\ndog\ncat cat");
+        let mut permissions = fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&path, permissions.clone()).unwrap();
+
+        let mut out = Vec::new();
+        assert!(Replacer::new(&matcher, &config).replace(&path, &mut out).unwrap());
+        assert!(out.is_empty());
+        assert_eq!("Cats\ndog\ncats cats", fs::read_to_string(&path).unwrap());
+        assert_eq!(permissions, fs::metadata(&path).unwrap().permissions());
+
+        fs::remove_file(&path).unwrap();
+        // No temporary file is left behind.
+        let name = format!(".{}.minigrep-", path.file_name().unwrap().to_string_lossy());
+        assert!(!fs::read_dir(env::temp_dir())
+            .unwrap()
+            .any(|entry| entry.unwrap().file_name().to_string_lossy().starts_with(&name)));
+    }
+}
@@ -0,0 +1,264 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use crate::decode::{self, Utf16Reader};
+use crate::gzip::{self, GzipReader};
+use crate::{Config, Matcher, Printer};
+
+/// Reads input a line at a time and hands every line to a `Printer`.
+///
+/// Only the current line is held in memory, plus whatever before-context the
+/// printer keeps, so inputs of any size can be searched, pipes included.
+///
+/// Input is decoded as UTF-8, with invalid bytes replaced, or as UTF-16 when
+/// it starts with a byte order mark. With `decompress`, input that starts
+/// like gzip data is decompressed first. Input with a NUL byte is binary: rather
+/// than its lines, only `Binary file X matches` is printed, unless `text` is
+/// set.
+pub struct Searcher<'m> {
+    matcher: &'m Matcher,
+    max_count: Option<usize>,
+    invert: bool,
+    /// Only count lines, as for `-c`, `-l` and `-L`.
+    quiet: bool,
+    /// Search binary input as if it were text.
+    text: bool,
+    /// Decompress gzip input, for `-z`.
+    decompress: bool,
+}
+
+/// How far into some input a search got: the lines and bytes it read.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub offset: usize,
+}
+
+impl<'m> Searcher<'m> {
+    pub fn new(matcher: &'m Matcher, config: &Config) -> Searcher<'m> {
+        // Whether a file has any match at all is settled by its first one.
+        let list_files = config.files_with_matches || config.files_without_match;
+        Searcher {
+            matcher,
+            max_count: if list_files { Some(1) } else { config.max_count },
+            invert: config.invert,
+            quiet: list_files || config.count,
+            text: config.text,
+            decompress: config.decompress,
+        }
+    }
+
+    /// Search everything `reader` yields, returning the number of selected
+    /// lines: the matching ones, or the others when inverted. With a max
+    /// count, reading stops as soon as that many lines have been selected
+    /// and their trailing context has been printed.
+    ///
+    /// # Errors
+    ///
+    /// Fails if reading or printing does.
+    pub fn search<R: BufRead, W: Write>(&self, reader: R, path: &Path, printer: &mut Printer<W>) -> io::Result<usize> {
+        let mut reader = decode::buffer_start(reader, 2)?;
+        if self.decompress && gzip::is_gzip(reader.fill_buf()?) {
+            return self.search_text(BufReader::new(GzipReader::new(reader)), path, printer);
+        }
+        self.search_text(reader, path, printer)
+    }
+
+    /// Search what `reader` yields as the rest of a file that's been read
+    /// as far as `position`, which is moved on past what's read. Line
+    /// numbers and offsets carry on from it. The input is taken to be
+    /// UTF-8, since whatever says otherwise is at the start of a file.
+    ///
+    /// # Errors
+    ///
+    /// Fails if reading or printing does.
+    pub fn search_from<R: BufRead, W: Write>(
+        &self,
+        reader: R,
+        path: &Path,
+        printer: &mut Printer<W>,
+        position: &mut Position,
+    ) -> io::Result<usize> {
+        self.search_lines(reader, path, printer, position)
+    }
+
+    /// Search text in whichever encoding it starts out saying it's in.
+    fn search_text<R: BufRead, W: Write>(&self, reader: R, path: &Path, printer: &mut Printer<W>) -> io::Result<usize> {
+        let mut reader = decode::buffer_start(reader, 2)?;
+        match decode::utf16_bom(reader.fill_buf()?) {
+            Some(big_endian) => {
+                reader.consume(2);
+                let reader = BufReader::new(Utf16Reader::new(reader, big_endian));
+                self.search_lines(reader, path, printer, &mut Position::default())
+            }
+            None => self.search_lines(reader, path, printer, &mut Position::default()),
+        }
+    }
+
+    fn search_lines<R: BufRead, W: Write>(
+        &self,
+        mut reader: R,
+        path: &Path,
+        printer: &mut Printer<W>,
+        position: &mut Position,
+    ) -> io::Result<usize> {
+        printer.start(path)?;
+
+        // A NUL in the first buffer makes the input binary, and so does one
+        // turning up later, from that line on.
+        let mut binary = !self.text && reader.fill_buf()?.contains(&0);
+        let mut buf = Vec::new();
+        let Position { line: mut number, mut offset } = *position;
+        let mut count = 0;
+
+        loop {
+            buf.clear();
+            let read = reader.read_until(b'\n', &mut buf)?;
+            if read == 0 {
+                break;
+            }
+            number += 1;
+            binary |= !self.text && buf.contains(&0);
+
+            let line = String::from_utf8_lossy(&buf);
+            let line = line.strip_suffix('\n').map_or(&*line, |line| line.strip_suffix('\r').unwrap_or(line));
+
+            let limit_reached = self.max_count.is_some_and(|max| count >= max);
+            if limit_reached && (self.quiet || !printer.wants_context()) {
+                break;
+            }
+
+            let selected = !limit_reached && self.matcher.is_match(line) != self.invert;
+            if selected {
+                count += 1;
+            }
+            if binary && !self.quiet {
+                if selected {
+                    printer.binary()?;
+                    break;
+                }
+            } else if !self.quiet {
+                // Only lines that match have anything to highlight.
+                let ranges = match printer.highlights() && (selected != self.invert) {
+                    true => self.matcher.find(line),
+                    false => Vec::new(),
+                };
+                if selected {
+                    let score = if self.invert { None } else { self.matcher.score(line) };
+                    printer.matched(number, offset, line, &ranges, score)?;
+                } else {
+                    printer.unmatched(number, offset, line, &ranges)?;
+                }
+            }
+
+            offset += read;
+        }
+
+        *position = Position { line: number, offset };
+        printer.finish(count)?;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    /// A reader that panics if it's read past `limit` bytes.
+    struct Limited<'a> {
+        data: &'a [u8],
+        limit: usize,
+    }
+
+    impl Read for Limited<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            assert!(self.limit > 0, "read past the limit");
+            let n = buf.len().min(self.data.len()).min(self.limit);
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            self.limit -= n;
+            Ok(n)
+        }
+    }
+
+    fn config(args: &[&str]) -> Config {
+        Config::new(["minigrep"].iter().chain(args).map(|s| s.to_string())).unwrap()
+    }
+
+    #[test]
+    fn stops_at_max_count() {
+        let config = config(&["-m", "2", "-A", "1", "x"]);
+        let matcher = Matcher::new(&config).unwrap();
+        let mut printer = Printer::new(Vec::new(), &config, false);
+
+        let data = "x1\nx2\nafter\nx3\n".to_string() + &"filler\n".repeat(10_000);
+        let reader = io::BufReader::with_capacity(16, Limited { data: data.as_bytes(), limit: 64 });
+
+        let count = Searcher::new(&matcher, &config).search(reader, Path::new("-"), &mut printer).unwrap();
+        assert_eq!(2, count);
+        assert_eq!("x1\nx2\nafter\n", String::from_utf8(printer.into_inner()).unwrap());
+    }
+
+    #[test]
+    fn inverts_and_counts() {
+        let config = config(&["-v", "-c", "x"]);
+        let matcher = Matcher::new(&config).unwrap();
+        let mut printer = Printer::new(Vec::new(), &config, false);
+
+        let count = Searcher::new(&matcher, &config).search(&b"x\ny\nz\n"[..], Path::new("-"), &mut printer).unwrap();
+        assert_eq!(2, count);
+        assert!(printer.into_inner().is_empty());
+    }
+
+    fn search(args: &[&str], input: &[u8]) -> (usize, String) {
+        search_with_capacity(args, input, 4096)
+    }
+
+    fn search_with_capacity(args: &[&str], input: &[u8], capacity: usize) -> (usize, String) {
+        let config = config(args);
+        let matcher = Matcher::new(&config).unwrap();
+        let mut printer = Printer::new(Vec::new(), &config, false);
+        let reader = io::BufReader::with_capacity(capacity, input);
+        let count = Searcher::new(&matcher, &config).search(reader, Path::new("data.bin"), &mut printer).unwrap();
+        (count, String::from_utf8(printer.into_inner()).unwrap())
+    }
+
+    #[test]
+    fn decodes_invalid_utf8_lossily() {
+        assert_eq!((1, "caf\u{fffd} x\n".to_string()), search(&["x"], b"ok\ncaf\xe9 x\n"));
+    }
+
+    #[test]
+    fn reports_binary_matches() {
+        assert_eq!((1, "Binary file data.bin matches\n".to_string()), search(&["x"], b"x\0\nx\n"));
+        assert_eq!((0, String::new()), search(&["y"], b"x\0\nx\n"));
+        // A NUL past the first buffer only stops lines from then on.
+        let late = "x\n".repeat(5000) + "\0x\n";
+        let (count, output) = search(&["-c", "x"], late.as_bytes());
+        assert_eq!((5001, ""), (count, output.as_str()));
+        assert!(search(&["x"], late.as_bytes()).1.ends_with("x\nx\nBinary file data.bin matches\n"));
+        assert_eq!((2, "x\0\nx\n".to_string()), search(&["-a", "x"], b"x\0\nx\n"));
+    }
+
+    #[test]
+    fn decompresses_gzip() {
+        // "hello hello hello\n", compressed with the fixed codes.
+        let gzip = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xcb\x48\xcd\xc9\xc9\x57\xc8\x40\x90\x5c\x00\x3b\x7c\x8a\xdf\x12\x00\x00\x00";
+        assert_eq!((1, "hello hello hello\n".to_string()), search(&["-z", "hello"], gzip));
+        assert_eq!((0, String::new()), search(&["hello"], gzip));
+        assert_eq!((1, "hello hello hello\n".to_string()), search_with_capacity(&["-z", "hello"], gzip, 1));
+        // Without the magic bytes, input is searched as it is.
+        assert_eq!((1, "plain\n".to_string()), search(&["-z", "plain"], b"plain\n"));
+    }
+
+    #[test]
+    fn transcodes_utf16() {
+        let le: Vec<u8> = [0xff, 0xfe].into_iter().chain("żółw\nkot\n".encode_utf16().flat_map(u16::to_le_bytes)).collect();
+        assert_eq!((1, "żółw\n".to_string()), search(&["-i", "ŻÓŁW"], &le));
+        let be: Vec<u8> = [0xfe, 0xff].into_iter().chain("żółw\nkot\n".encode_utf16().flat_map(u16::to_be_bytes)).collect();
+        assert_eq!((1, "kot\n".to_string()), search(&["kot"], &be));
+        // The byte order mark is found even when it takes two reads.
+        assert_eq!((1, "kot\n".to_string()), search_with_capacity(&["kot"], &be, 1));
+    }
+}
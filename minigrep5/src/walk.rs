@@ -0,0 +1,316 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::glob::{Glob, GlobError};
+use crate::index;
+
+/// Finds the files to search under the paths given on the command line.
+///
+/// Directories are walked recursively in name order. Files have to match one
+/// of the `include` globs, if there are any, and none of the `exclude` globs,
+/// which also prune whole directories. Globs without a `/` are matched
+/// against the file name, the others against the whole path.
+///
+/// With `gitignore` set, `.git` directories are skipped and every
+/// `.gitignore` met on the way down is obeyed the way git does.
+///
+/// Index files left by `minigrep index` are never included.
+pub struct Walker {
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+    gitignore: bool,
+}
+
+/// The rules of one `.gitignore` file.
+struct Ignore {
+    base: PathBuf,
+    rules: Vec<Rule>,
+}
+
+struct Rule {
+    glob: Glob,
+    negated: bool,
+    dir_only: bool,
+    /// Rules containing a `/` are relative to the `.gitignore`; the others
+    /// match a name at any depth.
+    anchored: bool,
+}
+
+impl Walker {
+    pub fn new(include: &[String], exclude: &[String], gitignore: bool) -> Result<Walker, GlobError> {
+        let globs = |patterns: &[String]| patterns.iter().map(|p| Glob::new(p)).collect::<Result<Vec<_>, _>>();
+        Ok(Walker { include: globs(include)?, exclude: globs(exclude)?, gitignore })
+    }
+
+    /// Every file to search, in order, or the error reading a path failed with.
+    pub fn files(&self, paths: &[String]) -> Vec<io::Result<PathBuf>> {
+        let mut files = Vec::new();
+
+        for path in paths {
+            let path = PathBuf::from(path);
+            match fs::metadata(&path) {
+                Ok(metadata) if metadata.is_dir() => self.walk(&path, &mut Vec::new(), &mut Vec::new(), &mut files),
+                Ok(_) => {
+                    if self.wanted(&path) {
+                        files.push(Ok(path));
+                    }
+                }
+                Err(e) => files.push(Err(with_path(&path, e))),
+            }
+        }
+
+        files
+    }
+
+    /// The directories the walk of `dir` goes into, `dir` first, and the
+    /// files it finds, as `files` would.
+    pub fn tree(&self, dir: &Path) -> (Vec<PathBuf>, Vec<io::Result<PathBuf>>) {
+        let (mut dirs, mut files) = (Vec::new(), Vec::new());
+        self.walk(dir, &mut Vec::new(), &mut dirs, &mut files);
+        (dirs, files)
+    }
+
+    /// Whether `path`, which turned up under `root` after it was walked,
+    /// would have been found by the walk, had it been there already.
+    pub fn admits(&self, root: &Path, path: &Path, is_dir: bool) -> bool {
+        let name = path.file_name().unwrap_or_default();
+        if name == index::FILE_NAME {
+            return false;
+        }
+        if self.gitignore {
+            // The .gitignore files of every directory down to the path's.
+            let mut ignores = Vec::new();
+            let mut parents: Vec<&Path> = path.ancestors().skip(1).take_while(|dir| dir.starts_with(root)).collect();
+            parents.reverse();
+            for dir in parents {
+                if let Ok(Some(ignore)) = Ignore::load(dir) {
+                    ignores.push(ignore);
+                }
+            }
+            if name == ".git" || is_ignored(&ignores, path, is_dir) {
+                return false;
+            }
+        }
+        if is_dir {
+            !self.excluded(path)
+        } else {
+            self.wanted(path)
+        }
+    }
+
+    fn walk(&self, dir: &Path, ignores: &mut Vec<Ignore>, dirs: &mut Vec<PathBuf>, files: &mut Vec<io::Result<PathBuf>>) {
+        let entries = match fs::read_dir(dir).and_then(|entries| entries.collect::<io::Result<Vec<_>>>()) {
+            Ok(mut entries) => {
+                entries.sort_by_key(|entry| entry.file_name());
+                entries
+            }
+            Err(e) => return files.push(Err(with_path(dir, e))),
+        };
+        dirs.push(dir.to_path_buf());
+
+        let pushed = self.gitignore && match Ignore::load(dir) {
+            Ok(Some(ignore)) => {
+                ignores.push(ignore);
+                true
+            }
+            Ok(None) => false,
+            Err(e) => {
+                files.push(Err(e));
+                false
+            }
+        };
+
+        for entry in entries {
+            // The index `minigrep index` leaves behind isn't worth searching.
+            if entry.file_name() == index::FILE_NAME {
+                continue;
+            }
+            let path = entry.path();
+            // Symlinked files are searched, but symlinked directories aren't
+            // followed, which could loop forever.
+            let is_dir = match entry.file_type() {
+                Ok(file_type) if file_type.is_symlink() => {
+                    if fs::metadata(&path).is_ok_and(|m| m.is_dir()) {
+                        continue;
+                    }
+                    false
+                }
+                Ok(file_type) => file_type.is_dir(),
+                Err(e) => {
+                    files.push(Err(with_path(&path, e)));
+                    continue;
+                }
+            };
+
+            if self.gitignore && (entry.file_name() == ".git" || is_ignored(ignores, &path, is_dir)) {
+                continue;
+            }
+            if is_dir {
+                if !self.excluded(&path) {
+                    self.walk(&path, ignores, dirs, files);
+                }
+            } else if self.wanted(&path) {
+                files.push(Ok(path));
+            }
+        }
+
+        if pushed {
+            ignores.pop();
+        }
+    }
+
+    fn wanted(&self, path: &Path) -> bool {
+        let included = self.include.is_empty() || self.include.iter().any(|glob| glob_matches(glob, path));
+        included && !self.excluded(path)
+    }
+
+    fn excluded(&self, path: &Path) -> bool {
+        self.exclude.iter().any(|glob| glob_matches(glob, path))
+    }
+}
+
+impl Ignore {
+    fn load(dir: &Path) -> io::Result<Option<Ignore>> {
+        let path = dir.join(".gitignore");
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(with_path(&path, e)),
+        };
+
+        let rules = contents.lines().filter_map(Rule::parse).collect();
+        Ok(Some(Ignore { base: dir.to_path_buf(), rules }))
+    }
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Rule> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+
+        // Lines git itself would reject are ignored rather than fatal.
+        let glob = Glob::new(line.strip_prefix('/').unwrap_or(line)).ok()?;
+        Some(Rule { glob, negated, dir_only, anchored })
+    }
+}
+
+/// Whether the last rule matching `path` ignores it.
+fn is_ignored(ignores: &[Ignore], path: &Path, is_dir: bool) -> bool {
+    let mut ignored = false;
+
+    for ignore in ignores {
+        let relative = match path.strip_prefix(&ignore.base) {
+            Ok(relative) => relative.to_string_lossy(),
+            Err(_) => continue,
+        };
+        let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+
+        for rule in &ignore.rules {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let subject = if rule.anchored { &relative } else { &name };
+            if rule.glob.matches(subject) {
+                ignored = !rule.negated;
+            }
+        }
+    }
+
+    ignored
+}
+
+fn glob_matches(glob: &Glob, path: &Path) -> bool {
+    let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    let path = path.to_string_lossy();
+    glob.matches(&name) || glob.matches(path.trim_start_matches("./"))
+}
+
+fn with_path(path: &Path, e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn tree(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = env::temp_dir().join(format!("minigrep-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (path, contents) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        root
+    }
+
+    fn found(walker: &Walker, root: &Path) -> Vec<String> {
+        walker
+            .files(&[root.to_string_lossy().into_owned()])
+            .into_iter()
+            .map(|file| file.unwrap().strip_prefix(root).unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn walks_with_include_and_exclude() {
+        let root = tree(
+            "walk",
+            &[("b.txt", ""), ("a.rs", ""), ("src/lib.rs", ""), ("src/notes.txt", ""), ("target/out.rs", "")],
+        );
+
+        let walker = Walker::new(&[], &[], false).unwrap();
+        assert_eq!(vec!["a.rs", "b.txt", "src/lib.rs", "src/notes.txt", "target/out.rs"], found(&walker, &root));
+
+        let walker = Walker::new(&["*.rs".to_string()], &["target".to_string()], false).unwrap();
+        assert_eq!(vec!["a.rs", "src/lib.rs"], found(&walker, &root));
+
+        let missing = Walker::new(&[], &[], false).unwrap().files(&["no/such/file".to_string()]);
+        assert!(missing[0].is_err());
+    }
+
+    #[test]
+    fn obeys_gitignore() {
+        let root = tree(
+            "gitignore",
+            &[
+                (".gitignore", "# build output\ntarget/\n*.log\n!keep.log\n/top.txt\n"),
+                (".git/HEAD", ""),
+                ("top.txt", ""),
+                ("debug.log", ""),
+                ("keep.log", ""),
+                ("target/out.rs", ""),
+                ("src/top.txt", ""),
+                ("src/.gitignore", "generated.rs\n"),
+                ("src/generated.rs", ""),
+                ("src/lib.rs", ""),
+            ],
+        );
+
+        let walker = Walker::new(&[], &[], true).unwrap();
+        assert_eq!(vec![".gitignore", "keep.log", "src/.gitignore", "src/lib.rs", "src/top.txt"], found(&walker, &root));
+        let (dirs, _) = walker.tree(&root);
+        assert_eq!(vec![root.clone(), root.join("src")], dirs);
+
+        // Files that turn up later are held to the same rules.
+        assert!(walker.admits(&root, &root.join("src/new.rs"), false));
+        assert!(!walker.admits(&root, &root.join("new.log"), false));
+        assert!(!walker.admits(&root, &root.join("src/generated.rs"), false));
+        assert!(!walker.admits(&root, &root.join("src/target"), true));
+        assert!(walker.admits(&root, &root.join("src/target"), false));
+    }
+}